@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use usn_reader::raw::file::UsnJournalFile;
+use usn_reader::reader::Reader;
+
+// an extracted `$J` stream.
+fuzz_target!(|data: &[u8]| {
+    let Ok(file) = UsnJournalFile::new(Cursor::new(data)) else {
+        return;
+    };
+    let mut reader = Reader::new(&file);
+    reader.set_recover_slack(true);
+    if let Ok(records) = reader.read() {
+        for _ in records.into_iter().take(10_000) {}
+    }
+    let _ = reader.seek_to_time(132989000930000000);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usn_reader::raw::ntfs::{decode_runs, MftRecord};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_runs(data, 0);
+    let Ok(record) = MftRecord::parse(0, data.to_vec()) else {
+        return;
+    };
+    let _ = record.in_use();
+    let _ = record.base_reference();
+    let _ = record.attributes();
+    let _ = record.standard_information();
+    let _ = record.file_name_times();
+    let _ = record.file_names();
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io::{Cursor, Read};
+use usn_reader::raw::ntfs::NtfsVolume;
+
+// a disk image, with or without a partition table.
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut volume) = NtfsVolume::open(Cursor::new(data)) else {
+        return;
+    };
+    for entry in 0..volume.entry_count().min(64) {
+        let _ = volume.read_mft_record(entry);
+    }
+    if let Ok(mut stream) = volume.open_usn_journal() {
+        let mut buf = vec![0u8; 1 << 16];
+        let _ = stream.read(&mut buf);
+    }
+});
@@ -0,0 +1,31 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usn_reader::raw::ioctl::USN_RECORD_V2;
+use usn_reader::raw::parser::{stale_records, Parser};
+use usn_reader::raw::usn_journal_wrapper::RawRecords;
+use usn_reader::usn_record::Record;
+
+const N: usize = 65536;
+
+// an FSCTL_READ_USN_JOURNAL output buffer, with the returned length in the first two bytes.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let body = &data[2..data.len().min(N + 2)];
+    let mut raw_ptr = Box::new([0u8; N]);
+    raw_ptr[..body.len()].copy_from_slice(body);
+    let raw = RawRecords {
+        raw_ptr,
+        len: u16::from_le_bytes([data[0], data[1]]) as u32,
+    };
+
+    for bytes in raw.split().iter() {
+        let _ = Record::from(*bytes);
+    }
+    let _ = raw.parse::<USN_RECORD_V2>();
+    for bytes in stale_records(body, 0) {
+        let _ = Record::from(bytes);
+    }
+});
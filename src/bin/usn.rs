@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use usn_reader::export::csv::CsvWriter;
+use usn_reader::export::jsonl::JsonLinesWriter;
+use usn_reader::export::timeline::{BodyFileWriter, TlnWriter};
+use usn_reader::filter::Filter;
+use usn_reader::raw::file::UsnJournalFile;
+use usn_reader::raw::ntfs::NtfsVolume;
+use usn_reader::raw::usn_journal_wrapper::UsnJournalWrapper;
+#[cfg(windows)]
+use usn_reader::raw::volume_handle::VolumeHandle;
+#[cfg(windows)]
+use usn_reader::raw::windows::WindowsUsnJournal;
+use usn_reader::reader::Reader;
+use usn_reader::stats::Stats;
+use usn_reader::timestamp::UtcOffset;
+use usn_reader::usn_journal_data::UsnJournalDataFactory;
+use usn_reader::volume::VolumePath;
+
+#[derive(Parser)]
+#[command(name = "usn", about = "Read the NTFS change journal.")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Input {
+    /// A volume such as `C`, `C:\mnt\vhd` or `\\?\Volume{GUID}`, a `$J` file, or a disk image
+    /// with `--image`.
+    input: String,
+    /// Read `$Extend\$UsnJrnl:$J` out of an NTFS volume or disk image.
+    #[arg(long)]
+    image: bool,
+    /// Also recover stale records from page slack, of a `$J` file or an image.
+    #[arg(long)]
+    recover_slack: bool,
+    /// Usn to start reading from.
+    #[arg(long)]
+    start_usn: Option<i64>,
+    /// Only keep matching records, e.g. `reason:FILE_DELETE AND name:*.docx`.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Show timestamps at a fixed offset such as `+09:00` instead of UTC.
+    #[arg(long, default_value = "Z", allow_hyphen_values = true)]
+    utc_offset: UtcOffset,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the journal data.
+    Info(Input),
+    /// Print records.
+    Dump(Input),
+    /// Summarize records per reason, hour, file, directory and extension.
+    Stats {
+        #[command(flatten)]
+        input: Input,
+        /// How many files, directories and extensions to list.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Write records to a file.
+    Export {
+        #[command(flatten)]
+        input: Input,
+        #[arg(short, long, value_enum)]
+        format: Format,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Host name for the TLN system field.
+        #[arg(long, default_value = "")]
+        system: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Jsonl,
+    Body,
+    Tln,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let input = match &cli.command {
+        Command::Info(input) | Command::Dump(input) => input,
+        Command::Stats { input, .. } | Command::Export { input, .. } => input,
+    };
+
+    // a mount point is a folder, anything else that parses is a volume name.
+    let volume = VolumePath::parse(&input.input)
+        .ok()
+        .filter(|v| !matches!(v, VolumePath::MountPoint(_)) || Path::new(&input.input).is_dir());
+    if let (false, Some(volume)) = (input.image, &volume) {
+        live(volume, &cli.command, input)
+    } else if input.image {
+        let volume = NtfsVolume::open(File::open(&input.input)?)?;
+        run(
+            &UsnJournalFile::new(volume.open_usn_journal()?)?,
+            &cli.command,
+            input,
+        )
+    } else {
+        run(&UsnJournalFile::open(&input.input)?, &cli.command, input)
+    }
+}
+
+#[cfg(windows)]
+fn live(volume: &VolumePath, command: &Command, input: &Input) -> Result<()> {
+    if input.recover_slack {
+        return Err(anyhow!(
+            "--recover-slack needs a $J file or an image, not volume {}.",
+            volume
+        ));
+    }
+    let handle = VolumeHandle::open(volume)?;
+    if let Command::Info(_) = command {
+        let info = handle.information()?;
+        println!("volume                          {}", info.guid_path);
+        println!(
+            "serial_number                   {:#010x}",
+            info.serial_number
+        );
+        println!("file_system                     {}", info.file_system);
+        println!("label                           {}", info.label);
+    }
+    run(&WindowsUsnJournal::new(&handle), command, input)
+}
+
+#[cfg(not(windows))]
+fn live(volume: &VolumePath, _: &Command, _: &Input) -> Result<()> {
+    Err(anyhow!(
+        "reading volume {}: needs windows, pass a $J file instead.",
+        volume
+    ))
+}
+
+fn run<U: UsnJournalWrapper>(journal: &U, command: &Command, input: &Input) -> Result<()> {
+    let data = UsnJournalDataFactory::new(journal).query()?;
+    let mut reader = Reader::new(journal);
+    reader.set_recover_slack(input.recover_slack);
+    reader.set_start_usn(input.start_usn.unwrap_or(data.data.first_usn));
+    if let Some(filter) = &input.filter {
+        reader.set_filter(filter.clone());
+    }
+
+    match command {
+        Command::Info(_) => {
+            let d = data.data;
+            let optional = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+            println!("version                         {:?}", data.ver);
+            println!("usn_journal_id                  {:#x}", d.usn_journal_id);
+            println!("first_usn                       {}", d.first_usn);
+            println!("next_usn                        {}", d.next_usn);
+            println!("lowest_valid_usn                {}", d.lowest_valid_usn);
+            println!("max_usn                         {}", d.max_usn);
+            println!("maximum_size                    {}", d.maximum_size);
+            println!("allocation_delta                {}", d.allocation_delta);
+            println!(
+                "min_supported_major_version     {}",
+                optional(d.min_supported_major_version.map(|v| v.to_string()))
+            );
+            println!(
+                "max_supported_major_version     {}",
+                optional(d.max_supported_major_version.map(|v| v.to_string()))
+            );
+            println!(
+                "flags                           {}",
+                optional(d.flags.map(|v| format!("{:#x}", v)))
+            );
+            println!(
+                "range_track_chunk_size          {}",
+                optional(d.range_track_chunk_size.map(|v| v.to_string()))
+            );
+            println!(
+                "range_track_file_size_threshold {}",
+                optional(d.range_track_file_size_threshold.map(|v| v.to_string()))
+            );
+        }
+        Command::Dump(_) => {
+            let mut stdout = std::io::stdout().lock();
+            for record in reader.read()? {
+                writeln!(stdout, "{}", record.display_with_offset(input.utc_offset))?;
+            }
+        }
+        Command::Stats { top, .. } => {
+            let stats: Stats = reader.read()?.into_iter().collect();
+            let coverage = stats.coverage(&data.data);
+            let hours = |ticks: i64| format!("{:.1}h", ticks as f64 / 36_000_000_000.0);
+            println!("records      {}", stats.records);
+            println!(
+                "creates      {} deletes {} ratio {}",
+                stats.creates,
+                stats.deletes,
+                stats
+                    .create_delete_ratio()
+                    .map_or("-".to_string(), |r| format!("{:.2}", r))
+            );
+            println!(
+                "span         {} over {} of {} bytes, {} when full",
+                hours(coverage.time_span),
+                coverage.used_bytes,
+                coverage.maximum_size,
+                coverage.projected_span.map_or("-".to_string(), hours)
+            );
+            println!("\nreasons");
+            for (reason, count) in &stats.by_reason {
+                println!("{:>12} {}", count, reason);
+            }
+            println!("\nhours");
+            for (hour, count) in &stats.by_hour {
+                println!("{:>12} {}", count, &input.utc_offset.format(*hour)[..13]);
+            }
+            for (title, top) in [
+                ("files", stats.top_files(*top)),
+                ("directories", stats.top_directories(*top)),
+                ("extensions", stats.top_extensions(*top)),
+            ] {
+                println!("\n{}", title);
+                for (key, count) in top {
+                    println!("{:>12} {}", count, key);
+                }
+            }
+        }
+        Command::Export {
+            format,
+            output,
+            system,
+            ..
+        } => {
+            let records = reader.read()?;
+            // sqlite opens the file itself, and appends to an existing database.
+            let create = || -> Result<BufWriter<File>> {
+                let file = File::create(output)
+                    .map_err(|e| anyhow!("create {} error: {}.", output.display(), e))?;
+                Ok(BufWriter::new(file))
+            };
+            let count = match format {
+                Format::Csv => {
+                    let mut writer = CsvWriter::new(create()?);
+                    writer.set_utc_offset(input.utc_offset);
+                    writer.write_all(records)?
+                }
+                Format::Jsonl => JsonLinesWriter::new(create()?).write_all(records)?,
+                Format::Body => BodyFileWriter::new(create()?).write_all(records)?,
+                Format::Tln => {
+                    let mut writer = TlnWriter::new(create()?);
+                    writer.set_system(system);
+                    writer.write_all(records)?
+                }
+                #[cfg(feature = "sqlite")]
+                Format::Sqlite => {
+                    let mut exporter = usn_reader::export::sqlite::SqliteExporter::open(output)?;
+                    exporter.write_journal_info(&data.data)?;
+                    exporter.write_records(data.data.usn_journal_id, records)?
+                }
+                #[cfg(feature = "arrow")]
+                Format::Parquet => {
+                    let mut exporter =
+                        usn_reader::export::columnar::ParquetExporter::new(create()?, 1 << 20)?;
+                    let count = exporter.write_all(records)?;
+                    exporter.close()?;
+                    count
+                }
+            };
+            eprintln!("{} records written to {}.", count, output.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,323 @@
+use crate::checkpoint::{Checkpoint, Stale};
+use crate::file_reference::FileReference;
+use crate::index::Index;
+use crate::raw::ioctl::{
+    USN_REASON_CLOSE, USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE, USN_REASON_RENAME_NEW_NAME,
+    USN_REASON_RENAME_OLD_NAME,
+};
+use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+use crate::reader::{Reader, RecordFetcher};
+use crate::usn_journal_data::UsnJournalDataFactory;
+use crate::usn_record::Record;
+use anyhow::Result;
+use std::collections::HashMap;
+
+// every reason but these changes the content or the metadata of a file.
+const NOT_MODIFY: u32 = USN_REASON_FILE_CREATE
+    | USN_REASON_FILE_DELETE
+    | USN_REASON_RENAME_OLD_NAME
+    | USN_REASON_RENAME_NEW_NAME
+    | USN_REASON_CLOSE;
+
+// The net change of every file between two checkpoints, with full paths. a file is in at most
+// one of `added`, `deleted` and `renamed`, and in `modified` too when renamed and changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChangeSet {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    // as the paths were at the first checkpoint.
+    pub deleted: Vec<String>,
+    // (old, new).
+    pub renamed: Vec<(String, String)>,
+    // where the change set ends, to pass in next time.
+    pub checkpoint: Checkpoint,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.deleted.is_empty()
+            && self.renamed.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Changes {
+    Changed(ChangeSet),
+    // changes since the checkpoint were lost, compare against a full scan instead.
+    FullRescanRequired(Stale),
+}
+
+// What the records of one file add up to.
+struct FileChange {
+    existed: bool,
+    exists: bool,
+    modified: bool,
+    // (parent, name) at the first checkpoint and now.
+    from: (FileReference, String),
+    to: (FileReference, String),
+}
+
+impl FileChange {
+    fn new(record: &Record) -> Self {
+        let name = (
+            record.parent_file_reference_number,
+            record.file_name.clone(),
+        );
+        Self {
+            existed: !record.reason.contains(USN_REASON_FILE_CREATE),
+            exists: true,
+            modified: false,
+            from: name.clone(),
+            to: name,
+        }
+    }
+
+    fn add(&mut self, record: &Record) {
+        self.exists = !record.reason.contains(USN_REASON_FILE_DELETE);
+        self.modified |= record.reason.0 & !NOT_MODIFY != 0;
+        // the old name record of a rename still has the name from before.
+        if !record.reason.contains(USN_REASON_RENAME_OLD_NAME) {
+            self.to = (
+                record.parent_file_reference_number,
+                record.file_name.clone(),
+            );
+        }
+    }
+}
+
+// Folds the records from the checkpoint of `index` on into a change set, and moves `index` to
+// the end of the journal. the index resolves the paths, both as they were at the checkpoint and
+// as they are now.
+pub fn changes_since<U: UsnJournalWrapper>(index: &mut Index, usn_journal: &U) -> Result<Changes> {
+    let data = UsnJournalDataFactory::new(usn_journal).query()?.data;
+    if let Some(stale) = index.checkpoint().stale(&data) {
+        return Ok(Changes::FullRescanRequired(stale));
+    }
+
+    let mut reader = Reader::new(usn_journal);
+    reader.set_checkpoint(index.checkpoint());
+    let mut records = vec![];
+    loop {
+        let block = match reader.do_fetch() {
+            Ok(block) => block,
+            Err(e) => {
+                return match Stale::from_read_error(usn_journal, &e) {
+                    Some(stale) => Ok(Changes::FullRescanRequired(stale)),
+                    None => Err(e),
+                }
+            }
+        };
+        if block.is_empty() {
+            break;
+        }
+        records.extend(*block);
+    }
+
+    let mut files: HashMap<FileReference, FileChange> = HashMap::new();
+    for record in &records {
+        files
+            .entry(record.file_reference_number)
+            .or_insert_with(|| FileChange::new(record))
+            .add(record);
+    }
+
+    // old paths before the index moves on, as directories may be renamed or deleted too.
+    let old_paths: HashMap<FileReference, String> = files
+        .iter()
+        .filter(|(_, f)| f.existed)
+        .map(|(r, f)| (*r, index.join(f.from.0, &f.from.1)))
+        .collect();
+    for record in &records {
+        index.apply(record);
+    }
+    index.set_checkpoint(reader.checkpoint());
+
+    let mut changes = ChangeSet {
+        checkpoint: index.checkpoint(),
+        ..Default::default()
+    };
+    for (reference, file) in &files {
+        let new_path = || index.join(file.to.0, &file.to.1);
+        match (file.existed, file.exists) {
+            // created and deleted again, nothing to back up.
+            (false, false) => {}
+            (false, true) => changes.added.push(new_path()),
+            (true, false) => changes.deleted.push(old_paths[reference].clone()),
+            (true, true) => {
+                if file.from != file.to {
+                    changes
+                        .renamed
+                        .push((old_paths[reference].clone(), new_path()));
+                }
+                if file.modified {
+                    changes.modified.push(new_path());
+                }
+            }
+        }
+    }
+    changes.added.sort();
+    changes.modified.sort();
+    changes.deleted.sort();
+    changes.renamed.sort();
+    Ok(Changes::Changed(changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::changes::{changes_since, ChangeSet, Changes};
+    use crate::checkpoint::Stale;
+    use crate::index::Index;
+    use crate::raw::ioctl::{ERROR_ACCESS_DENIED, ERROR_JOURNAL_ENTRY_DELETED};
+    use crate::raw::usn_journal_wrapper::error_code;
+    use crate::testing::{record, Call, FakeJournal};
+
+    const ROOT: u64 = 0x0005_0000_0000_0005;
+    const DOCS: u64 = 0x0001_0000_0000_0010;
+
+    // `\docs\a.txt`, `\docs\b.txt` and `\c.txt`, indexed.
+    fn indexed() -> (FakeJournal, Index) {
+        let journal = FakeJournal::new();
+        journal
+            .extend(&[
+                record(DOCS, ROOT, 0x80000100, "docs"),
+                record(0x11, DOCS, 0x80000100, "a.txt"),
+                record(0x12, DOCS, 0x80000100, "b.txt"),
+                record(0x13, ROOT, 0x80000100, "c.txt"),
+            ])
+            .unwrap();
+        let index = Index::from_enum(&journal).unwrap();
+        (journal, index)
+    }
+
+    fn changed(changes: Changes) -> ChangeSet {
+        match changes {
+            Changes::Changed(changes) => changes,
+            Changes::FullRescanRequired(stale) => panic!("{}", stale),
+        }
+    }
+
+    #[test]
+    fn it_should_fold_records_into_net_changes() {
+        let (journal, mut index) = indexed();
+        journal
+            .extend(&[
+                record(0x11, DOCS, 0x2, "a.txt"),
+                record(0x11, DOCS, 0x80000002, "a.txt"),
+                record(0x12, DOCS, 0x80000200, "b.txt"),
+                record(0x14, ROOT, 0x100, "d.txt"),
+                record(0x14, ROOT, 0x80000102, "d.txt"),
+                // created and deleted in between.
+                record(0x15, ROOT, 0x80000100, "tmp"),
+                record(0x15, ROOT, 0x80000200, "tmp"),
+            ])
+            .unwrap();
+        let changes = changed(changes_since(&mut index, &journal).unwrap());
+
+        assert_eq!(changes.added, vec!["\\d.txt"]);
+        assert_eq!(changes.modified, vec!["\\docs\\a.txt"]);
+        assert_eq!(changes.deleted, vec!["\\docs\\b.txt"]);
+        assert!(changes.renamed.is_empty());
+        assert_eq!(changes.checkpoint.next_usn, journal.data().next_usn);
+        assert!(changed(changes_since(&mut index, &journal).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn it_should_fold_rename_chains() {
+        let (journal, mut index) = indexed();
+        journal
+            .extend(&[
+                // a.txt to b2.txt to \e.txt.
+                record(0x11, DOCS, 0x1000, "a.txt"),
+                record(0x11, DOCS, 0x80002000, "b2.txt"),
+                record(0x11, DOCS, 0x1000, "b2.txt"),
+                record(0x11, ROOT, 0x80002000, "e.txt"),
+                // c.txt to x.txt and back, but written to.
+                record(0x13, ROOT, 0x1000, "c.txt"),
+                record(0x13, ROOT, 0x80002000, "x.txt"),
+                record(0x13, ROOT, 0x1000, "x.txt"),
+                record(0x13, ROOT, 0x2000, "c.txt"),
+                record(0x13, ROOT, 0x80000001, "c.txt"),
+            ])
+            .unwrap();
+        let changes = changed(changes_since(&mut index, &journal).unwrap());
+
+        assert_eq!(
+            changes.renamed,
+            vec![("\\docs\\a.txt".to_string(), "\\e.txt".to_string())]
+        );
+        assert_eq!(changes.modified, vec!["\\c.txt"]);
+        assert!(changes.added.is_empty() && changes.deleted.is_empty());
+    }
+
+    #[test]
+    fn it_should_use_old_paths_of_renamed_directories() {
+        let (journal, mut index) = indexed();
+        journal
+            .extend(&[
+                record(DOCS, ROOT, 0x1000, "docs"),
+                record(DOCS, ROOT, 0x80002000, "papers"),
+                record(0x12, DOCS, 0x80000200, "b.txt"),
+                record(0x11, DOCS, 0x80000001, "a.txt"),
+            ])
+            .unwrap();
+        let changes = changed(changes_since(&mut index, &journal).unwrap());
+
+        assert_eq!(
+            changes.renamed,
+            vec![("\\docs".to_string(), "\\papers".to_string())]
+        );
+        assert_eq!(changes.deleted, vec!["\\docs\\b.txt"]);
+        assert_eq!(changes.modified, vec!["\\papers\\a.txt"]);
+    }
+
+    #[test]
+    fn it_should_require_a_full_rescan() {
+        let (journal, mut index) = indexed();
+        journal.append(&record(0x14, ROOT, 0x100, "d.txt")).unwrap();
+        journal.purge(journal.data().next_usn);
+        let lowest_valid_usn = journal.data().lowest_valid_usn;
+
+        assert_eq!(
+            changes_since(&mut index, &journal).unwrap(),
+            Changes::FullRescanRequired(Stale::Purged { lowest_valid_usn })
+        );
+
+        journal.recreate(9);
+        assert_eq!(
+            changes_since(&mut index, &journal).unwrap(),
+            Changes::FullRescanRequired(Stale::Recreated { usn_journal_id: 9 })
+        );
+    }
+
+    #[test]
+    fn it_should_keep_the_checkpoint_when_a_read_fails() {
+        let (journal, mut index) = indexed();
+        journal.append(&record(0x14, ROOT, 0x100, "d.txt")).unwrap();
+        let checkpoint = index.checkpoint();
+        journal.fail_next(Call::Read, ERROR_ACCESS_DENIED);
+        let err = changes_since(&mut index, &journal).unwrap_err();
+
+        assert_eq!(error_code(&err), Some(ERROR_ACCESS_DENIED));
+        assert_eq!(index.checkpoint(), checkpoint);
+        assert_eq!(
+            changed(changes_since(&mut index, &journal).unwrap()).added,
+            vec!["\\d.txt"]
+        );
+    }
+
+    #[test]
+    fn it_should_require_a_full_rescan_when_purged_while_reading() {
+        let (journal, mut index) = indexed();
+        journal.fail_next(Call::Read, ERROR_JOURNAL_ENTRY_DELETED);
+        let lowest_valid_usn = journal.data().lowest_valid_usn;
+
+        assert_eq!(
+            changes_since(&mut index, &journal).unwrap(),
+            Changes::FullRescanRequired(Stale::Purged { lowest_valid_usn })
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::raw::ioctl::ERROR_JOURNAL_ENTRY_DELETED;
+use crate::raw::usn_journal_wrapper::{error_code, UsnJournalWrapper};
+use crate::usn_journal_data::{Data, UsnJournalDataFactory};
+use std::fmt::{Display, Formatter};
+
+// Position in a journal to resume reading from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    pub usn_journal_id: u64,
+    pub next_usn: i64,
+}
+
+// Why a checkpoint can't be resumed from: the records after it are gone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stale {
+    // deleted and created again, or another volume.
+    Recreated { usn_journal_id: u64 },
+    // wrapped, or purged up to `lowest_valid_usn`.
+    Purged { lowest_valid_usn: i64 },
+}
+
+impl Display for Stale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stale::Recreated { usn_journal_id } => {
+                write!(f, "usn journal was recreated as {:#x}", usn_journal_id)
+            }
+            Stale::Purged { lowest_valid_usn } => {
+                write!(f, "usn journal was purged up to {}", lowest_valid_usn)
+            }
+        }
+    }
+}
+
+impl Stale {
+    // a read past the checkpoint that failed as the journal wrapped after it was checked.
+    pub fn from_read_error<U: UsnJournalWrapper>(
+        usn_journal: &U,
+        error: &anyhow::Error,
+    ) -> Option<Self> {
+        if error_code(error) != Some(ERROR_JOURNAL_ENTRY_DELETED) {
+            return None;
+        }
+        let data = UsnJournalDataFactory::new(usn_journal).query().ok()?.data;
+        Some(Stale::Purged {
+            lowest_valid_usn: data.lowest_valid_usn,
+        })
+    }
+}
+
+impl Checkpoint {
+    pub fn stale(&self, data: &Data) -> Option<Stale> {
+        if data.usn_journal_id != self.usn_journal_id {
+            return Some(Stale::Recreated {
+                usn_journal_id: data.usn_journal_id,
+            });
+        }
+        if self.next_usn < data.lowest_valid_usn {
+            return Some(Stale::Purged {
+                lowest_valid_usn: data.lowest_valid_usn,
+            });
+        }
+        None
+    }
+}
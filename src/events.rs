@@ -0,0 +1,234 @@
+use crate::file_reference::FileReference;
+use crate::flags::DATA_CHANGE;
+use crate::raw::ioctl::{
+    USN_REASON_BASIC_INFO_CHANGE, USN_REASON_CLOSE, USN_REASON_EA_CHANGE, USN_REASON_FILE_CREATE,
+    USN_REASON_FILE_DELETE, USN_REASON_RENAME_NEW_NAME, USN_REASON_RENAME_OLD_NAME,
+    USN_REASON_SECURITY_CHANGE, USN_REASON_STREAM_CHANGE,
+};
+use crate::usn_record::Record;
+use std::collections::{HashMap, VecDeque};
+
+const MODIFY: u32 = DATA_CHANGE | USN_REASON_BASIC_INFO_CHANGE | USN_REASON_EA_CHANGE;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+    Created,
+    Modified,
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Moved {
+        from_parent: FileReference,
+        to_parent: FileReference,
+    },
+    Deleted,
+    SecurityChanged,
+    StreamChanged,
+}
+
+// One user level operation on a file, made of the records up to its CLOSE.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event {
+    pub kind: EventKind,
+    pub file_reference_number: FileReference,
+    // name and parent as of the last record.
+    pub parent_file_reference_number: FileReference,
+    pub file_name: String,
+    pub path: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::filetime::rfc3339"))]
+    pub first_timestamp: i64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::filetime::rfc3339"))]
+    pub last_timestamp: i64,
+    pub usns: Vec<i64>,
+}
+
+fn to_events(records: &[Record]) -> Vec<Event> {
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return vec![];
+    };
+    let reason = records.iter().fold(0, |reason, r| reason | r.reason.0);
+    let has = |flag: u32| reason & flag != 0;
+    let old = records
+        .iter()
+        .find(|r| r.reason.contains(USN_REASON_RENAME_OLD_NAME));
+    let new = records
+        .iter()
+        .rev()
+        .find(|r| r.reason.contains(USN_REASON_RENAME_NEW_NAME));
+
+    let mut kinds = vec![];
+    if has(USN_REASON_FILE_CREATE) {
+        kinds.push(EventKind::Created);
+    }
+    if let (Some(old), Some(new)) = (old, new) {
+        if old.file_name != new.file_name {
+            kinds.push(EventKind::Renamed {
+                from: old.file_name.clone(),
+                to: new.file_name.clone(),
+            });
+        }
+        if old.parent_file_reference_number != new.parent_file_reference_number {
+            kinds.push(EventKind::Moved {
+                from_parent: old.parent_file_reference_number,
+                to_parent: new.parent_file_reference_number,
+            });
+        }
+    }
+    // writes right after a create are part of creating the file.
+    if has(MODIFY) && !has(USN_REASON_FILE_CREATE) {
+        kinds.push(EventKind::Modified);
+    }
+    if has(USN_REASON_SECURITY_CHANGE) {
+        kinds.push(EventKind::SecurityChanged);
+    }
+    if has(USN_REASON_STREAM_CHANGE) {
+        kinds.push(EventKind::StreamChanged);
+    }
+    if has(USN_REASON_FILE_DELETE) {
+        kinds.push(EventKind::Deleted);
+    }
+
+    kinds
+        .into_iter()
+        .map(|kind| Event {
+            kind,
+            file_reference_number: last.file_reference_number,
+            parent_file_reference_number: last.parent_file_reference_number,
+            file_name: last.file_name.clone(),
+            path: last.path.clone(),
+            first_timestamp: first.timestamp,
+            last_timestamp: last.timestamp,
+            usns: records.iter().map(|r| r.usn).collect(),
+        })
+        .collect()
+}
+
+// Groups records per file up to CLOSE. files still open when the records run out are
+// flushed at the end, in the order they were first seen.
+pub struct EventAggregator<I: Iterator<Item = Record>> {
+    records: I,
+    open: HashMap<FileReference, Vec<Record>>,
+    ready: VecDeque<Event>,
+}
+
+impl<I: Iterator<Item = Record>> EventAggregator<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(records: T) -> Self {
+        Self {
+            records: records.into_iter(),
+            open: HashMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Record>> Iterator for EventAggregator<I> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+
+            match self.records.next() {
+                Some(record) => {
+                    let key = record.file_reference_number;
+                    let close = record.reason.contains(USN_REASON_CLOSE);
+                    self.open.entry(key).or_default().push(record);
+                    if close {
+                        let group = self.open.remove(&key).unwrap_or_default();
+                        self.ready.extend(to_events(&group));
+                    }
+                }
+                None if self.open.is_empty() => return None,
+                None => {
+                    let mut groups: Vec<Vec<Record>> = self.open.drain().map(|(_, g)| g).collect();
+                    groups.sort_by_key(|g| g[0].usn);
+                    for group in groups {
+                        self.ready.extend(to_events(&group));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{EventAggregator, EventKind};
+    use crate::testing::{numbered, record};
+    use crate::usn_record::Record;
+
+    fn kinds(records: Vec<Record>) -> Vec<EventKind> {
+        EventAggregator::new(records).map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn it_should_coalesce_a_save() {
+        let records = numbered(vec![
+            record(10, 5, 0x2, "a.txt"),
+            record(10, 5, 0x3, "a.txt"),
+            record(10, 5, 0x8003, "a.txt"),
+            record(10, 5, 0x80008003, "a.txt"),
+        ]);
+        let events: Vec<_> = EventAggregator::new(records).collect();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Modified);
+        assert_eq!(events[0].usns, vec![1, 2, 3, 4]);
+        assert_eq!(events[0].first_timestamp, 132989000930000001);
+        assert_eq!(events[0].last_timestamp, 132989000930000004);
+    }
+
+    #[test]
+    fn it_should_detect_rename_and_move() {
+        let renamed = numbered(vec![
+            record(10, 5, 0x1000, "a.txt"),
+            record(10, 5, 0x2000, "b.txt"),
+            record(10, 5, 0x80002000, "b.txt"),
+        ]);
+        let moved = numbered(vec![
+            record(10, 5, 0x1000, "a.txt"),
+            record(10, 6, 0x80002000, "a.txt"),
+        ]);
+
+        assert_eq!(
+            kinds(renamed),
+            vec![EventKind::Renamed {
+                from: "a.txt".to_string(),
+                to: "b.txt".to_string()
+            }]
+        );
+        assert_eq!(
+            kinds(moved),
+            vec![EventKind::Moved {
+                from_parent: 5.into(),
+                to_parent: 6.into()
+            }]
+        );
+    }
+
+    #[test]
+    fn it_should_group_per_file() {
+        let records = numbered(vec![
+            record(10, 5, 0x100, "a.txt"),
+            record(11, 5, 0x800, "b.txt"),
+            record(10, 5, 0x80000102, "a.txt"),
+            record(11, 5, 0x80000a00, "b.txt"),
+            record(12, 5, 0x200000, "c.txt"),
+        ]);
+
+        assert_eq!(
+            kinds(records),
+            vec![
+                EventKind::Created,
+                EventKind::SecurityChanged,
+                EventKind::Deleted,
+                EventKind::StreamChanged,
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use crate::usn_record::Record;
+use crate::util::filetime::UNIX_EPOCH_AS_FILETIME;
+use ::arrow::array::{
+    ArrayRef, BooleanArray, Int64Array, ListBuilder, StringArray, StringBuilder,
+    TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array,
+};
+use ::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use anyhow::Result;
+use std::io::Write;
+use std::sync::Arc;
+
+pub fn schema() -> SchemaRef {
+    let names = || DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("usn", DataType::Int64, false),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("major_version", DataType::UInt16, false),
+        Field::new("file_reference", DataType::UInt64, false),
+        Field::new("file_entry", DataType::UInt64, false),
+        Field::new("file_sequence", DataType::UInt16, false),
+        Field::new("parent_reference", DataType::UInt64, false),
+        Field::new("parent_entry", DataType::UInt64, false),
+        Field::new("parent_sequence", DataType::UInt16, false),
+        Field::new("reason", DataType::UInt32, false),
+        Field::new("reason_names", names(), false),
+        Field::new("file_attributes", DataType::UInt32, false),
+        Field::new("attribute_names", names(), false),
+        Field::new("source_info", DataType::UInt32, false),
+        Field::new("security_id", DataType::UInt32, false),
+        Field::new("file_name", DataType::Utf8, false),
+        Field::new("path", DataType::Utf8, true),
+        Field::new("recovered", DataType::Boolean, false),
+    ]))
+}
+
+// timestamp[ns] only covers 1677..2262, anything outside is null.
+fn to_unix_nanos(filetime: i64) -> Option<i64> {
+    filetime
+        .checked_sub(UNIX_EPOCH_AS_FILETIME)?
+        .checked_mul(100)
+}
+
+fn names<'a>(records: &'a [Record], f: impl Fn(&'a Record) -> Vec<&'static str>) -> ArrayRef {
+    let mut builder = ListBuilder::new(StringBuilder::new());
+    for record in records {
+        for name in f(record) {
+            builder.values().append_value(name);
+        }
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+pub fn to_record_batch(records: &[Record]) -> Result<RecordBatch> {
+    let column = |f: fn(&Record) -> u64| UInt64Array::from_iter_values(records.iter().map(f));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(records.iter().map(|r| r.usn))),
+        Arc::new(
+            TimestampNanosecondArray::from_iter(records.iter().map(|r| to_unix_nanos(r.timestamp)))
+                .with_timezone("UTC"),
+        ),
+        Arc::new(UInt16Array::from_iter_values(
+            records.iter().map(|r| r.major_version),
+        )),
+        Arc::new(column(|r| r.file_reference_number.0)),
+        Arc::new(column(|r| r.file_reference_number.entry())),
+        Arc::new(UInt16Array::from_iter_values(
+            records.iter().map(|r| r.file_reference_number.sequence()),
+        )),
+        Arc::new(column(|r| r.parent_file_reference_number.0)),
+        Arc::new(column(|r| r.parent_file_reference_number.entry())),
+        Arc::new(UInt16Array::from_iter_values(
+            records
+                .iter()
+                .map(|r| r.parent_file_reference_number.sequence()),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            records.iter().map(|r| r.reason.0),
+        )),
+        names(records, |r| r.reason.names()),
+        Arc::new(UInt32Array::from_iter_values(
+            records.iter().map(|r| r.file_attributes.0),
+        )),
+        names(records, |r| r.file_attributes.names()),
+        Arc::new(UInt32Array::from_iter_values(
+            records.iter().map(|r| r.source_info),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            records.iter().map(|r| r.security_id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            records.iter().map(|r| r.file_name.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            records.iter().map(|r| r.path.as_deref()),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            records.iter().map(|r| Some(r.recovered)),
+        )),
+    ];
+
+    Ok(RecordBatch::try_new(schema(), columns)?)
+}
+
+pub struct ParquetExporter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    row_group_size: usize,
+}
+
+impl<W: Write + Send> ParquetExporter<W> {
+    pub fn new(writer: W, row_group_size: usize) -> Result<Self> {
+        let row_group_size = row_group_size.max(1);
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(row_group_size)
+            .set_compression(Compression::SNAPPY)
+            .build();
+        Ok(Self {
+            writer: ArrowWriter::try_new(writer, schema(), Some(properties))?,
+            row_group_size,
+        })
+    }
+
+    pub fn write(&mut self, records: &[Record]) -> Result<()> {
+        self.writer.write(&to_record_batch(records)?)?;
+        Ok(())
+    }
+
+    // only one row group worth of records is held in memory at a time.
+    pub fn write_all<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Result<u64> {
+        let mut count = 0;
+        let mut batch = Vec::with_capacity(self.row_group_size);
+        for record in records {
+            batch.push(record);
+            if batch.len() == self.row_group_size {
+                self.write(&batch)?;
+                count += batch.len() as u64;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            self.write(&batch)?;
+            count += batch.len() as u64;
+        }
+        Ok(count)
+    }
+
+    pub fn close(self) -> Result<()> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::columnar::{to_record_batch, ParquetExporter};
+    use crate::flags::Reason;
+    use crate::usn_record::Record;
+    use ::arrow::array::{Array, ListArray, StringArray, TimestampNanosecondArray};
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+    use bytes::Bytes;
+
+    fn records(count: i64) -> Vec<Record> {
+        (0..count)
+            .map(|usn| Record {
+                usn,
+                timestamp: 132989000930000001,
+                reason: Reason(0x80000100),
+                file_name: format!("{}.txt", usn),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_should_convert_records_to_batch() {
+        let batch = to_record_batch(&records(2)).unwrap();
+        let timestamps = batch
+            .column_by_name("timestamp")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .unwrap();
+        let reasons = batch
+            .column_by_name("reason_names")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap()
+            .value(0);
+        let reasons = reasons.as_any().downcast_ref::<StringArray>().unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(timestamps.value(0), 1654426493000000100);
+        assert_eq!(reasons.value(0), "FILE_CREATE");
+        assert_eq!(reasons.value(1), "CLOSE");
+        assert!(batch.column_by_name("path").unwrap().is_null(0));
+    }
+
+    #[test]
+    fn it_should_write_null_for_timestamps_out_of_range() {
+        let mut records = records(3);
+        records[0].timestamp = i64::MIN;
+        records[1].timestamp = i64::MAX;
+        let batch = to_record_batch(&records).unwrap();
+        let timestamps = batch.column_by_name("timestamp").unwrap();
+
+        assert!(timestamps.is_null(0));
+        assert!(timestamps.is_null(1));
+        assert!(!timestamps.is_null(2));
+    }
+
+    #[test]
+    fn it_should_write_row_groups() {
+        let mut output = vec![];
+        let mut exporter = ParquetExporter::new(&mut output, 4).unwrap();
+        let count = exporter.write_all(records(10)).unwrap();
+        exporter.close().unwrap();
+
+        let reader = SerializedFileReader::new(Bytes::from(output)).unwrap();
+
+        assert_eq!(count, 10);
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 10);
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+    }
+}
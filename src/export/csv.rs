@@ -0,0 +1,220 @@
+use crate::timestamp::UtcOffset;
+use crate::usn_record::Record;
+use crate::util::filetime::to_iso8601;
+use anyhow::Result;
+use std::io::Write;
+
+/// CSV columns, in the order they are written.
+///
+/// | column            | content                                   |
+/// |-------------------|-------------------------------------------|
+/// | `usn`             | update sequence number                    |
+/// | `timestamp`       | ISO-8601, 100ns precision, UTC by default |
+/// | `file_name`       | file name                                 |
+/// | `path`            | full path, empty when not resolved        |
+/// | `file_entry`      | MFT entry of the file                     |
+/// | `file_sequence`   | sequence number of the file               |
+/// | `parent_entry`    | MFT entry of the parent directory         |
+/// | `parent_sequence` | sequence number of the parent directory   |
+/// | `reasons`         | reason flag names joined by `\|`          |
+/// | `attributes`      | file attribute names joined by `\|`       |
+/// | `source_info`     | source info, hex                          |
+/// | `security_id`     | security id                               |
+/// | `version`         | record version, e.g. `2.0`                |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Usn,
+    Timestamp,
+    FileName,
+    Path,
+    FileEntry,
+    FileSequence,
+    ParentEntry,
+    ParentSequence,
+    Reasons,
+    Attributes,
+    SourceInfo,
+    SecurityId,
+    Version,
+}
+
+impl Column {
+    pub const ALL: [Column; 13] = [
+        Column::Usn,
+        Column::Timestamp,
+        Column::FileName,
+        Column::Path,
+        Column::FileEntry,
+        Column::FileSequence,
+        Column::ParentEntry,
+        Column::ParentSequence,
+        Column::Reasons,
+        Column::Attributes,
+        Column::SourceInfo,
+        Column::SecurityId,
+        Column::Version,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Usn => "usn",
+            Column::Timestamp => "timestamp",
+            Column::FileName => "file_name",
+            Column::Path => "path",
+            Column::FileEntry => "file_entry",
+            Column::FileSequence => "file_sequence",
+            Column::ParentEntry => "parent_entry",
+            Column::ParentSequence => "parent_sequence",
+            Column::Reasons => "reasons",
+            Column::Attributes => "attributes",
+            Column::SourceInfo => "source_info",
+            Column::SecurityId => "security_id",
+            Column::Version => "version",
+        }
+    }
+
+    pub fn value(&self, record: &Record) -> String {
+        match self {
+            Column::Usn => record.usn.to_string(),
+            Column::Timestamp => to_iso8601(record.timestamp),
+            Column::FileName => record.file_name.clone(),
+            Column::Path => record.path.clone().unwrap_or_default(),
+            Column::FileEntry => record.file_reference_number.entry().to_string(),
+            Column::FileSequence => record.file_reference_number.sequence().to_string(),
+            Column::ParentEntry => record.parent_file_reference_number.entry().to_string(),
+            Column::ParentSequence => record.parent_file_reference_number.sequence().to_string(),
+            Column::Reasons => record.reason.to_string(),
+            Column::Attributes => record.file_attributes.to_string(),
+            Column::SourceInfo => format!("{:#x}", record.source_info),
+            Column::SecurityId => record.security_id.to_string(),
+            Column::Version => format!("{}.0", record.major_version),
+        }
+    }
+}
+
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Vec<Column>,
+    utc_offset: UtcOffset,
+    header_written: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+            columns: Column::ALL.to_vec(),
+            utc_offset: UtcOffset::UTC,
+            header_written: false,
+        }
+    }
+
+    // selected columns are always written in schema order.
+    pub fn set_columns(&mut self, columns: &[Column]) -> &Self {
+        self.columns = Column::ALL
+            .into_iter()
+            .filter(|c| columns.contains(c))
+            .collect();
+        self
+    }
+
+    pub fn set_utc_offset(&mut self, offset: UtcOffset) -> &Self {
+        self.utc_offset = offset;
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        if !self.header_written {
+            self.writer
+                .write_record(self.columns.iter().map(|c| c.name()))?;
+            self.header_written = true;
+        }
+        self.writer
+            .write_record(self.columns.iter().map(|c| match c {
+                Column::Timestamp => self.utc_offset.format(record.timestamp),
+                c => c.value(record),
+            }))?;
+        Ok(())
+    }
+
+    pub fn write_all<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Result<u64> {
+        let mut count = 0;
+        for record in records {
+            self.write(&record)?;
+            count += 1;
+        }
+        self.writer.flush()?;
+        Ok(count)
+    }
+
+    pub fn into_inner(self) -> Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|e| anyhow::anyhow!("flush csv writer error: {}.", e.error()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::csv::{Column, CsvWriter};
+    use crate::flags::Reason;
+    use crate::usn_record::Record;
+
+    fn record() -> Record {
+        Record {
+            usn: 2424307712,
+            timestamp: 132989000930000000,
+            major_version: 2,
+            file_reference_number: 0x0003_0000_0000_0010.into(),
+            parent_file_reference_number: 0x0005_0000_0000_0005.into(),
+            reason: Reason(0x80000100),
+            file_name: "a, b.txt".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_should_write_all_columns_in_order() {
+        let mut writer = CsvWriter::new(vec![]);
+        writer.write_all(vec![record()]).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let mut lines = output.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "usn,timestamp,file_name,path,file_entry,file_sequence,parent_entry,\
+             parent_sequence,reasons,attributes,source_info,security_id,version"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "2424307712,2022-06-05T10:54:53.0000000Z,\"a, b.txt\",,16,3,5,5,\
+             FILE_CREATE|CLOSE,,0x0,0,2.0"
+        );
+    }
+
+    #[test]
+    fn it_should_write_selected_columns() {
+        let mut writer = CsvWriter::new(vec![]);
+        writer.set_columns(&[Column::FileName, Column::Usn]);
+        writer.write_all(vec![record()]).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(output, "usn,file_name\n2424307712,\"a, b.txt\"\n");
+    }
+
+    #[test]
+    fn it_should_write_timestamp_at_offset() {
+        let mut writer = CsvWriter::new(vec![]);
+        writer.set_columns(&[Column::Timestamp]);
+        writer.set_utc_offset("-05:00".parse().unwrap());
+        writer.write_all(vec![record()]).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            output,
+            "timestamp
+2022-06-05T05:54:53.0000000-05:00
+"
+        );
+    }
+}
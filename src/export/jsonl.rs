@@ -0,0 +1,155 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, Write};
+use std::marker::PhantomData;
+
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn write_all<T: Serialize, I: IntoIterator<Item = T>>(&mut self, values: I) -> Result<u64> {
+        let mut count = 0;
+        for value in values {
+            self.write(&value)?;
+            count += 1;
+        }
+        self.writer.flush()?;
+        Ok(count)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub struct JsonLinesReader<R: BufRead, T: DeserializeOwned> {
+    lines: std::io::Lines<R>,
+    _value: PhantomData<T>,
+}
+
+impl<R: BufRead, T: DeserializeOwned> JsonLinesReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for JsonLinesReader<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        match line.trim().is_empty() {
+            true => self.next(),
+            false => Some(serde_json::from_str(&line).map_err(Into::into)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::Checkpoint;
+    use crate::export::jsonl::{JsonLinesReader, JsonLinesWriter};
+    use crate::flags::{FileAttributes, Reason};
+    use crate::usn_journal_data::Data;
+    use crate::usn_record::Record;
+    use anyhow::Result;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                usn: 2424307712,
+                timestamp: 132989000930000001,
+                major_version: 2,
+                file_reference_number: 0x0003_0000_0000_0010.into(),
+                parent_file_reference_number: 0x0005_0000_0000_0005.into(),
+                reason: Reason(0x80000102),
+                file_attributes: FileAttributes(0x20),
+                file_name: "report.docx".to_string(),
+                path: Some(r"C:\Users\report.docx".to_string()),
+                ..Default::default()
+            },
+            Record {
+                usn: 2424307856,
+                timestamp: 132989000940000000,
+                major_version: 2,
+                reason: Reason(0x200),
+                file_name: "日本語.txt".to_string(),
+                recovered: true,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn it_should_round_trip_records() {
+        let mut writer = JsonLinesWriter::new(vec![]);
+        writer.write_all(records()).unwrap();
+        let output = writer.into_inner();
+
+        let read: Vec<Record> = JsonLinesReader::new(output.as_slice())
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn it_should_serialize_flags_and_timestamp_as_text() {
+        let line = serde_json::to_value(&records()[0]).unwrap();
+
+        assert_eq!(line["timestamp"], "2022-06-05T10:54:53.0000001Z");
+        assert_eq!(
+            line["reason"],
+            serde_json::json!(["DATA_EXTEND", "FILE_CREATE", "CLOSE"])
+        );
+        assert_eq!(line["file_attributes"], serde_json::json!(["ARCHIVE"]));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_flag() {
+        let result = serde_json::from_str::<Reason>(r#"["FILE_CREATE", "NOPE"]"#);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_should_round_trip_data_and_checkpoint() {
+        let data = Data {
+            usn_journal_id: 42,
+            next_usn: 1024,
+            max_supported_major_version: Some(4),
+            ..Default::default()
+        };
+        let checkpoint = Checkpoint {
+            usn_journal_id: 42,
+            next_usn: 1024,
+        };
+
+        let data_json = serde_json::to_string(&data).unwrap();
+        let checkpoint_json = serde_json::to_string(&checkpoint).unwrap();
+
+        assert_eq!(serde_json::from_str::<Data>(&data_json).unwrap(), data);
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&checkpoint_json).unwrap(),
+            checkpoint
+        );
+    }
+}
@@ -0,0 +1,8 @@
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod csv;
+#[cfg(feature = "serde")]
+pub mod jsonl;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod timeline;
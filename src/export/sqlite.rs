@@ -0,0 +1,269 @@
+use crate::usn_journal_data::Data;
+use crate::usn_record::Record;
+use crate::util::filetime::to_iso8601;
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS journal_info (
+    usn_journal_id INTEGER PRIMARY KEY,
+    first_usn INTEGER NOT NULL,
+    next_usn INTEGER NOT NULL,
+    lowest_valid_usn INTEGER NOT NULL,
+    max_usn INTEGER NOT NULL,
+    maximum_size INTEGER NOT NULL,
+    allocation_delta INTEGER NOT NULL,
+    min_supported_major_version INTEGER,
+    max_supported_major_version INTEGER,
+    flags INTEGER,
+    range_track_chunk_size INTEGER,
+    range_track_file_size_threshold INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS records (
+    usn_journal_id INTEGER NOT NULL,
+    usn INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    filetime INTEGER NOT NULL,
+    file_reference INTEGER NOT NULL,
+    file_entry INTEGER NOT NULL,
+    file_sequence INTEGER NOT NULL,
+    parent_reference INTEGER NOT NULL,
+    parent_entry INTEGER NOT NULL,
+    parent_sequence INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    path TEXT,
+    reason INTEGER NOT NULL,
+    reasons TEXT NOT NULL,
+    attributes INTEGER NOT NULL,
+    attribute_names TEXT NOT NULL,
+    source_info INTEGER NOT NULL,
+    security_id INTEGER NOT NULL,
+    major_version INTEGER NOT NULL,
+    recovered INTEGER NOT NULL,
+    PRIMARY KEY (usn_journal_id, usn)
+);
+
+CREATE INDEX IF NOT EXISTS records_usn ON records (usn);
+CREATE INDEX IF NOT EXISTS records_timestamp ON records (filetime);
+CREATE INDEX IF NOT EXISTS records_file_reference ON records (file_reference);
+CREATE INDEX IF NOT EXISTS records_parent_reference ON records (parent_reference);
+CREATE INDEX IF NOT EXISTS records_name ON records (name);
+"#;
+
+const MFT_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS mft (
+    file_reference INTEGER PRIMARY KEY,
+    file_entry INTEGER NOT NULL,
+    file_sequence INTEGER NOT NULL,
+    parent_reference INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    attributes INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS mft_parent_reference ON mft (parent_reference);
+CREATE INDEX IF NOT EXISTS mft_name ON mft (name);
+"#;
+
+pub struct SqliteExporter {
+    connection: Connection,
+    batch_size: usize,
+}
+
+impl SqliteExporter {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection,
+            batch_size: 10_000,
+        })
+    }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) -> &Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn write_journal_info(&mut self, data: &Data) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO journal_info VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                data.usn_journal_id as i64,
+                data.first_usn,
+                data.next_usn,
+                data.lowest_valid_usn,
+                data.max_usn,
+                data.maximum_size as i64,
+                data.allocation_delta as i64,
+                data.min_supported_major_version,
+                data.max_supported_major_version,
+                data.flags,
+                data.range_track_chunk_size.map(|s| s as i64),
+                data.range_track_file_size_threshold,
+            ],
+        )?;
+        Ok(())
+    }
+
+    // records already present for the same journal and usn are left untouched,
+    // so overlapping ranges can be imported again.
+    pub fn write_records<I>(&mut self, usn_journal_id: u64, records: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        let mut records = records.into_iter().peekable();
+        let mut inserted = 0;
+        while records.peek().is_some() {
+            let transaction = self.connection.transaction()?;
+            {
+                let mut statement = transaction.prepare_cached(
+                    "INSERT OR IGNORE INTO records VALUES \
+                     (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+                )?;
+                for record in records.by_ref().take(self.batch_size) {
+                    let file = record.file_reference_number;
+                    let parent = record.parent_file_reference_number;
+                    inserted += statement.execute(params![
+                        usn_journal_id as i64,
+                        record.usn,
+                        to_iso8601(record.timestamp),
+                        record.timestamp,
+                        file.0 as i64,
+                        file.entry() as i64,
+                        file.sequence(),
+                        parent.0 as i64,
+                        parent.entry() as i64,
+                        parent.sequence(),
+                        record.file_name,
+                        record.path,
+                        record.reason.0,
+                        record.reason.to_string(),
+                        record.file_attributes.0,
+                        record.file_attributes.to_string(),
+                        record.source_info,
+                        record.security_id,
+                        record.major_version,
+                        record.recovered,
+                    ])? as u64;
+                }
+            }
+            transaction.commit()?;
+        }
+
+        Ok(inserted)
+    }
+
+    // entries come from MFT enumeration, which reports each file as a record.
+    pub fn write_mft<I>(&mut self, entries: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        self.connection.execute_batch(MFT_SCHEMA)?;
+        let mut entries = entries.into_iter().peekable();
+        let mut inserted = 0;
+        while entries.peek().is_some() {
+            let transaction = self.connection.transaction()?;
+            {
+                let mut statement = transaction
+                    .prepare_cached("INSERT OR REPLACE INTO mft VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+                for entry in entries.by_ref().take(self.batch_size) {
+                    let file = entry.file_reference_number;
+                    inserted += statement.execute(params![
+                        file.0 as i64,
+                        file.entry() as i64,
+                        file.sequence(),
+                        entry.parent_file_reference_number.0 as i64,
+                        entry.file_name,
+                        entry.file_attributes.0,
+                    ])? as u64;
+                }
+            }
+            transaction.commit()?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::sqlite::SqliteExporter;
+    use crate::flags::Reason;
+    use crate::usn_journal_data::Data;
+    use crate::usn_record::Record;
+
+    fn records(usns: std::ops::Range<i64>) -> Vec<Record> {
+        usns.map(|usn| Record {
+            usn: usn * 64,
+            timestamp: 132989000930000000 + usn,
+            file_reference_number: (0x0001_0000_0000_0000 + usn as u64).into(),
+            reason: Reason(0x100),
+            file_name: format!("{}.txt", usn),
+            ..Default::default()
+        })
+        .collect()
+    }
+
+    fn count(exporter: &SqliteExporter, sql: &str) -> i64 {
+        exporter
+            .connection()
+            .query_row(sql, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn it_should_write_records_in_batches() {
+        let mut exporter = SqliteExporter::open_in_memory().unwrap();
+        exporter.set_batch_size(3);
+
+        let inserted = exporter.write_records(7, records(0..10)).unwrap();
+
+        assert_eq!(inserted, 10);
+        assert_eq!(count(&exporter, "SELECT COUNT(*) FROM records"), 10);
+        assert_eq!(
+            count(&exporter, "SELECT usn FROM records WHERE name = '4.txt'"),
+            256
+        );
+    }
+
+    #[test]
+    fn it_should_append_overlapping_ranges_once() {
+        let mut exporter = SqliteExporter::open_in_memory().unwrap();
+        exporter.write_records(7, records(0..10)).unwrap();
+
+        let inserted = exporter.write_records(7, records(5..15)).unwrap();
+
+        assert_eq!(inserted, 5);
+        assert_eq!(count(&exporter, "SELECT COUNT(*) FROM records"), 15);
+    }
+
+    #[test]
+    fn it_should_write_journal_info_and_mft() {
+        let mut exporter = SqliteExporter::open_in_memory().unwrap();
+        let data = Data {
+            usn_journal_id: 7,
+            next_usn: 640,
+            ..Default::default()
+        };
+
+        exporter.write_journal_info(&data).unwrap();
+        exporter.write_journal_info(&data).unwrap();
+        exporter.write_mft(records(0..4)).unwrap();
+
+        assert_eq!(count(&exporter, "SELECT COUNT(*) FROM journal_info"), 1);
+        assert_eq!(count(&exporter, "SELECT COUNT(*) FROM mft"), 4);
+    }
+}
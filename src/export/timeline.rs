@@ -0,0 +1,182 @@
+use crate::flags::DATA_CHANGE;
+use crate::raw::ioctl::USN_REASON_FILE_CREATE;
+use crate::usn_record::Record;
+use crate::util::filetime::to_unix_seconds;
+use anyhow::Result;
+use std::io::Write;
+
+// both formats are `|` separated, so it can not appear inside a field.
+fn description(record: &Record) -> String {
+    let name = record.path.as_deref().unwrap_or(&record.file_name);
+    format!(
+        "{} ($UsnJrnl: {})",
+        name.replace('|', "_"),
+        record.reason.names().join(",")
+    )
+}
+
+// Sleuthkit body file, `MD5|name|inode|mode|UID|GID|size|atime|mtime|ctime|crtime`.
+pub struct BodyFileWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BodyFileWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        let time = to_unix_seconds(record.timestamp);
+        let reason = record.reason;
+        let pick = |hit: bool| if hit { time } else { 0 };
+        let crtime = pick(reason.contains(USN_REASON_FILE_CREATE));
+        let mtime = pick(reason.0 & DATA_CHANGE != 0);
+        // every journal entry is a change of the file's metadata.
+        let ctime = time;
+        let mode = match record.file_attributes.names().contains(&"DIRECTORY") {
+            true => "d/drwxrwxrwx",
+            false => "r/rrwxrwxrwx",
+        };
+
+        writeln!(
+            self.writer,
+            "0|{}|{}-{}|{}|0|0|0|0|{}|{}|{}",
+            description(record),
+            record.file_reference_number.entry(),
+            record.file_reference_number.sequence(),
+            mode,
+            mtime,
+            ctime,
+            crtime
+        )?;
+        Ok(())
+    }
+
+    pub fn write_all<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Result<u64> {
+        let mut count = 0;
+        for record in records {
+            self.write(&record)?;
+            count += 1;
+        }
+        self.writer.flush()?;
+        Ok(count)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+// TLN, `Time|Source|System|User|Description`.
+pub struct TlnWriter<W: Write> {
+    writer: W,
+    system: String,
+}
+
+impl<W: Write> TlnWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            system: String::new(),
+        }
+    }
+
+    pub fn set_system(&mut self, system: &str) -> &Self {
+        self.system = system.replace('|', "_");
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        writeln!(
+            self.writer,
+            "{}|USN|{}||{}",
+            to_unix_seconds(record.timestamp),
+            self.system,
+            description(record)
+        )?;
+        Ok(())
+    }
+
+    pub fn write_all<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Result<u64> {
+        let mut count = 0;
+        for record in records {
+            self.write(&record)?;
+            count += 1;
+        }
+        self.writer.flush()?;
+        Ok(count)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::timeline::{BodyFileWriter, TlnWriter};
+    use crate::flags::{FileAttributes, Reason};
+    use crate::usn_record::Record;
+
+    fn record(reason: u32) -> Record {
+        Record {
+            timestamp: 132989000930000000,
+            file_reference_number: 0x0003_0000_0000_0010.into(),
+            reason: Reason(reason),
+            file_name: "a|b.txt".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_should_write_body_file_line() {
+        let mut writer = BodyFileWriter::new(vec![]);
+        writer.write_all(vec![record(0x80000100)]).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "0|a_b.txt ($UsnJrnl: FILE_CREATE,CLOSE)|16-3|r/rrwxrwxrwx|0|0|0|0|0|1654426493|1654426493\n"
+        );
+    }
+
+    #[test]
+    fn it_should_set_mtime_on_data_change() {
+        let mut writer = BodyFileWriter::new(vec![]);
+        let mut directory = record(0x2);
+        directory.file_attributes = FileAttributes(0x10);
+        writer.write(&directory).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let fields: Vec<&str> = output.trim_end().split('|').collect();
+
+        assert_eq!(fields.len(), 11);
+        assert_eq!(fields[3], "d/drwxrwxrwx");
+        assert_eq!(&fields[7..], ["0", "1654426493", "1654426493", "0"]);
+    }
+
+    #[test]
+    fn it_should_write_tln_line() {
+        let mut writer = TlnWriter::new(vec![]);
+        writer.set_system("HOST");
+        let mut deleted = record(0x80000200);
+        deleted.path = Some(r"C:\a.txt".to_string());
+        writer.write(&deleted).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "1654426493|USN|HOST||C:\\a.txt ($UsnJrnl: FILE_DELETE,CLOSE)\n"
+        );
+    }
+
+    #[test]
+    fn it_should_write_extreme_timestamps() {
+        let mut writer = TlnWriter::new(vec![]);
+        let mut earliest = record(0x2);
+        earliest.timestamp = i64::MIN;
+        writer.write(&earliest).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert!(output.starts_with("-933981677286|USN|"));
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::{Display, Formatter};
+
+// NTFS file reference: 48 bit MFT entry number, 16 bit sequence number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct FileReference(pub u64);
+
+impl FileReference {
+    pub fn entry(&self) -> u64 {
+        self.0 & 0x0000_FFFF_FFFF_FFFF
+    }
+
+    pub fn sequence(&self) -> u16 {
+        (self.0 >> 48) as u16
+    }
+}
+
+impl From<u64> for FileReference {
+    fn from(reference: u64) -> Self {
+        Self(reference)
+    }
+}
+
+impl Display for FileReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_reference::FileReference;
+
+    #[test]
+    fn it_should_split_entry_and_sequence() {
+        let reference = FileReference(0x0005_0000_0001_2345);
+
+        assert_eq!(reference.entry(), 0x1_2345);
+        assert_eq!(reference.sequence(), 5);
+    }
+}
@@ -0,0 +1,543 @@
+use crate::flags::{FileAttributes, Reason};
+use crate::usn_record::Record;
+use crate::util::filetime::from_iso8601;
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// A predicate over records, e.g. `reason:FILE_DELETE AND name:*.docx AND time>2024-01-01`.
+//
+// terms are `field op value`, combined with `AND`, `OR`, `NOT` and parentheses.
+// - `reason:NAME`, `attr:NAME`: the flag is set.
+// - `name`, `path`: `:` glob, `~` regex, `=` / `!=` exact, case insensitive except regex.
+// - `usn`, `file`, `parent`, `security`, `source`, `version`: `=`, `!=`, `<`, `<=`, `>`, `>=`.
+// - `time`: the same comparisons against `2024-01-01` or `2024-01-01T14:00:00[.fffffff][Z]`, UTC.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    // in chars, not bytes, from 0.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}.", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+fn error<T>(position: usize, message: impl Into<String>) -> Result<T, FilterError> {
+    Err(FilterError {
+        position,
+        message: message.into(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Match,
+    Regex,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Match | Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Regex => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberField {
+    Usn,
+    File,
+    Parent,
+    Security,
+    Source,
+    Version,
+}
+
+#[derive(Debug, Clone)]
+enum Text {
+    Glob(String),
+    Regex(Regex),
+    Exact(String, bool),
+}
+
+impl Text {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Text::Glob(pattern) => glob_match(pattern, &value.to_lowercase()),
+            Text::Regex(regex) => regex.is_match(value),
+            Text::Exact(expected, equal) => expected.eq_ignore_ascii_case(value) == *equal,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Reason(u32),
+    Attr(u32),
+    Name(Text),
+    Path(Text),
+    Number(NumberField, Op, u64),
+    Time(Op, i64),
+}
+
+impl Expr {
+    fn matches(&self, record: &Record) -> bool {
+        match self {
+            Expr::And(l, r) => l.matches(record) && r.matches(record),
+            Expr::Or(l, r) => l.matches(record) || r.matches(record),
+            Expr::Not(e) => !e.matches(record),
+            Expr::Reason(flag) => record.reason.contains(*flag),
+            Expr::Attr(flag) => record.file_attributes.contains(*flag),
+            Expr::Name(text) => text.matches(&record.file_name),
+            Expr::Path(text) => record.path.as_deref().is_some_and(|p| text.matches(p)),
+            Expr::Number(field, op, value) => {
+                let actual = match field {
+                    NumberField::Usn => record.usn as u64,
+                    NumberField::File => record.file_reference_number.0,
+                    NumberField::Parent => record.parent_file_reference_number.0,
+                    NumberField::Security => record.security_id as u64,
+                    NumberField::Source => record.source_info as u64,
+                    NumberField::Version => record.major_version as u64,
+                };
+                op.compare(actual, *value)
+            }
+            Expr::Time(op, value) => op.compare(record.timestamp, *value),
+        }
+    }
+}
+
+// case insensitive `*` and `?` matching, `value` is already lower case.
+pub(crate) fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bv)) => {
+                    backtrack = Some((bp, bv + 1));
+                    p = bp + 1;
+                    v = bv + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term {
+        field: String,
+        op: Op,
+        value: String,
+        value_position: usize,
+    },
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let chars: Vec<(usize, char)> = text.chars().enumerate().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '(' || c == ')' {
+            tokens.push((
+                position,
+                if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                },
+            ));
+            i += 1;
+            continue;
+        }
+        if !(c.is_ascii_alphabetic() || c == '_') {
+            return error(position, format!("unexpected '{}'", c));
+        }
+
+        let start = i;
+        while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '_') {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().map(|(_, c)| c).collect();
+        let next = chars.get(i).map(|(_, c)| *c);
+        let after = chars.get(i + 1).map(|(_, c)| *c);
+        let op = match (next, after) {
+            (Some('!'), Some('=')) => Some((Op::Ne, 2)),
+            (Some('<'), Some('=')) => Some((Op::Le, 2)),
+            (Some('>'), Some('=')) => Some((Op::Ge, 2)),
+            (Some(':'), _) => Some((Op::Match, 1)),
+            (Some('~'), _) => Some((Op::Regex, 1)),
+            (Some('='), _) => Some((Op::Eq, 1)),
+            (Some('<'), _) => Some((Op::Lt, 1)),
+            (Some('>'), _) => Some((Op::Gt, 1)),
+            _ => None,
+        };
+
+        let (op, len) = match op {
+            Some(op) => op,
+            None => {
+                let keyword = match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => return error(position, format!("expected an operator after '{}'", word)),
+                };
+                tokens.push((position, keyword));
+                continue;
+            }
+        };
+        i += len;
+
+        let value_position = i;
+        let mut value = String::new();
+        if chars.get(i).map(|(_, c)| *c) == Some('"') {
+            i += 1;
+            loop {
+                match chars.get(i).map(|(_, c)| *c) {
+                    None => return error(value_position, "unterminated string"),
+                    Some('"') => break,
+                    // only quotes are escaped, so windows paths read as written.
+                    Some('\\') if chars.get(i + 1).map(|(_, c)| *c) == Some('"') => {
+                        value.push(chars[i + 1].1);
+                        i += 2;
+                    }
+                    Some(c) => {
+                        value.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+        } else {
+            while i < chars.len() && !chars[i].1.is_whitespace() && chars[i].1 != ')' {
+                value.push(chars[i].1);
+                i += 1;
+            }
+        }
+        if value.is_empty() {
+            return error(value_position, format!("expected a value for '{}'", word));
+        }
+
+        tokens.push((
+            position,
+            Token::Term {
+                field: word.to_ascii_lowercase(),
+                op,
+                value,
+                value_position,
+            },
+        ));
+    }
+
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(p, _)| *p)
+            .unwrap_or(self.end)
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        let position = self.position();
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        match token {
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return error(self.position(), "expected ')'");
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Term {
+                field,
+                op,
+                value,
+                value_position,
+            }) => term(position, &field, op, &value, value_position),
+            Some(_) => error(position, "expected a term or '('"),
+            None => error(position, "unexpected end of filter"),
+        }
+    }
+}
+
+fn number(value: &str) -> Option<u64> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn time(value: &str) -> Option<i64> {
+    let value = match value.len() {
+        10 => format!("{}T00:00:00Z", value),
+        _ if value.ends_with('Z') => value.to_string(),
+        _ => format!("{}Z", value),
+    };
+    from_iso8601(&value).ok()
+}
+
+fn term(
+    position: usize,
+    field: &str,
+    op: Op,
+    value: &str,
+    value_position: usize,
+) -> Result<Expr, FilterError> {
+    let flag = |found: Option<u32>, kind: &str| match (op, found) {
+        (Op::Match, Some(flag)) => Ok(flag),
+        (Op::Match, None) => error(value_position, format!("unknown {} '{}'", kind, value)),
+        _ => error(position, format!("'{}' only supports ':'", field)),
+    };
+    let text = || match op {
+        Op::Match => Ok(Text::Glob(value.to_string())),
+        Op::Regex => Regex::new(value)
+            .map(Text::Regex)
+            .or_else(|e| error(value_position, format!("invalid regex: {}", e))),
+        Op::Eq => Ok(Text::Exact(value.to_string(), true)),
+        Op::Ne => Ok(Text::Exact(value.to_string(), false)),
+        _ => error(position, format!("'{}' does not support ordering", field)),
+    };
+    let compare = |op: Op| match op {
+        Op::Regex => error(position, format!("'{}' does not support '~'", field)),
+        op => Ok(op),
+    };
+
+    let number_field = match field {
+        "usn" => Some(NumberField::Usn),
+        "file" => Some(NumberField::File),
+        "parent" => Some(NumberField::Parent),
+        "security" => Some(NumberField::Security),
+        "source" => Some(NumberField::Source),
+        "version" => Some(NumberField::Version),
+        _ => None,
+    };
+    if let Some(number_field) = number_field {
+        let op = compare(op)?;
+        return match number(value) {
+            Some(n) => Ok(Expr::Number(number_field, op, n)),
+            None => error(value_position, format!("invalid number '{}'", value)),
+        };
+    }
+
+    match field {
+        "reason" => Ok(Expr::Reason(flag(Reason::from_name(value), "reason")?)),
+        "attr" => Ok(Expr::Attr(flag(
+            FileAttributes::from_name(value),
+            "attribute",
+        )?)),
+        "name" => Ok(Expr::Name(text()?)),
+        "path" => Ok(Expr::Path(text()?)),
+        "time" => {
+            let op = match compare(op)? {
+                Op::Match => return error(position, "'time' needs a comparison such as '>'"),
+                op => op,
+            };
+            match time(value) {
+                Some(t) => Ok(Expr::Time(op, t)),
+                None => error(value_position, format!("invalid time '{}'", value)),
+            }
+        }
+        _ => error(position, format!("unknown field '{}'", field)),
+    }
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let mut parser = ExprParser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.chars().count(),
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return error(parser.position(), "expected AND or OR");
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        self.expr.matches(record)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::Filter;
+    use crate::flags::{FileAttributes, Reason};
+    use crate::usn_record::Record;
+
+    fn record() -> Record {
+        Record {
+            usn: 4096,
+            timestamp: 133485408000000000, // 2024-01-01T00:00:00Z
+            parent_file_reference_number: 0x0005_0000_0000_1234.into(),
+            reason: Reason(0x80000200),
+            file_attributes: FileAttributes(0x20),
+            file_name: "Report Q1.DOCX".to_string(),
+            path: Some(r"C:\Users\a\Report Q1.DOCX".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn matches(filter: &str) -> bool {
+        Filter::parse(filter).unwrap().matches(&record())
+    }
+
+    #[test]
+    fn it_should_match_flags_and_globs() {
+        assert!(matches(
+            "reason:FILE_DELETE AND name:*.docx AND time>=2024-01-01"
+        ));
+        assert!(!matches("reason:FILE_DELETE AND time>2024-01-01"));
+        assert!(matches("attr:ARCHIVE"));
+        assert!(!matches("attr:DIRECTORY"));
+        assert!(matches("parent=0x5000000001234"));
+    }
+
+    #[test]
+    fn it_should_combine_with_precedence() {
+        assert!(matches("attr:DIRECTORY OR reason:CLOSE AND usn=4096"));
+        assert!(!matches("(attr:DIRECTORY OR reason:CLOSE) AND usn>4096"));
+        assert!(matches("NOT attr:DIRECTORY"));
+        assert!(matches("not (usn<100 or usn>=8192)"));
+    }
+
+    #[test]
+    fn it_should_match_paths_and_regex() {
+        assert!(matches(r#"path:"c:\users\*\report q?.docx""#));
+        assert!(matches(r#"name~"^Report Q\d""#));
+        assert!(!matches(r"name~^report"));
+        assert!(matches(r#"name="report q1.docx""#));
+        assert!(matches("time<2024-01-01T00:00:00.0000001Z"));
+    }
+
+    #[test]
+    fn it_should_report_error_position() {
+        let error = Filter::parse("reason:FILE_DELETE AND name:*.docx AND tiem>2024").unwrap_err();
+        assert_eq!(error.position, 39);
+        assert_eq!(error.to_string(), "unknown field 'tiem' at column 40.");
+
+        let error = Filter::parse("reason:NOPE").unwrap_err();
+        assert_eq!(error.position, 7);
+
+        let error = Filter::parse("name:\"é ü\" AND tiem>2024").unwrap_err();
+        assert_eq!(error.to_string(), "unknown field 'tiem' at column 16.");
+        let error = Filter::parse("name:日本 AND").unwrap_err();
+        assert_eq!(error.position, 11);
+
+        let error = Filter::parse("(usn>1 OR usn<0").unwrap_err();
+        assert_eq!(error.message, "expected ')'");
+        assert_eq!(error.position, 15);
+
+        let error = Filter::parse("usn>1 usn<0").unwrap_err();
+        assert_eq!(error.position, 6);
+
+        let error = Filter::parse("time>yesterday").unwrap_err();
+        assert_eq!(error.position, 5);
+
+        for filter in ["time>99999999999999-01-01T00:00:00", "time>2024-13-45"] {
+            let error = Filter::parse(filter).unwrap_err();
+            assert_eq!(error.position, 5);
+            assert!(error.message.starts_with("invalid time"));
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::raw::ioctl::{
+    FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_COMPRESSED, FILE_ATTRIBUTE_DEVICE,
+    FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_EA, FILE_ATTRIBUTE_ENCRYPTED, FILE_ATTRIBUTE_HIDDEN,
+    FILE_ATTRIBUTE_INTEGRITY_STREAM, FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_NOT_CONTENT_INDEXED,
+    FILE_ATTRIBUTE_NO_SCRUB_DATA, FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_PINNED,
+    FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_REPARSE_POINT,
+    FILE_ATTRIBUTE_SPARSE_FILE, FILE_ATTRIBUTE_SYSTEM, FILE_ATTRIBUTE_TEMPORARY,
+    FILE_ATTRIBUTE_UNPINNED, FILE_ATTRIBUTE_VIRTUAL,
+};
+use crate::raw::ioctl::{
+    USN_REASON_BASIC_INFO_CHANGE, USN_REASON_CLOSE, USN_REASON_COMPRESSION_CHANGE,
+    USN_REASON_DATA_EXTEND, USN_REASON_DATA_OVERWRITE, USN_REASON_DATA_TRUNCATION,
+    USN_REASON_DESIRED_STORAGE_CLASS_CHANGE, USN_REASON_EA_CHANGE, USN_REASON_ENCRYPTION_CHANGE,
+    USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE, USN_REASON_HARD_LINK_CHANGE,
+    USN_REASON_INDEXABLE_CHANGE, USN_REASON_INTEGRITY_CHANGE, USN_REASON_NAMED_DATA_EXTEND,
+    USN_REASON_NAMED_DATA_OVERWRITE, USN_REASON_NAMED_DATA_TRUNCATION, USN_REASON_OBJECT_ID_CHANGE,
+    USN_REASON_RENAME_NEW_NAME, USN_REASON_RENAME_OLD_NAME, USN_REASON_REPARSE_POINT_CHANGE,
+    USN_REASON_SECURITY_CHANGE, USN_REASON_STREAM_CHANGE, USN_REASON_TRANSACTED_CHANGE,
+};
+use std::fmt::{Display, Formatter};
+
+// any change to the content of the default or a named stream.
+pub const DATA_CHANGE: u32 = USN_REASON_DATA_OVERWRITE
+    | USN_REASON_DATA_EXTEND
+    | USN_REASON_DATA_TRUNCATION
+    | USN_REASON_NAMED_DATA_OVERWRITE
+    | USN_REASON_NAMED_DATA_EXTEND
+    | USN_REASON_NAMED_DATA_TRUNCATION;
+
+macro_rules! flags {
+    ($name:ident, [$(($flag:expr, $text:literal)),* $(,)?]) => {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        pub struct $name(pub u32);
+
+        impl $name {
+            pub const ALL: &'static [(u32, &'static str)] = &[$(($flag, $text)),*];
+
+            pub fn contains(&self, other: u32) -> bool {
+                self.0 & other == other
+            }
+
+            pub fn names(&self) -> Vec<&'static str> {
+                Self::ALL
+                    .iter()
+                    .filter(|(flag, _)| self.contains(*flag))
+                    .map(|(_, name)| *name)
+                    .collect()
+            }
+
+            // bits set that none of the names cover.
+            pub fn unknown(&self) -> u32 {
+                Self::ALL.iter().fold(self.0, |rest, (flag, _)| rest & !flag)
+            }
+
+            pub fn from_name(name: &str) -> Option<u32> {
+                Self::ALL
+                    .iter()
+                    .find(|(_, n)| n.eq_ignore_ascii_case(name))
+                    .map(|(flag, _)| *flag)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                // unknown bits go last as one hex entry, so they survive a round trip.
+                let mut names: Vec<String> = self.names().iter().map(|n| n.to_string()).collect();
+                if self.unknown() != 0 {
+                    names.push(format!("{:#x}", self.unknown()));
+                }
+                serializer.collect_seq(names)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let names = Vec::<String>::deserialize(deserializer)?;
+                names.iter().try_fold(Self(0), |flags, name| {
+                    let hex = name.strip_prefix("0x").map(|h| u32::from_str_radix(h, 16).ok());
+                    hex.unwrap_or_else(|| Self::from_name(name))
+                        .map(|flag| Self(flags.0 | flag))
+                        .ok_or_else(|| serde::de::Error::custom(format!("unknown flag {}.", name)))
+                })
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.names().join("|"))
+            }
+        }
+    };
+}
+
+flags!(
+    Reason,
+    [
+        (USN_REASON_DATA_OVERWRITE, "DATA_OVERWRITE"),
+        (USN_REASON_DATA_EXTEND, "DATA_EXTEND"),
+        (USN_REASON_DATA_TRUNCATION, "DATA_TRUNCATION"),
+        (USN_REASON_NAMED_DATA_OVERWRITE, "NAMED_DATA_OVERWRITE"),
+        (USN_REASON_NAMED_DATA_EXTEND, "NAMED_DATA_EXTEND"),
+        (USN_REASON_NAMED_DATA_TRUNCATION, "NAMED_DATA_TRUNCATION"),
+        (USN_REASON_FILE_CREATE, "FILE_CREATE"),
+        (USN_REASON_FILE_DELETE, "FILE_DELETE"),
+        (USN_REASON_EA_CHANGE, "EA_CHANGE"),
+        (USN_REASON_SECURITY_CHANGE, "SECURITY_CHANGE"),
+        (USN_REASON_RENAME_OLD_NAME, "RENAME_OLD_NAME"),
+        (USN_REASON_RENAME_NEW_NAME, "RENAME_NEW_NAME"),
+        (USN_REASON_INDEXABLE_CHANGE, "INDEXABLE_CHANGE"),
+        (USN_REASON_BASIC_INFO_CHANGE, "BASIC_INFO_CHANGE"),
+        (USN_REASON_HARD_LINK_CHANGE, "HARD_LINK_CHANGE"),
+        (USN_REASON_COMPRESSION_CHANGE, "COMPRESSION_CHANGE"),
+        (USN_REASON_ENCRYPTION_CHANGE, "ENCRYPTION_CHANGE"),
+        (USN_REASON_OBJECT_ID_CHANGE, "OBJECT_ID_CHANGE"),
+        (USN_REASON_REPARSE_POINT_CHANGE, "REPARSE_POINT_CHANGE"),
+        (USN_REASON_STREAM_CHANGE, "STREAM_CHANGE"),
+        (USN_REASON_TRANSACTED_CHANGE, "TRANSACTED_CHANGE"),
+        (USN_REASON_INTEGRITY_CHANGE, "INTEGRITY_CHANGE"),
+        (
+            USN_REASON_DESIRED_STORAGE_CLASS_CHANGE,
+            "DESIRED_STORAGE_CLASS_CHANGE"
+        ),
+        (USN_REASON_CLOSE, "CLOSE"),
+    ]
+);
+
+flags!(
+    FileAttributes,
+    [
+        (FILE_ATTRIBUTE_READONLY, "READONLY"),
+        (FILE_ATTRIBUTE_HIDDEN, "HIDDEN"),
+        (FILE_ATTRIBUTE_SYSTEM, "SYSTEM"),
+        (FILE_ATTRIBUTE_DIRECTORY, "DIRECTORY"),
+        (FILE_ATTRIBUTE_ARCHIVE, "ARCHIVE"),
+        (FILE_ATTRIBUTE_DEVICE, "DEVICE"),
+        (FILE_ATTRIBUTE_NORMAL, "NORMAL"),
+        (FILE_ATTRIBUTE_TEMPORARY, "TEMPORARY"),
+        (FILE_ATTRIBUTE_SPARSE_FILE, "SPARSE_FILE"),
+        (FILE_ATTRIBUTE_REPARSE_POINT, "REPARSE_POINT"),
+        (FILE_ATTRIBUTE_COMPRESSED, "COMPRESSED"),
+        (FILE_ATTRIBUTE_OFFLINE, "OFFLINE"),
+        (FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, "NOT_CONTENT_INDEXED"),
+        (FILE_ATTRIBUTE_ENCRYPTED, "ENCRYPTED"),
+        (FILE_ATTRIBUTE_INTEGRITY_STREAM, "INTEGRITY_STREAM"),
+        (FILE_ATTRIBUTE_VIRTUAL, "VIRTUAL"),
+        (FILE_ATTRIBUTE_NO_SCRUB_DATA, "NO_SCRUB_DATA"),
+        (FILE_ATTRIBUTE_EA, "EA"),
+        (FILE_ATTRIBUTE_PINNED, "PINNED"),
+        (FILE_ATTRIBUTE_UNPINNED, "UNPINNED"),
+        (
+            FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS,
+            "RECALL_ON_DATA_ACCESS"
+        ),
+    ]
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::flags::{FileAttributes, Reason};
+
+    #[test]
+    fn it_should_list_reason_names() {
+        let reason = Reason(0x80000102);
+
+        assert_eq!(reason.names(), vec!["DATA_EXTEND", "FILE_CREATE", "CLOSE"]);
+        assert_eq!(reason.to_string(), "DATA_EXTEND|FILE_CREATE|CLOSE");
+    }
+
+    #[test]
+    fn it_should_find_flag_by_name() {
+        assert_eq!(FileAttributes::from_name("directory"), Some(0x10));
+        assert_eq!(Reason::from_name("NOT_A_REASON"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_should_keep_unknown_bits_through_serde() {
+        let reason = Reason(0x80000008);
+        let json = serde_json::to_string(&reason).unwrap();
+
+        assert_eq!(json, r#"["CLOSE","0x8"]"#);
+        assert_eq!(serde_json::from_str::<Reason>(&json).unwrap(), reason);
+
+        let attributes = FileAttributes(0x2000_0000);
+        let json = serde_json::to_string(&attributes).unwrap();
+        assert_eq!(json, r#"["0x20000000"]"#);
+        assert_eq!(
+            serde_json::from_str::<FileAttributes>(&json).unwrap(),
+            attributes
+        );
+        assert!(serde_json::from_str::<Reason>(r#"["0xzz"]"#).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::events::{Event, EventAggregator};
+use crate::file_reference::FileReference;
+use crate::filter::Filter;
+use crate::usn_record::Record;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug)]
+pub enum FileSelector {
+    // exactly this file.
+    Reference(FileReference),
+    // every file that used this MFT entry, whatever its sequence number.
+    Entry(u64),
+    // every file with at least one matching record, e.g. `name:*.docx`.
+    Matching(Filter),
+}
+
+impl FileSelector {
+    // matching files are all found in a first pass.
+    fn selects(&self, record: &Record) -> bool {
+        match self {
+            FileSelector::Reference(r) => record.file_reference_number == *r,
+            FileSelector::Entry(e) => record.file_reference_number.entry() == *e,
+            FileSelector::Matching(_) => false,
+        }
+    }
+
+    // records of other files are only needed to find reused entries.
+    fn keeps(&self, record: &Record, matched_entries: &HashSet<u64>) -> bool {
+        let entry = record.file_reference_number.entry();
+        match self {
+            FileSelector::Reference(r) => entry == r.entry(),
+            FileSelector::Entry(e) => entry == *e,
+            FileSelector::Matching(_) => matched_entries.contains(&entry),
+        }
+    }
+}
+
+// Everything that happened to one file, in usn order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lifecycle {
+    pub file_reference_number: FileReference,
+    // every name the file had, oldest first.
+    pub names: Vec<String>,
+    pub events: Vec<Event>,
+    pub records: Vec<Record>,
+    // other files that used the same MFT entry with a different sequence number.
+    pub same_entry: Vec<FileReference>,
+}
+
+// One lifecycle per selected file, ordered by the first record of each. a reused MFT entry has
+// a new sequence number, so the files before and after the reuse stay apart. `records` hands out
+// the same records on every call: a filter may match any record of a file, so a `Matching`
+// selector reads them twice and only keeps the records of the entries it matched.
+pub fn file_history<F, I>(records: F, selector: &FileSelector) -> Vec<Lifecycle>
+where
+    F: Fn() -> I,
+    I: IntoIterator<Item = Record>,
+{
+    let mut selected = HashSet::new();
+    if let FileSelector::Matching(filter) = selector {
+        selected.extend(
+            records()
+                .into_iter()
+                .filter(|r| filter.matches(r))
+                .map(|r| r.file_reference_number),
+        );
+    }
+    let matched_entries: HashSet<u64> = selected.iter().map(|r| r.entry()).collect();
+
+    let mut files: HashMap<FileReference, Vec<Record>> = HashMap::new();
+    for record in records() {
+        if selector.selects(&record) {
+            selected.insert(record.file_reference_number);
+        }
+        if selector.keeps(&record, &matched_entries) {
+            files
+                .entry(record.file_reference_number)
+                .or_default()
+                .push(record);
+        }
+    }
+    let mut by_entry: HashMap<u64, Vec<FileReference>> = HashMap::new();
+    for reference in files.keys() {
+        by_entry
+            .entry(reference.entry())
+            .or_default()
+            .push(*reference);
+    }
+
+    let mut history: Vec<Lifecycle> = selected
+        .into_iter()
+        .map(|reference| {
+            let mut records = files.remove(&reference).unwrap_or_default();
+            records.sort_by_key(|r| r.usn);
+
+            let mut names: Vec<String> = vec![];
+            for record in &records {
+                if names.last() != Some(&record.file_name) {
+                    names.push(record.file_name.clone());
+                }
+            }
+
+            let mut same_entry: Vec<FileReference> = by_entry
+                .get(&reference.entry())
+                .into_iter()
+                .flatten()
+                .filter(|r| **r != reference)
+                .copied()
+                .collect();
+            same_entry.sort_by_key(|r| r.sequence());
+
+            Lifecycle {
+                file_reference_number: reference,
+                names,
+                events: EventAggregator::new(records.iter().cloned()).collect(),
+                records,
+                same_entry,
+            }
+        })
+        .collect();
+
+    history.sort_by_key(|l| l.records.first().map(|r| r.usn));
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::EventKind;
+    use crate::filter::Filter;
+    use crate::history::{file_history, FileSelector};
+    use crate::testing::{numbered, record};
+    use crate::usn_record::Record;
+
+    // entry 0x10 is "a.txt" renamed to "b.txt" and deleted, then reused for "c.txt".
+    fn journal() -> Vec<Record> {
+        let first = 0x0001_0000_0000_0010;
+        let second = 0x0002_0000_0000_0010;
+        numbered(vec![
+            record(first, 5, 0x80000100, "a.txt"),
+            record(0x20, 5, 0x80000100, "other.txt"),
+            record(first, 5, 0x1000, "a.txt"),
+            record(first, 5, 0x80002000, "b.txt"),
+            record(first, 5, 0x80000002, "b.txt"),
+            record(first, 5, 0x80000200, "b.txt"),
+            record(second, 5, 0x80000100, "c.txt"),
+        ])
+    }
+
+    #[test]
+    fn it_should_follow_renames() {
+        let history = file_history(
+            journal,
+            &FileSelector::Reference(0x0001_0000_0000_0010.into()),
+        );
+        let lifecycle = &history[0];
+        let kinds: Vec<EventKind> = lifecycle.events.iter().map(|e| e.kind.clone()).collect();
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(lifecycle.names, vec!["a.txt", "b.txt"]);
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::Created,
+                EventKind::Renamed {
+                    from: "a.txt".to_string(),
+                    to: "b.txt".to_string()
+                },
+                EventKind::Modified,
+                EventKind::Deleted,
+            ]
+        );
+        assert_eq!(lifecycle.same_entry, vec![0x0002_0000_0000_0010.into()]);
+    }
+
+    #[test]
+    fn it_should_keep_reused_entries_apart() {
+        let history = file_history(journal, &FileSelector::Entry(0x10));
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].records.len(), 5);
+        assert_eq!(history[1].names, vec!["c.txt"]);
+    }
+
+    #[test]
+    fn it_should_select_by_any_former_name() {
+        let filter = Filter::parse("name:b.*").unwrap();
+        let history = file_history(journal, &FileSelector::Matching(filter));
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].records[0].usn, 1);
+        assert_eq!(history[0].same_entry, vec![0x0002_0000_0000_0010.into()]);
+    }
+}
@@ -0,0 +1,571 @@
+use crate::checkpoint::{Checkpoint, Stale};
+use crate::file_reference::FileReference;
+use crate::filter::glob_match;
+use crate::flags::FileAttributes;
+use crate::raw::ioctl::{USN_REASON_FILE_DELETE, USN_REASON_RENAME_OLD_NAME};
+use crate::raw::ntfs::{MftRecord, NtfsVolume};
+use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+use crate::reader::{Reader, RecordFetcher};
+use crate::usn_journal_data::UsnJournalDataFactory;
+use crate::usn_journal_record::UsnRecordFactory;
+use crate::usn_record::Record;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{ErrorKind, Read, Seek};
+
+// MFT entry of the root directory, which is its own parent.
+const ROOT_ENTRY: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub file_reference_number: FileReference,
+    pub parent_file_reference_number: FileReference,
+    pub name: String,
+    pub file_attributes: FileAttributes,
+}
+
+impl From<&Record> for Entry {
+    fn from(record: &Record) -> Self {
+        Self {
+            file_reference_number: record.file_reference_number,
+            parent_file_reference_number: record.parent_file_reference_number,
+            name: record.file_name.clone(),
+            file_attributes: record.file_attributes,
+        }
+    }
+}
+
+// Case insensitive, on the name alone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    Prefix(String),
+    Substring(String),
+    // `*` and `?`, e.g. `*.docx`.
+    Glob(String),
+}
+
+impl Query {
+    // `name` is already lower case.
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Query::Prefix(prefix) => name.starts_with(&prefix.to_lowercase()),
+            Query::Substring(part) => name.contains(&part.to_lowercase()),
+            Query::Glob(pattern) => glob_match(pattern, name),
+        }
+    }
+}
+
+// Every file on a volume by MFT entry, with the journal position it is current as of. build it
+// from an MFT enumeration or an offline $MFT, then `follow` the journal to keep it current.
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    entries: HashMap<u64, Entry>,
+    // lower case name and MFT entry, ordered for prefix queries.
+    names: BTreeSet<(String, u64)>,
+    checkpoint: Checkpoint,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // enumerates the MFT of a live volume. the journal is queried first, so changes made
+    // while enumerating are caught up by `follow`.
+    pub fn from_enum<U: UsnJournalWrapper>(usn_journal: &U) -> Result<Self> {
+        let data = UsnJournalDataFactory::new(usn_journal).query()?.data;
+        let mut index = Self::new();
+        index.set_checkpoint(Checkpoint {
+            usn_journal_id: data.usn_journal_id,
+            next_usn: data.next_usn,
+        });
+
+        let mut factory = UsnRecordFactory::new(usn_journal);
+        factory.set_usn_journal_id(data.usn_journal_id);
+        loop {
+            let block = factory.enums::<65536>()?;
+            let next = block.next_usn.unwrap_or_default() as u64;
+            let records = block.parse();
+            if records.is_empty() {
+                break;
+            }
+            for record in records.iter() {
+                index.insert(record.into());
+            }
+            if next <= factory.start_file_reference {
+                break;
+            }
+            factory.set_start_file_reference(next);
+        }
+
+        Ok(index)
+    }
+
+    // reads the MFT out of an NTFS volume or disk image. it has no checkpoint, set one to
+    // follow a journal from the same point in time.
+    pub fn from_volume<R: Read + Seek>(volume: &mut NtfsVolume<R>) -> Result<Self> {
+        let mut index = Self::new();
+        for entry in 0..volume.entry_count() {
+            // unused and torn entries are skipped rather than failing the whole volume.
+            if let Ok(record) = volume.read_mft_record(entry) {
+                index.insert_mft_record(&record);
+            }
+        }
+        Ok(index)
+    }
+
+    // reads an extracted `$MFT` file, its record size taken from the first record.
+    pub fn from_mft_file<R: Read>(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 0x20];
+        reader.read_exact(&mut header)?;
+        let record_size = u32::from_le_bytes(header[0x1C..0x20].try_into()?) as usize;
+        if &header[0..4] != b"FILE" || !(512..=65536).contains(&record_size) {
+            return Err(anyhow!("invalid $MFT file."));
+        }
+
+        let mut index = Self::new();
+        let mut bytes = vec![0u8; record_size];
+        bytes[..header.len()].copy_from_slice(&header);
+        reader.read_exact(&mut bytes[header.len()..])?;
+        for entry in 0.. {
+            if let Ok(record) = MftRecord::parse(entry, bytes.clone()) {
+                index.insert_mft_record(&record);
+            }
+            match reader.read_exact(&mut bytes) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(index)
+    }
+
+    fn insert_mft_record(&mut self, record: &MftRecord) {
+        // names of extension records are found through their base record.
+        if !record.in_use() || record.base_reference() != 0 {
+            return;
+        }
+        let Some((parent, name)) = record.file_names().ok().and_then(|n| n.into_iter().next())
+        else {
+            return;
+        };
+        self.insert(Entry {
+            file_reference_number: record.file_reference().into(),
+            parent_file_reference_number: parent.into(),
+            name,
+            file_attributes: FileAttributes(record.file_attributes().ok().flatten().unwrap_or(0)),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.checkpoint
+    }
+
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) -> &Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    pub fn get(&self, reference: FileReference) -> Option<&Entry> {
+        self.entries
+            .get(&reference.entry())
+            .filter(|e| e.file_reference_number == reference)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    // replaces whatever file used the same MFT entry before.
+    pub fn insert(&mut self, entry: Entry) {
+        let key = entry.file_reference_number.entry();
+        let name = entry.name.to_lowercase();
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.names.remove(&(old.name.to_lowercase(), key));
+        }
+        self.names.insert((name, key));
+    }
+
+    pub fn remove(&mut self, reference: FileReference) -> Option<Entry> {
+        self.get(reference)?;
+        let old = self.entries.remove(&reference.entry())?;
+        self.names
+            .remove(&(old.name.to_lowercase(), reference.entry()));
+        Some(old)
+    }
+
+    // the record carries the name and parent as of the change, except the old name of a rename.
+    pub fn apply(&mut self, record: &Record) {
+        if record.reason.contains(USN_REASON_FILE_DELETE) {
+            self.remove(record.file_reference_number);
+        } else if !record.reason.contains(USN_REASON_RENAME_OLD_NAME) {
+            self.insert(record.into());
+        }
+    }
+
+    // applies every record from the checkpoint on and moves the checkpoint past them. fails
+    // when the journal was recreated or has wrapped past the checkpoint, as the index then
+    // missed changes and has to be built again.
+    pub fn follow<U: UsnJournalWrapper>(&mut self, usn_journal: &U) -> Result<usize> {
+        self.follow_with(usn_journal, |_, _| {})
+    }
+
+    // `follow`, handing each record to `f` along with the index as it was just before it.
+    pub fn follow_with<U, F>(&mut self, usn_journal: &U, mut f: F) -> Result<usize>
+    where
+        U: UsnJournalWrapper,
+        F: FnMut(&Index, &Record),
+    {
+        let data = UsnJournalDataFactory::new(usn_journal).query()?.data;
+        if let Some(stale) = self.checkpoint.stale(&data) {
+            return Err(anyhow!("{}, rebuild the index.", stale));
+        }
+
+        let mut reader = Reader::new(usn_journal);
+        reader.set_checkpoint(self.checkpoint);
+        let mut count = 0;
+        loop {
+            let records = match reader.do_fetch() {
+                Ok(records) => records,
+                Err(e) => match Stale::from_read_error(usn_journal, &e) {
+                    Some(stale) => return Err(anyhow!("{}, rebuild the index.", stale)),
+                    None => return Err(e),
+                },
+            };
+            if records.is_empty() {
+                break;
+            }
+            for record in records.iter() {
+                f(self, record);
+                self.apply(record);
+            }
+            count += records.len();
+            // a later block failing leaves the checkpoint after the records applied.
+            self.checkpoint = reader.checkpoint();
+        }
+        self.checkpoint = reader.checkpoint();
+        Ok(count)
+    }
+
+    // `\dir\name` from the root of the volume. a parent missing from the index shows as its
+    // file reference, e.g. `<0x5000000001234>\name`.
+    pub fn path(&self, reference: FileReference) -> Option<String> {
+        let entry = self.get(reference)?;
+        Some(match reference.entry() {
+            ROOT_ENTRY => "\\".to_string(),
+            _ => self.join(entry.parent_file_reference_number, &entry.name),
+        })
+    }
+
+    // the path `name` has under `parent`, as far as the index knows it.
+    pub fn join(&self, parent: FileReference, name: &str) -> String {
+        let mut parts = vec![name];
+        let mut parent = parent;
+        // a corrupt index could loop, no path is deeper than the index is large.
+        for _ in 0..=self.entries.len() {
+            if parent.entry() == ROOT_ENTRY {
+                parts.push("");
+                break;
+            }
+            match self.entries.get(&parent.entry()) {
+                Some(entry) => {
+                    parts.push(&entry.name);
+                    parent = entry.parent_file_reference_number;
+                }
+                None => {
+                    return format!("<{}>\\{}", parent, rev_join(&parts));
+                }
+            }
+        }
+        rev_join(&parts)
+    }
+
+    // fills in `path` of a record, from its name and parent as the index has it now.
+    pub fn resolve_path(&self, record: &mut Record) {
+        record.path = Some(self.join(record.parent_file_reference_number, &record.file_name));
+    }
+
+    // full paths of the matching files, sorted.
+    pub fn search(&self, query: &Query) -> Vec<String> {
+        let matches: Box<dyn Iterator<Item = &(String, u64)>> = match query {
+            Query::Prefix(prefix) => {
+                let prefix = prefix.to_lowercase();
+                Box::new(
+                    self.names
+                        .range((prefix.clone(), 0)..)
+                        .take_while(move |(name, _)| name.starts_with(&prefix)),
+                )
+            }
+            _ => Box::new(self.names.iter().filter(|(name, _)| query.matches(name))),
+        };
+        let mut paths: Vec<String> = matches
+            .filter_map(|(_, entry)| self.path(self.entries[entry].file_reference_number))
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+fn rev_join(parts: &[&str]) -> String {
+    parts.iter().rev().copied().collect::<Vec<_>>().join("\\")
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    checkpoint: Checkpoint,
+    // (file reference, parent reference, attributes, name), much smaller than named fields.
+    entries: Vec<(u64, u64, u32, String)>,
+}
+
+#[cfg(feature = "serde")]
+impl Index {
+    pub fn save<W: std::io::Write>(&self, writer: W) -> Result<()> {
+        let snapshot = Snapshot {
+            checkpoint: self.checkpoint,
+            entries: self
+                .entries
+                .values()
+                .map(|e| {
+                    (
+                        e.file_reference_number.0,
+                        e.parent_file_reference_number.0,
+                        e.file_attributes.0,
+                        e.name.clone(),
+                    )
+                })
+                .collect(),
+        };
+        serde_json::to_writer(writer, &snapshot)?;
+        Ok(())
+    }
+
+    // a saved index, `follow` catches it up from its checkpoint.
+    pub fn load<R: Read>(reader: R) -> Result<Self> {
+        let snapshot: Snapshot = serde_json::from_reader(reader)?;
+        let mut index = Self::new();
+        index.set_checkpoint(snapshot.checkpoint);
+        for (file, parent, attributes, name) in snapshot.entries {
+            index.insert(Entry {
+                file_reference_number: file.into(),
+                parent_file_reference_number: parent.into(),
+                name,
+                file_attributes: FileAttributes(attributes),
+            });
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::Checkpoint;
+    use crate::index::{Index, Query};
+    use crate::raw::ioctl::{ERROR_ACCESS_DENIED, ERROR_JOURNAL_ENTRY_DELETED};
+    use crate::raw::ntfs::tests::{entry, file_name, standard_information};
+    use crate::testing::{record, Call, FakeJournal};
+
+    const ROOT: u64 = 0x0005_0000_0000_0005;
+
+    // `\docs\a.txt`, `\docs\B.md` and `\c.txt`.
+    fn journal() -> FakeJournal {
+        let journal = FakeJournal::new();
+        journal
+            .extend(&[
+                record(0x0001_0000_0000_0010, ROOT, 0x80000100, "docs"),
+                record(
+                    0x0001_0000_0000_0011,
+                    0x0001_0000_0000_0010,
+                    0x80000100,
+                    "a.txt",
+                ),
+                record(
+                    0x0001_0000_0000_0012,
+                    0x0001_0000_0000_0010,
+                    0x80000100,
+                    "B.md",
+                ),
+                record(0x0001_0000_0000_0013, ROOT, 0x80000100, "c.txt"),
+            ])
+            .unwrap();
+        journal
+    }
+
+    #[test]
+    fn it_should_build_from_enumeration() {
+        let journal = journal();
+        let index = Index::from_enum(&journal).unwrap();
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            index.path(0x0001_0000_0000_0011.into()).unwrap(),
+            "\\docs\\a.txt"
+        );
+        assert_eq!(index.path(0x0002_0000_0000_0011.into()), None);
+        assert_eq!(
+            index.checkpoint(),
+            Checkpoint {
+                usn_journal_id: 1,
+                next_usn: journal.data().next_usn
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_search_names() {
+        let journal = journal();
+        let index = Index::from_enum(&journal).unwrap();
+
+        assert_eq!(
+            index.search(&Query::Prefix("b".to_string())),
+            vec!["\\docs\\B.md"]
+        );
+        assert_eq!(
+            index.search(&Query::Substring(".TXT".to_string())),
+            vec!["\\c.txt", "\\docs\\a.txt"]
+        );
+        assert_eq!(
+            index.search(&Query::Glob("?.*".to_string())),
+            vec!["\\c.txt", "\\docs\\B.md", "\\docs\\a.txt"]
+        );
+    }
+
+    #[test]
+    fn it_should_follow_the_journal() {
+        let journal = journal();
+        let mut index = Index::from_enum(&journal).unwrap();
+        journal
+            .extend(&[
+                record(
+                    0x0001_0000_0000_0011,
+                    0x0001_0000_0000_0010,
+                    0x1000,
+                    "a.txt",
+                ),
+                record(0x0001_0000_0000_0011, ROOT, 0x80002000, "moved.txt"),
+                record(0x0001_0000_0000_0013, ROOT, 0x80000200, "c.txt"),
+                // entry 0x12 reused for a new file.
+                record(0x0002_0000_0000_0012, ROOT, 0x80000100, "new.md"),
+            ])
+            .unwrap();
+
+        assert_eq!(index.follow(&journal).unwrap(), 4);
+        assert_eq!(index.follow(&journal).unwrap(), 0);
+        assert_eq!(index.checkpoint().next_usn, journal.data().next_usn);
+
+        let mut paths: Vec<String> = index
+            .entries()
+            .filter_map(|e| index.path(e.file_reference_number))
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["\\docs", "\\moved.txt", "\\new.md"]);
+    }
+
+    #[test]
+    fn it_should_keep_what_was_followed_when_a_read_fails() {
+        let journal = journal();
+        let mut index = Index::from_enum(&journal).unwrap();
+        journal.append(&record(0x14, ROOT, 0x100, "d.txt")).unwrap();
+        // the first block reads, the empty one after it fails.
+        journal.fail_at(
+            Call::Read,
+            journal.calls(Call::Read) + 2,
+            ERROR_ACCESS_DENIED,
+        );
+
+        assert!(index.follow(&journal).is_err());
+        assert_eq!(index.checkpoint().next_usn, journal.data().next_usn);
+        assert_eq!(index.follow(&journal).unwrap(), 0);
+        assert!(index.path(0x14.into()).is_some());
+
+        journal.fail_next(Call::Read, ERROR_JOURNAL_ENTRY_DELETED);
+        let err = index.follow(&journal).unwrap_err();
+        assert!(err.to_string().contains("rebuild the index"));
+    }
+
+    #[test]
+    fn it_should_require_a_rebuild() {
+        let journal = journal();
+        let mut index = Index::from_enum(&journal).unwrap();
+        journal.append(&record(0x14, ROOT, 0x100, "d.txt")).unwrap();
+        journal.purge(journal.data().next_usn);
+
+        assert!(index.follow(&journal).is_err());
+
+        let mut index = Index::from_enum(&journal).unwrap();
+        journal.recreate(2);
+        assert!(index.follow(&journal).is_err());
+    }
+
+    #[test]
+    fn it_should_resolve_orphans_and_records() {
+        let mut index = Index::new();
+        index.apply(&record(0x11, 0x10, 0x100, "a.txt"));
+        let mut moved = record(0x12, 0x0001_0000_0000_0011, 0x100, "b.txt");
+        index.resolve_path(&mut moved);
+
+        assert_eq!(index.path(0x11.into()).unwrap(), "<0x10>\\a.txt");
+        assert_eq!(moved.path.unwrap(), "<0x10>\\a.txt\\b.txt");
+    }
+
+    fn mft_record(sequence: u16, parent: u64, name: &str, flags: u16) -> Vec<u8> {
+        entry(
+            sequence,
+            flags,
+            &[standard_information(0, 0x20), file_name(parent, name, 0)],
+        )
+    }
+
+    #[test]
+    fn it_should_build_from_an_mft_file() {
+        let mut mft = vec![];
+        for entry in 0..0x12u64 {
+            mft.extend(match entry {
+                5 => mft_record(5, ROOT, ".", 0x3),
+                0x10 => mft_record(1, ROOT, "docs", 0x3),
+                0x11 => mft_record(1, 0x0001_0000_0000_0010, "a.txt", 0x1),
+                _ => vec![0u8; 1024],
+            });
+        }
+        let error = Index::from_mft_file(mft.as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid $MFT file.");
+
+        mft[..1024].copy_from_slice(&mft_record(1, ROOT, "$MFT", 0x1));
+        let index = Index::from_mft_file(mft.as_slice()).unwrap();
+        let docs = index.get(0x0001_0000_0000_0010.into()).unwrap();
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(docs.file_attributes.0, 0x30);
+        assert_eq!(
+            index.search(&Query::Glob("*.txt".to_string())),
+            vec!["\\docs\\a.txt"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_should_reload_a_snapshot_and_catch_up() {
+        let journal = journal();
+        let mut snapshot = vec![];
+        Index::from_enum(&journal)
+            .unwrap()
+            .save(&mut snapshot)
+            .unwrap();
+        journal
+            .append(&record(0x0001_0000_0000_0014, ROOT, 0x80000100, "d.txt"))
+            .unwrap();
+
+        let mut index = Index::load(snapshot.as_slice()).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.follow(&journal).unwrap(), 1);
+        assert_eq!(index.path(0x0001_0000_0000_0014.into()).unwrap(), "\\d.txt");
+    }
+}
@@ -0,0 +1,454 @@
+use crate::file_reference::FileReference;
+use crate::raw::ioctl::{
+    USN_REASON_BASIC_INFO_CHANGE, USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE,
+};
+use crate::raw::ntfs::MftRecord;
+use crate::raw::parser::PAGE_SIZE;
+use crate::usn_journal_data::Data;
+use crate::usn_record::Record;
+use crate::util::filetime::TICKS_PER_SECOND;
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndicatorConfig {
+    // lower case, without the dot.
+    pub executable_extensions: Vec<String>,
+    // a time change within this many ticks of the create is flagged.
+    pub after_create: i64,
+    pub log_extensions: Vec<String>,
+    // this many log deletions within `window` ticks are flagged.
+    pub mass_delete_count: usize,
+    pub window: i64,
+    // a journal that is not full and starts this long after the volume was formatted.
+    pub journal_start_delay: i64,
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        Self {
+            executable_extensions: strings(&[
+                "exe", "dll", "sys", "scr", "com", "ps1", "bat", "cmd", "vbs", "js", "msi",
+            ]),
+            after_create: 600 * TICKS_PER_SECOND,
+            log_extensions: strings(&["evtx", "evt", "etl", "log", "pf"]),
+            mass_delete_count: 10,
+            window: 60 * TICKS_PER_SECOND,
+            journal_start_delay: 86_400 * TICKS_PER_SECOND,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Indicator {
+    // BASIC_INFO_CHANGE on an executable shortly after it was created.
+    TimeChangeAfterCreate,
+    // the oldest record is much younger than the volume, yet the journal has room to spare.
+    RecentJournal {
+        volume_created: i64,
+        first_timestamp: i64,
+    },
+    // records are missing between two usns.
+    UsnGap {
+        expected: i64,
+        found: i64,
+    },
+    MassLogDeletion,
+    // $STANDARD_INFORMATION created before $FILE_NAME created.
+    StandardInformationBeforeFileName {
+        si_created: i64,
+        fn_created: i64,
+    },
+    // $STANDARD_INFORMATION times without a sub-second part, as set by most stomping tools.
+    WholeSecondTimes,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub indicator: Indicator,
+    pub file_reference_number: Option<FileReference>,
+    pub records: Vec<Record>,
+}
+
+fn extension(name: &str) -> Option<String> {
+    name.rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .map(|(_, e)| e.to_lowercase())
+}
+
+// bytes the record takes up in the journal, None when it can not be told.
+fn record_len(record: &Record) -> Option<i64> {
+    let header = match record.major_version {
+        2 => 60,
+        3 => 76,
+        _ => return record.record_length.map(i64::from),
+    };
+    let name = record.file_name.encode_utf16().count() as i64 * 2;
+    Some((header + name + 7) / 8 * 8)
+}
+
+// Anti-forensics checks. records are pushed in usn order; the journal and $MFT checks are
+// separate because they need data the record stream does not have.
+pub struct IndicatorScanner {
+    config: IndicatorConfig,
+    created: HashMap<FileReference, Record>,
+    // the creates in `created`, oldest first, to expire them.
+    creates: VecDeque<(i64, FileReference)>,
+    log_deletes: VecDeque<Record>,
+    previous: Option<Record>,
+}
+
+impl IndicatorScanner {
+    pub fn new(config: IndicatorConfig) -> Self {
+        Self {
+            config,
+            created: HashMap::new(),
+            creates: VecDeque::new(),
+            log_deletes: VecDeque::new(),
+            previous: None,
+        }
+    }
+
+    pub fn push(&mut self, record: &Record) -> Vec<Finding> {
+        let mut findings = vec![];
+        if record.recovered {
+            return findings;
+        }
+        findings.extend(self.check_gap(record));
+        findings.extend(self.check_time_change(record));
+        findings.extend(self.check_log_deletion(record));
+        findings
+    }
+
+    // records never cross a page, so the next one starts at most at the next page.
+    fn check_gap(&mut self, record: &Record) -> Option<Finding> {
+        let previous = self.previous.replace(record.clone())?;
+        let end = previous.usn + record_len(&previous)?;
+        let page = PAGE_SIZE as i64;
+        let expected = (end + page - 1) / page * page;
+        (record.usn > expected).then(|| Finding {
+            indicator: Indicator::UsnGap {
+                expected: end,
+                found: record.usn,
+            },
+            file_reference_number: None,
+            records: vec![previous, record.clone()],
+        })
+    }
+
+    fn check_time_change(&mut self, record: &Record) -> Option<Finding> {
+        let oldest = record.timestamp.saturating_sub(self.config.after_create);
+        while let Some((timestamp, file)) = self.creates.front().copied() {
+            if timestamp >= oldest {
+                break;
+            }
+            self.creates.pop_front();
+            // unless it was created again since.
+            if self
+                .created
+                .get(&file)
+                .is_some_and(|c| c.timestamp == timestamp)
+            {
+                self.created.remove(&file);
+            }
+        }
+
+        let file = record.file_reference_number;
+        let executable = extension(&record.file_name)
+            .is_some_and(|e| self.config.executable_extensions.contains(&e));
+        if !executable {
+            return None;
+        }
+        if record.reason.contains(USN_REASON_FILE_CREATE) && !self.created.contains_key(&file) {
+            self.created.insert(file, record.clone());
+            self.creates.push_back((record.timestamp, file));
+        }
+        if !record.reason.contains(USN_REASON_BASIC_INFO_CHANGE) {
+            return None;
+        }
+
+        let created = self.created.remove(&file)?;
+        (record.timestamp.saturating_sub(created.timestamp) <= self.config.after_create).then(
+            || Finding {
+                indicator: Indicator::TimeChangeAfterCreate,
+                file_reference_number: Some(file),
+                records: vec![created, record.clone()],
+            },
+        )
+    }
+
+    fn check_log_deletion(&mut self, record: &Record) -> Option<Finding> {
+        let oldest = record.timestamp.saturating_sub(self.config.window);
+        while self
+            .log_deletes
+            .front()
+            .is_some_and(|r| r.timestamp < oldest)
+        {
+            self.log_deletes.pop_front();
+        }
+
+        let log =
+            extension(&record.file_name).is_some_and(|e| self.config.log_extensions.contains(&e));
+        if !record.reason.contains(USN_REASON_FILE_DELETE) || !log {
+            return None;
+        }
+        self.log_deletes.push_back(record.clone());
+        (self.log_deletes.len() >= self.config.mass_delete_count).then(|| Finding {
+            indicator: Indicator::MassLogDeletion,
+            file_reference_number: None,
+            records: self.log_deletes.drain(..).collect(),
+        })
+    }
+
+    // `volume_created` is usually the creation time of $MFT itself.
+    pub fn check_journal(
+        &self,
+        data: &Data,
+        first: &Record,
+        volume_created: i64,
+    ) -> Option<Finding> {
+        let used = (data.next_usn - data.first_usn).max(0) as u64;
+        let late = first.timestamp.saturating_sub(volume_created) > self.config.journal_start_delay;
+        (late && used < data.maximum_size / 2).then(|| Finding {
+            indicator: Indicator::RecentJournal {
+                volume_created,
+                first_timestamp: first.timestamp,
+            },
+            file_reference_number: None,
+            records: vec![first.clone()],
+        })
+    }
+
+    pub fn check_mft_record(&self, record: &MftRecord) -> Result<Vec<Finding>> {
+        let mut findings = vec![];
+        let Some(si) = record.standard_information()? else {
+            return Ok(findings);
+        };
+        if !record.in_use() {
+            return Ok(findings);
+        }
+        let reference = FileReference(record.entry | (record.sequence() as u64) << 48);
+        let finding = |indicator| Finding {
+            indicator,
+            file_reference_number: Some(reference),
+            records: vec![],
+        };
+
+        let fn_created = record.file_name_times()?.iter().map(|t| t.created).min();
+        if let Some(fn_created) = fn_created.filter(|c| si.created < *c) {
+            findings.push(finding(Indicator::StandardInformationBeforeFileName {
+                si_created: si.created,
+                fn_created,
+            }));
+        }
+        let whole = |t: i64| t % TICKS_PER_SECOND == 0;
+        if whole(si.created) && whole(si.modified) && fn_created.is_some_and(|t| !whole(t)) {
+            findings.push(finding(Indicator::WholeSecondTimes));
+        }
+
+        Ok(findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indicators::{Indicator, IndicatorScanner};
+    use crate::raw::ntfs::tests::{entry, file_name, standard_information};
+    use crate::raw::ntfs::MftRecord;
+    use crate::testing::record;
+    use crate::usn_journal_data::Data;
+    use crate::usn_record::Record;
+
+    const SECOND: i64 = 10_000_000;
+    const CREATED: i64 = 132989000931234567;
+
+    // ten milliseconds a byte.
+    fn at(usn: i64, record: Record) -> Record {
+        Record {
+            usn,
+            timestamp: CREATED + usn * SECOND / 100,
+            ..record
+        }
+    }
+
+    fn scan(records: &[Record]) -> Vec<Indicator> {
+        let mut scanner = IndicatorScanner::new(Default::default());
+        records
+            .iter()
+            .flat_map(|r| scanner.push(r))
+            .map(|f| f.indicator)
+            .collect()
+    }
+
+    #[test]
+    fn it_should_flag_time_change_after_create() {
+        let records = [
+            at(0, record(0x10, 5, 0x100, "tool.exe")),
+            at(72, record(0x10, 5, 0x8102, "tool.exe")),
+            at(144, record(0x11, 5, 0x100, "notes.txt")),
+            at(224, record(0x11, 5, 0x8100, "notes.txt")),
+        ];
+
+        assert_eq!(scan(&records), vec![Indicator::TimeChangeAfterCreate]);
+    }
+
+    #[test]
+    fn it_should_forget_old_creates() {
+        let mut scanner = IndicatorScanner::new(Default::default());
+        for i in 0..100 {
+            let name = format!("{}.exe", i);
+            scanner.push(&at(i * 72, record(0x10 + i as u64, 5, 0x100, &name)));
+        }
+        assert_eq!(scanner.created.len(), 100);
+
+        // more than ten minutes after the last create.
+        let late = Record {
+            timestamp: CREATED + 700 * SECOND,
+            ..at(100 * 72, record(0x200, 5, 0x2, "notes.txt"))
+        };
+        assert!(scanner.push(&late).is_empty());
+        assert!(scanner.created.is_empty());
+        assert!(scanner.creates.is_empty());
+    }
+
+    #[test]
+    fn it_should_flag_usn_gaps() {
+        // "a.txt" takes 72 bytes, the next record may start on the next page at the latest.
+        let records = [
+            at(0, record(0x10, 5, 0x2, "a.txt")),
+            at(72, record(0x10, 5, 0x2, "a.txt")),
+            at(4096, record(0x10, 5, 0x2, "a.txt")),
+            at(3 * 4096, record(0x10, 5, 0x2, "a.txt")),
+        ];
+
+        assert_eq!(
+            scan(&records),
+            vec![Indicator::UsnGap {
+                expected: 4096 + 72,
+                found: 3 * 4096
+            }]
+        );
+    }
+
+    #[test]
+    fn it_should_flag_usn_gaps_after_v4_records() {
+        // a V4 record has no name to size it by.
+        let ranges = Record {
+            major_version: 4,
+            record_length: Some(0x50),
+            ..at(72, record(0x10, 5, 0x2, ""))
+        };
+        let mut records = [
+            at(0, record(0x10, 5, 0x2, "a.txt")),
+            ranges,
+            at(4096, record(0x10, 5, 0x2, "a.txt")),
+        ];
+        assert!(scan(&records).is_empty());
+
+        records[2].usn = 2 * 4096;
+        assert_eq!(
+            scan(&records),
+            vec![Indicator::UsnGap {
+                expected: 72 + 0x50,
+                found: 2 * 4096
+            }]
+        );
+    }
+
+    #[test]
+    fn it_should_flag_mass_log_deletion() {
+        let mut usn = 0;
+        let records: Vec<Record> = (0..10)
+            .map(|i| {
+                let name = format!("Security-{}.evtx", i);
+                let record = at(usn, record(0x10 + i, 5, 0x80000200, &name));
+                usn += (60 + name.len() as i64 * 2 + 7) / 8 * 8;
+                record
+            })
+            .collect();
+
+        assert_eq!(scan(&records), vec![Indicator::MassLogDeletion]);
+    }
+
+    #[test]
+    fn it_should_flag_recent_journal() {
+        let scanner = IndicatorScanner::new(Default::default());
+        let data = Data {
+            first_usn: 0,
+            next_usn: 1 << 20,
+            maximum_size: 32 << 20,
+            ..Default::default()
+        };
+        let first = at(0, record(0x10, 5, 0x100, "a.txt"));
+
+        assert!(scanner
+            .check_journal(&data, &first, CREATED - 30 * 86_400 * SECOND)
+            .is_some());
+        assert!(scanner
+            .check_journal(&data, &first, CREATED - 3600 * SECOND)
+            .is_none());
+    }
+
+    #[test]
+    fn it_should_scan_extreme_timestamps() {
+        let mut records = [
+            at(0, record(0x10, 5, 0x100, "tool.exe")),
+            at(72, record(0x10, 5, 0x8102, "tool.exe")),
+            at(144, record(0x11, 5, 0x80000200, "a.evtx")),
+        ];
+        records[0].timestamp = i64::MAX;
+        records[1].timestamp = i64::MIN;
+        records[2].timestamp = i64::MIN;
+        let scanner = IndicatorScanner::new(Default::default());
+        let data = Data {
+            maximum_size: 32 << 20,
+            ..Default::default()
+        };
+
+        assert_eq!(scan(&records), vec![Indicator::TimeChangeAfterCreate]);
+        assert!(scanner
+            .check_journal(&data, &records[0], i64::MIN)
+            .is_some());
+    }
+
+    fn mft_record(si_created: i64, fn_created: i64) -> MftRecord {
+        let bytes = entry(
+            3,
+            0x1,
+            &[
+                standard_information(si_created, 0),
+                file_name(0x5, "a.txt", fn_created),
+            ],
+        );
+        MftRecord::parse(0x42, bytes).unwrap()
+    }
+
+    #[test]
+    fn it_should_compare_si_and_fn_times() {
+        let scanner = IndicatorScanner::new(Default::default());
+        let stomped = CREATED / SECOND * SECOND - 365 * 86_400 * SECOND;
+        let findings = scanner
+            .check_mft_record(&mft_record(stomped, CREATED))
+            .unwrap();
+
+        assert_eq!(
+            findings.iter().map(|f| &f.indicator).collect::<Vec<_>>(),
+            vec![
+                &Indicator::StandardInformationBeforeFileName {
+                    si_created: stomped,
+                    fn_created: CREATED
+                },
+                &Indicator::WholeSecondTimes,
+            ]
+        );
+        assert_eq!(
+            findings[0].file_reference_number,
+            Some(0x0003_0000_0000_0042.into())
+        );
+        assert!(scanner
+            .check_mft_record(&mft_record(CREATED, CREATED))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,10 +1,28 @@
+pub mod changes;
+pub mod checkpoint;
+pub mod events;
+pub mod export;
+pub mod file_reference;
+pub mod filter;
+pub mod flags;
+pub mod history;
+pub mod index;
+pub mod indicators;
+pub mod multi;
+pub mod ranges;
+pub mod ransomware;
 pub mod raw;
 pub mod reader;
+pub mod stats;
+pub mod subscription;
+pub mod testing;
+pub mod timestamp;
 pub mod usn_journal_data;
 pub mod usn_journal_record;
 pub mod usn_journal_record_iter;
 pub mod usn_record;
 mod util;
+pub mod volume;
 
 #[cfg(test)]
 mod tests {
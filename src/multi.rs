@@ -0,0 +1,263 @@
+use crate::checkpoint::Checkpoint;
+use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+use crate::reader::{Reader, RecordFetcher};
+use crate::usn_record::Record;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, VecDeque};
+
+// What the merged reader needs of each volume, so live journals and files can be mixed.
+pub trait Source {
+    fn fetch(&self) -> Result<Box<Vec<Record>>>;
+    fn checkpoint(&self) -> Checkpoint;
+}
+
+impl<'a, U: UsnJournalWrapper> Source for Reader<'a, U> {
+    fn fetch(&self) -> Result<Box<Vec<Record>>> {
+        self.do_fetch()
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Reader::checkpoint(self)
+    }
+}
+
+// One checkpoint per volume, saved and loaded together.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Checkpoints(pub BTreeMap<String, Checkpoint>);
+
+impl Checkpoints {
+    pub fn get(&self, volume: &str) -> Option<Checkpoint> {
+        self.0.get(volume).copied()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Checkpoints {
+    pub fn save<W: std::io::Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<R: std::io::Read>(reader: R) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+struct Volume<'a> {
+    name: String,
+    source: Box<dyn Source + 'a>,
+    // fetched, not handed out yet.
+    pending: VecDeque<Record>,
+    // read to the end in this pass.
+    exhausted: bool,
+}
+
+impl Volume<'_> {
+    // the usn of the first record not handed out yet, recovered records have stale ones.
+    fn checkpoint(&self) -> Checkpoint {
+        let checkpoint = self.source.checkpoint();
+        match self.pending.iter().find(|r| !r.recovered) {
+            Some(record) => Checkpoint {
+                next_usn: record.usn,
+                ..checkpoint
+            },
+            None => checkpoint,
+        }
+    }
+}
+
+// Several journals read as one stream ordered by timestamp, each record tagged with the
+// volume it came from. set the checkpoint of each reader before adding it to resume.
+#[derive(Default)]
+pub struct MultiReader<'a> {
+    volumes: Vec<Volume<'a>>,
+}
+
+impl<'a> MultiReader<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_source<S: Source + 'a>(&mut self, volume: &str, source: S) -> Result<&Self> {
+        if self.volumes.iter().any(|v| v.name == volume) {
+            return Err(anyhow!("volume {} is already added.", volume));
+        }
+        self.volumes.push(Volume {
+            name: volume.to_string(),
+            source: Box::new(source),
+            pending: VecDeque::new(),
+            exhausted: false,
+        });
+        Ok(self)
+    }
+
+    pub fn checkpoints(&self) -> Checkpoints {
+        Checkpoints(
+            self.volumes
+                .iter()
+                .map(|v| (v.name.clone(), v.checkpoint()))
+                .collect(),
+        )
+    }
+
+    // the oldest record among the volumes, or `None` once all of them are read to the end.
+    // the next call after that reads on from where each volume stopped.
+    pub fn next_record(&mut self) -> Result<Option<Record>> {
+        for volume in self.volumes.iter_mut() {
+            if volume.pending.is_empty() && !volume.exhausted {
+                let block = volume.source.fetch()?;
+                volume.exhausted = block.is_empty();
+                volume.pending.extend(*block);
+            }
+        }
+
+        let oldest = self
+            .volumes
+            .iter_mut()
+            .filter(|v| !v.pending.is_empty())
+            .min_by_key(|v| v.pending[0].timestamp);
+        let Some(volume) = oldest else {
+            self.volumes.iter_mut().for_each(|v| v.exhausted = false);
+            return Ok(None);
+        };
+        Ok(volume.pending.pop_front().map(|record| Record {
+            volume: Some(volume.name.clone()),
+            ..record
+        }))
+    }
+
+    // everything there is to read now, merged.
+    pub fn read(&mut self) -> Result<Vec<Record>> {
+        let mut records = vec![];
+        while let Some(record) = self.next_record()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    use crate::multi::Checkpoints;
+    use crate::multi::MultiReader;
+    use crate::raw::builder::{JournalWriter, RecordBuilder};
+    use crate::raw::file::UsnJournalFile;
+    use crate::reader::Reader;
+    use crate::testing::FakeJournal;
+    use crate::usn_record::Record;
+    use std::io::Cursor;
+
+    fn file(timestamps: &[i64]) -> UsnJournalFile<Cursor<Vec<u8>>> {
+        let mut writer = JournalWriter::new(4096);
+        for timestamp in timestamps {
+            writer
+                .push(
+                    RecordBuilder::default()
+                        .set_timestamp(*timestamp)
+                        .set_file_name("a.txt"),
+                )
+                .unwrap();
+        }
+        UsnJournalFile::new(Cursor::new(writer.stream())).unwrap()
+    }
+
+    fn live(timestamps: &[i64]) -> FakeJournal {
+        let journal = FakeJournal::new();
+        for timestamp in timestamps {
+            journal
+                .append(&Record {
+                    timestamp: *timestamp,
+                    major_version: 2,
+                    file_name: "b.txt".to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        journal
+    }
+
+    fn tags(records: &[Record]) -> Vec<(i64, &str)> {
+        records
+            .iter()
+            .map(|r| (r.timestamp, r.volume.as_deref().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_merge_by_timestamp() {
+        let (c, d, e) = (live(&[1, 4, 6]), file(&[2, 3, 9]), file(&[5]));
+        let mut reader = MultiReader::new();
+        reader.add_source("C:", Reader::new(&c)).unwrap();
+        reader.add_source("D:", Reader::new(&d)).unwrap();
+        reader.add_source("vhd", Reader::new(&e)).unwrap();
+
+        assert!(reader.add_source("C:", Reader::new(&c)).is_err());
+        assert_eq!(
+            tags(&reader.read().unwrap()),
+            vec![
+                (1, "C:"),
+                (2, "D:"),
+                (3, "D:"),
+                (4, "C:"),
+                (5, "vhd"),
+                (6, "C:"),
+                (9, "D:")
+            ]
+        );
+        assert!(reader.read().unwrap().is_empty());
+
+        c.append(&Record {
+            timestamp: 10,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(tags(&reader.read().unwrap()), vec![(10, "C:")]);
+    }
+
+    #[test]
+    fn it_should_resume_from_checkpoints() {
+        let (c, d) = (live(&[1, 4, 6]), file(&[2, 3, 9]));
+        let mut reader = MultiReader::new();
+        reader.add_source("C:", Reader::new(&c)).unwrap();
+        reader.add_source("D:", Reader::new(&d)).unwrap();
+        let first: Vec<Record> = (0..3)
+            .map(|_| reader.next_record().unwrap().unwrap())
+            .collect();
+        let checkpoints = reader.checkpoints();
+
+        let mut reader = MultiReader::new();
+        let mut source = Reader::new(&c);
+        source.set_checkpoint(checkpoints.get("C:").unwrap());
+        reader.add_source("C:", source).unwrap();
+        let mut source = Reader::new(&d);
+        source.set_checkpoint(checkpoints.get("D:").unwrap());
+        reader.add_source("D:", source).unwrap();
+
+        assert_eq!(tags(&first), vec![(1, "C:"), (2, "D:"), (3, "D:")]);
+        assert_eq!(
+            tags(&reader.read().unwrap()),
+            vec![(4, "C:"), (6, "C:"), (9, "D:")]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_should_save_checkpoints_together() {
+        let (c, d) = (live(&[1]), file(&[2]));
+        let mut reader = MultiReader::new();
+        reader.add_source("C:", Reader::new(&c)).unwrap();
+        reader.add_source("D:", Reader::new(&d)).unwrap();
+        reader.read().unwrap();
+        let mut saved = vec![];
+        reader.checkpoints().save(&mut saved).unwrap();
+
+        assert!(String::from_utf8_lossy(&saved).starts_with(r#"{"C:":{"usn_journal_id":1,"#));
+        assert_eq!(
+            Checkpoints::load(saved.as_slice()).unwrap(),
+            reader.checkpoints()
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::checkpoint::{Checkpoint, Stale};
+use crate::file_reference::FileReference;
+use crate::flags::DATA_CHANGE;
+use crate::raw::ioctl::{USN_REASON_CLOSE, USN_REASON_FILE_DELETE};
+use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+use crate::reader::{Reader, RecordFetcher};
+use crate::usn_journal_data::UsnJournalDataFactory;
+use crate::usn_record::{Extent, Record};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Turns on range tracking: files of at least `file_size_threshold` bytes get V4 records of the
+// `chunk_size` aligned ranges written to. returns where tracking starts.
+pub fn track_modified_ranges<U: UsnJournalWrapper>(
+    usn_journal: &U,
+    chunk_size: u64,
+    file_size_threshold: i64,
+) -> Result<Checkpoint> {
+    unsafe { usn_journal.raw_track_ranges(chunk_size, file_size_threshold)? };
+    let data = UsnJournalDataFactory::new(usn_journal).query()?.data;
+    Ok(Checkpoint {
+        usn_journal_id: data.usn_journal_id,
+        next_usn: data.next_usn,
+    })
+}
+
+// Byte ranges of one file, sorted, with overlapping and touching ones merged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct RangeSet {
+    extents: Vec<Extent>,
+}
+
+fn end(extent: &Extent) -> i64 {
+    extent.offset.saturating_add(extent.length)
+}
+
+impl RangeSet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, extent: Extent) {
+        if extent.length <= 0 {
+            return;
+        }
+        let (mut start, mut stop) = (extent.offset, end(&extent));
+        let first = self.extents.partition_point(|e| end(e) < start);
+        let last = self.extents.partition_point(|e| e.offset <= stop);
+        for e in &self.extents[first..last] {
+            start = start.min(e.offset);
+            stop = stop.max(end(e));
+        }
+        let merged = Extent {
+            offset: start,
+            length: stop.checked_sub(start).unwrap_or(i64::MAX),
+        };
+        self.extents.splice(first..last, [merged]);
+    }
+
+    pub fn extents(&self) -> &[Extent] {
+        &self.extents
+    }
+
+    pub fn len(&self) -> usize {
+        self.extents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extents.is_empty()
+    }
+
+    // bytes covered, each counted once.
+    pub fn total_length(&self) -> i64 {
+        self.extents
+            .iter()
+            .fold(0, |sum, e| sum.saturating_add(e.length))
+    }
+}
+
+// What was written between two checkpoints, by file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModifiedRanges {
+    pub files: BTreeMap<FileReference, RangeSet>,
+    // written to without range records, being under the size threshold. copy these whole.
+    pub whole_files: BTreeSet<FileReference>,
+    // where these end, to pass in next time.
+    pub checkpoint: Checkpoint,
+    // files written to since their last close, and whether they got range records.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    open: BTreeMap<FileReference, bool>,
+}
+
+impl ModifiedRanges {
+    // deleted files are dropped, there is nothing left to copy. a file written to without range
+    // records until its close stays whole, even if it gets range records later on.
+    pub fn add(&mut self, record: &Record) {
+        let file = record.file_reference_number;
+        if record.reason.contains(USN_REASON_FILE_DELETE) {
+            self.files.remove(&file);
+            self.whole_files.remove(&file);
+            self.open.remove(&file);
+            return;
+        }
+        if record.major_version == 4 {
+            self.open.insert(file, true);
+            let ranges = self.files.entry(file).or_default();
+            record.extents.iter().for_each(|e| ranges.insert(*e));
+            return;
+        }
+        if record.reason.0 & DATA_CHANGE != 0 {
+            self.open.entry(file).or_insert(false);
+        }
+        if record.reason.contains(USN_REASON_CLOSE) && self.open.remove(&file) == Some(false) {
+            self.whole_files.insert(file);
+        }
+    }
+
+    // files still open at the end may not have all their range records yet.
+    pub fn finish(&mut self) {
+        for (file, ranged) in std::mem::take(&mut self.open) {
+            if !ranged {
+                self.whole_files.insert(file);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.whole_files.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RangeChanges {
+    Changed(ModifiedRanges),
+    // records since the checkpoint were lost, copy everything instead.
+    FullRescanRequired(Stale),
+}
+
+// Reads the records from `checkpoint` on, V4 ones included, into ranges per file.
+pub fn modified_ranges_since<U: UsnJournalWrapper>(
+    usn_journal: &U,
+    checkpoint: Checkpoint,
+) -> Result<RangeChanges> {
+    let data = UsnJournalDataFactory::new(usn_journal).query()?.data;
+    if let Some(stale) = checkpoint.stale(&data) {
+        return Ok(RangeChanges::FullRescanRequired(stale));
+    }
+
+    let mut reader = Reader::new(usn_journal);
+    reader.set_checkpoint(checkpoint);
+    reader.set_max_major_version(4);
+    let mut ranges = ModifiedRanges::default();
+    loop {
+        let block = match reader.do_fetch() {
+            Ok(block) => block,
+            Err(e) => {
+                return match Stale::from_read_error(usn_journal, &e) {
+                    Some(stale) => Ok(RangeChanges::FullRescanRequired(stale)),
+                    None => Err(e),
+                }
+            }
+        };
+        if block.is_empty() {
+            break;
+        }
+        block.iter().for_each(|r| ranges.add(r));
+    }
+    ranges.finish();
+    ranges.checkpoint = reader.checkpoint();
+    Ok(RangeChanges::Changed(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::Stale;
+    use crate::flags::Reason;
+    use crate::ranges::{
+        modified_ranges_since, track_modified_ranges, ModifiedRanges, RangeChanges, RangeSet,
+    };
+    use crate::raw::ioctl::ERROR_INVALID_PARAMETER;
+    use crate::raw::usn_journal_wrapper::error_code;
+    use crate::reader::{Reader, RecordFetcher};
+    use crate::testing::{record, FakeJournal};
+    use crate::usn_journal_data::DataVer;
+    use crate::usn_record::{Extent, Record};
+
+    const BIG: u64 = 0x0001_0000_0000_0020;
+    const SMALL: u64 = 0x0001_0000_0000_0021;
+
+    fn extent(offset: i64, length: i64) -> Extent {
+        Extent { offset, length }
+    }
+
+    fn ranges(file: u64, extents: &[(i64, i64)]) -> Record {
+        Record {
+            major_version: 4,
+            file_reference_number: file.into(),
+            parent_file_reference_number: 5.into(),
+            reason: Reason(0x1),
+            extents: extents.iter().map(|(o, l)| extent(*o, *l)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn changed(changes: RangeChanges) -> ModifiedRanges {
+        match changes {
+            RangeChanges::Changed(ranges) => ranges,
+            RangeChanges::FullRescanRequired(stale) => panic!("{}", stale),
+        }
+    }
+
+    #[test]
+    fn it_should_coalesce_ranges() {
+        let mut set = RangeSet::new();
+        set.insert(extent(0x4000, 0x1000));
+        set.insert(extent(0x0, 0x1000));
+        set.insert(extent(0x8000, 0x1000));
+        // touches the first, overlaps the second.
+        set.insert(extent(0x1000, 0x3800));
+        set.insert(extent(0x2000, 0x0));
+
+        assert_eq!(
+            set.extents(),
+            &[extent(0x0, 0x5000), extent(0x8000, 0x1000)]
+        );
+        assert_eq!(set.total_length(), 0x6000);
+
+        set.insert(extent(0x0, 0x10000));
+        assert_eq!(set.extents(), &[extent(0x0, 0x10000)]);
+    }
+
+    #[test]
+    fn it_should_only_read_v4_records_when_asked() {
+        let journal = FakeJournal::new();
+        journal
+            .extend(&[
+                record(BIG, 5, 0x1, "a.vhdx"),
+                ranges(BIG, &[(0x10000, 0x1000)]),
+            ])
+            .unwrap();
+        let mut reader = Reader::new(&journal);
+
+        assert_eq!(reader.do_fetch().unwrap().len(), 1);
+
+        reader.set_start_usn(0);
+        reader.set_max_major_version(4);
+        let records = reader.do_fetch().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].extents, vec![extent(0x10000, 0x1000)]);
+        assert!(records[1].file_name.is_empty());
+    }
+
+    #[test]
+    fn it_should_merge_ranges_between_checkpoints() {
+        let journal = FakeJournal::new();
+        journal
+            .append(&record(BIG, 5, 0x80000100, "a.vhdx"))
+            .unwrap();
+        let checkpoint = track_modified_ranges(&journal, 0x1000, 0x100000).unwrap();
+        journal
+            .extend(&[
+                record(BIG, 5, 0x1, "a.vhdx"),
+                ranges(BIG, &[(0x10000, 0x1000), (0x30000, 0x2000)]),
+                ranges(BIG, &[(0x11000, 0x1000)]),
+                record(SMALL, 5, 0x2, "a.vhdx"),
+                // created, written and deleted in between.
+                ranges(0x22, &[(0x0, 0x1000)]),
+                record(0x22, 5, 0x80000200, "a.vhdx"),
+            ])
+            .unwrap();
+        let changes = changed(modified_ranges_since(&journal, checkpoint).unwrap());
+
+        assert_eq!(journal.data().range_track_chunk_size, Some(0x1000));
+        assert_eq!(changes.files.len(), 1);
+        assert_eq!(
+            changes.files[&BIG.into()].extents(),
+            &[extent(0x10000, 0x2000), extent(0x30000, 0x2000)]
+        );
+        assert_eq!(
+            changes.whole_files.iter().copied().collect::<Vec<_>>(),
+            vec![SMALL.into()]
+        );
+        assert_eq!(changes.checkpoint.next_usn, journal.data().next_usn);
+        assert!(changed(modified_ranges_since(&journal, changes.checkpoint).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn it_should_keep_whole_files_whole() {
+        let journal = FakeJournal::new();
+        let checkpoint = track_modified_ranges(&journal, 0x1000, 0x100000).unwrap();
+        journal
+            .extend(&[
+                // grew past the threshold, after being written to without range records.
+                record(SMALL, 5, 0x2, "a.vhdx"),
+                record(SMALL, 5, 0x80000002, "a.vhdx"),
+                record(BIG, 5, 0x2, "a.vhdx"),
+                ranges(SMALL, &[(0x100000, 0x1000)]),
+                ranges(BIG, &[(0x0, 0x1000)]),
+                record(SMALL, 5, 0x80000002, "a.vhdx"),
+                record(BIG, 5, 0x80000002, "a.vhdx"),
+            ])
+            .unwrap();
+        let changes = changed(modified_ranges_since(&journal, checkpoint).unwrap());
+
+        assert_eq!(
+            changes.whole_files.iter().copied().collect::<Vec<_>>(),
+            vec![SMALL.into()]
+        );
+        assert_eq!(changes.files.len(), 2);
+    }
+
+    #[test]
+    fn it_should_not_overflow_extreme_ranges() {
+        let mut set = RangeSet::new();
+        set.insert(extent(i64::MAX - 0x1000, 0x1000));
+        set.insert(extent(-0x1000, i64::MAX));
+
+        assert_eq!(set.extents(), &[extent(-0x1000, i64::MAX)]);
+        assert_eq!(set.total_length(), i64::MAX);
+    }
+
+    #[test]
+    fn it_should_need_journal_data_v2_and_a_current_checkpoint() {
+        let journal = FakeJournal::new();
+        journal.set_data_ver(DataVer::V1);
+        let err = track_modified_ranges(&journal, 0x1000, 0).err().unwrap();
+
+        assert_eq!(error_code(&err), Some(ERROR_INVALID_PARAMETER));
+
+        journal.set_data_ver(DataVer::V2);
+        let checkpoint = track_modified_ranges(&journal, 0x1000, 0).unwrap();
+        journal.recreate(2);
+        assert_eq!(
+            modified_ranges_since(&journal, checkpoint).unwrap(),
+            RangeChanges::FullRescanRequired(Stale::Recreated { usn_journal_id: 2 })
+        );
+    }
+}
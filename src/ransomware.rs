@@ -0,0 +1,379 @@
+use crate::file_reference::FileReference;
+use crate::flags::DATA_CHANGE;
+use crate::raw::ioctl::{
+    USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE, USN_REASON_RENAME_NEW_NAME,
+    USN_REASON_RENAME_OLD_NAME,
+};
+use crate::usn_record::Record;
+use crate::util::filetime::TICKS_PER_SECOND;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectorConfig {
+    // in FILETIME ticks.
+    pub window: i64,
+    // files and directories hit within one window before an alert.
+    pub min_files: usize,
+    pub min_directories: usize,
+    // lower case, without the dot. a file renamed or created with one of these is a hit on its own.
+    pub extensions: Vec<String>,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            window: 60 * TICKS_PER_SECOND,
+            min_files: 20,
+            min_directories: 3,
+            extensions: [
+                "encrypted",
+                "enc",
+                "locked",
+                "crypt",
+                "crypted",
+                "cry",
+                "locky",
+                "wncry",
+                "cerber",
+                "zepto",
+                "ryk",
+                "conti",
+                "lockbit",
+            ]
+            .iter()
+            .map(|e| e.to_string())
+            .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pattern {
+    // data written, then renamed to a different extension.
+    OverwriteRename,
+    // a copy created next to the original, and the original deleted.
+    ReplaceDelete,
+    // renamed or created with a known ransomware extension.
+    SuspiciousExtension,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    pub files: usize,
+    pub directories: usize,
+    pub patterns: BTreeSet<Pattern>,
+    pub evidence: Vec<Record>,
+}
+
+struct Hit {
+    timestamp: i64,
+    file: FileReference,
+    directory: FileReference,
+    pattern: Pattern,
+    evidence: Vec<Record>,
+}
+
+fn extension(name: &str) -> Option<String> {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => Some(extension.to_lowercase()),
+        _ => None,
+    }
+}
+
+// "a.docx" replaced by "a.docx.locked" or by "a.enc".
+fn replaces(new: &str, old: &str) -> bool {
+    let (new, old) = (new.to_lowercase(), old.to_lowercase());
+    let stem = |name: &str| {
+        name.rsplit_once('.')
+            .map_or(name.to_string(), |(s, _)| s.into())
+    };
+    new != old && (new.starts_with(&format!("{}.", old)) || stem(&new) == stem(&old))
+}
+
+// Sliding window heuristics over records. push records one at a time, from a follow loop or
+// an offline journal, and an alert is returned once enough files are hit within the window.
+pub struct RansomwareDetector {
+    config: DetectorConfig,
+    writes: HashMap<FileReference, Record>,
+    old_names: HashMap<FileReference, Record>,
+    creates: HashMap<FileReference, Vec<Record>>,
+    deletes: HashMap<FileReference, Vec<Record>>,
+    hits: VecDeque<Hit>,
+}
+
+impl RansomwareDetector {
+    pub fn new(config: DetectorConfig) -> Self {
+        Self {
+            config,
+            writes: HashMap::new(),
+            old_names: HashMap::new(),
+            creates: HashMap::new(),
+            deletes: HashMap::new(),
+            hits: VecDeque::new(),
+        }
+    }
+
+    fn suspicious(&self, name: &str) -> bool {
+        extension(name).is_some_and(|e| self.config.extensions.contains(&e))
+    }
+
+    fn expire(&mut self, now: i64) {
+        let oldest = now.saturating_sub(self.config.window);
+        self.writes.retain(|_, r| r.timestamp >= oldest);
+        self.old_names.retain(|_, r| r.timestamp >= oldest);
+        for records in [&mut self.creates, &mut self.deletes] {
+            records.retain(|_, v| {
+                v.retain(|r| r.timestamp >= oldest);
+                !v.is_empty()
+            });
+        }
+        while self.hits.front().is_some_and(|h| h.timestamp < oldest) {
+            self.hits.pop_front();
+        }
+    }
+
+    fn hit(&mut self, record: &Record, pattern: Pattern, evidence: Vec<Record>) {
+        self.hits.push_back(Hit {
+            timestamp: record.timestamp,
+            file: record.file_reference_number,
+            directory: record.parent_file_reference_number,
+            pattern,
+            evidence,
+        });
+    }
+
+    pub fn push(&mut self, record: &Record) -> Option<Alert> {
+        self.expire(record.timestamp);
+        let reason = record.reason;
+        let file = record.file_reference_number;
+        let directory = record.parent_file_reference_number;
+
+        if reason.0 & DATA_CHANGE != 0 {
+            self.writes.insert(file, record.clone());
+        }
+        if reason.contains(USN_REASON_RENAME_OLD_NAME) {
+            self.old_names.insert(file, record.clone());
+        }
+        if reason.contains(USN_REASON_RENAME_NEW_NAME) {
+            if let Some(old) = self.old_names.remove(&file) {
+                let changed = extension(&old.file_name) != extension(&record.file_name);
+                if self.suspicious(&record.file_name) {
+                    self.hit(
+                        record,
+                        Pattern::SuspiciousExtension,
+                        vec![old, record.clone()],
+                    );
+                } else if let (true, Some(write)) = (changed, self.writes.get(&file).cloned()) {
+                    self.hit(
+                        record,
+                        Pattern::OverwriteRename,
+                        vec![write, old, record.clone()],
+                    );
+                }
+            }
+        }
+        if reason.contains(USN_REASON_FILE_CREATE) {
+            if self.suspicious(&record.file_name) {
+                self.hit(record, Pattern::SuspiciousExtension, vec![record.clone()]);
+            }
+            let deleted = self.deletes.get(&directory).and_then(|v| {
+                v.iter()
+                    .find(|d| replaces(&record.file_name, &d.file_name))
+                    .cloned()
+            });
+            match deleted {
+                Some(deleted) => self.hit(
+                    record,
+                    Pattern::ReplaceDelete,
+                    vec![record.clone(), deleted],
+                ),
+                None => self
+                    .creates
+                    .entry(directory)
+                    .or_default()
+                    .push(record.clone()),
+            }
+        }
+        if reason.contains(USN_REASON_FILE_DELETE) {
+            let created = self.creates.get(&directory).and_then(|v| {
+                v.iter()
+                    .find(|c| replaces(&c.file_name, &record.file_name))
+                    .cloned()
+            });
+            match created {
+                Some(created) => self.hit(
+                    &created,
+                    Pattern::ReplaceDelete,
+                    vec![created.clone(), record.clone()],
+                ),
+                None => self
+                    .deletes
+                    .entry(directory)
+                    .or_default()
+                    .push(record.clone()),
+            }
+        }
+
+        self.alert()
+    }
+
+    fn alert(&mut self) -> Option<Alert> {
+        let files: HashSet<FileReference> = self.hits.iter().map(|h| h.file).collect();
+        let directories: HashSet<FileReference> = self.hits.iter().map(|h| h.directory).collect();
+        if files.len() < self.config.min_files || directories.len() < self.config.min_directories {
+            return None;
+        }
+
+        // the hits are reported once, the next alert needs a fresh window of them.
+        let hits: Vec<Hit> = self.hits.drain(..).collect();
+        Some(Alert {
+            first_timestamp: hits.iter().map(|h| h.timestamp).min()?,
+            last_timestamp: hits.iter().map(|h| h.timestamp).max()?,
+            files: files.len(),
+            directories: directories.len(),
+            patterns: hits.iter().map(|h| h.pattern).collect(),
+            evidence: hits.into_iter().flat_map(|h| h.evidence).collect(),
+        })
+    }
+
+    pub fn scan<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Vec<Alert> {
+        records
+            .into_iter()
+            .filter_map(|record| self.push(&record))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ransomware::{DetectorConfig, Pattern, RansomwareDetector};
+    use crate::testing::{record, TIMESTAMP};
+    use crate::usn_record::Record;
+
+    const SECOND: i64 = 10_000_000;
+
+    struct Trace {
+        records: Vec<Record>,
+        usn: i64,
+    }
+
+    impl Trace {
+        fn new() -> Self {
+            Self {
+                records: vec![],
+                usn: 0,
+            }
+        }
+
+        fn push(&mut self, file: u64, parent: u64, reason: u32, name: &str) {
+            self.usn += 1;
+            self.records.push(Record {
+                usn: self.usn,
+                // ten records a second.
+                timestamp: TIMESTAMP + self.usn * SECOND / 10,
+                ..record(file, parent, reason, name)
+            });
+        }
+    }
+
+    // encrypt in place, then rename "n.docx" to "n.docx.x7k".
+    fn overwrite_rename(files: u64, directories: u64) -> Vec<Record> {
+        let mut trace = Trace::new();
+        for f in 0..files {
+            let (file, parent, name) = (0x100 + f, 0x10 + f % directories, format!("{}.docx", f));
+            trace.push(file, parent, 0x1, &name);
+            trace.push(file, parent, 0x80000001, &name);
+            trace.push(file, parent, 0x1000, &name);
+            trace.push(file, parent, 0x80002000, &format!("{}.x7k", name));
+        }
+        trace.records
+    }
+
+    // write an encrypted copy next to the original, then delete the original.
+    fn replace_delete(files: u64, directories: u64) -> Vec<Record> {
+        let mut trace = Trace::new();
+        for f in 0..files {
+            let (parent, name) = (0x10 + f % directories, format!("{}.pdf", f));
+            trace.push(0x1000 + f, parent, 0x80000102, &format!("{}.locked", name));
+            trace.push(0x100 + f, parent, 0x80000200, &name);
+        }
+        trace.records
+    }
+
+    #[test]
+    fn it_should_detect_overwrite_and_rename() {
+        let alerts = RansomwareDetector::new(Default::default()).scan(overwrite_rename(30, 5));
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].files, 20);
+        assert_eq!(alerts[0].directories, 5);
+        assert!(alerts[0].patterns.contains(&Pattern::OverwriteRename));
+        assert_eq!(alerts[0].evidence.len(), 60);
+    }
+
+    #[test]
+    fn it_should_detect_replace_and_delete() {
+        let config = DetectorConfig {
+            extensions: vec![],
+            ..Default::default()
+        };
+        let alerts = RansomwareDetector::new(config).scan(replace_delete(40, 4));
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(
+            alerts[0].patterns.iter().collect::<Vec<_>>(),
+            vec![&Pattern::ReplaceDelete]
+        );
+    }
+
+    #[test]
+    fn it_should_expire_extreme_timestamps() {
+        let mut records = overwrite_rename(30, 5);
+        records.iter_mut().for_each(|r| r.timestamp = i64::MIN);
+        let alerts = RansomwareDetector::new(Default::default()).scan(records);
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].first_timestamp, i64::MIN);
+    }
+
+    #[test]
+    fn it_should_flag_known_extensions() {
+        let alerts = RansomwareDetector::new(Default::default()).scan(replace_delete(20, 4));
+
+        assert!(alerts[0].patterns.contains(&Pattern::SuspiciousExtension));
+    }
+
+    #[test]
+    fn it_should_ignore_slow_or_narrow_activity() {
+        let config = DetectorConfig {
+            window: 2 * SECOND,
+            ..Default::default()
+        };
+        let mut narrow = overwrite_rename(40, 1);
+
+        assert!(RansomwareDetector::new(config)
+            .scan(overwrite_rename(40, 5))
+            .is_empty());
+        narrow.extend(replace_delete(1, 1));
+        assert!(RansomwareDetector::new(Default::default())
+            .scan(narrow)
+            .is_empty());
+    }
+
+    #[test]
+    fn it_should_ignore_saves_that_keep_the_extension() {
+        let mut trace = Trace::new();
+        for f in 0..50 {
+            let (file, parent, name) = (0x100 + f, 0x10 + f % 5, format!("{}.docx", f));
+            trace.push(file, parent, 0x80000001, &name);
+            trace.push(file, parent, 0x1000, &name);
+            trace.push(file, parent, 0x80002000, &format!("~{}", name));
+        }
+
+        assert!(RansomwareDetector::new(Default::default())
+            .scan(trace.records)
+            .is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+use crate::raw::parser::PAGE_SIZE;
+use crate::raw::usn_journal_wrapper::RawRecords;
+use crate::usn_record::{Extent, Record};
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordVersion {
+    #[default]
+    V2,
+    V3,
+    V4,
+}
+
+// Byte exact USN_RECORD_V2/V3/V4. fields a version does not have are left out, e.g. the name
+// and timestamp of a V4 record, or the extents of a V2 record.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordBuilder {
+    version: RecordVersion,
+    usn: i64,
+    timestamp: i64,
+    file_reference_number: u128,
+    parent_file_reference_number: u128,
+    reason: u32,
+    source_info: u32,
+    security_id: u32,
+    file_attributes: u32,
+    file_name: String,
+    extents: Vec<Extent>,
+    remaining_extents: u32,
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(size_of::<u64>()) * size_of::<u64>()
+}
+
+impl RecordBuilder {
+    pub fn new(version: RecordVersion) -> Self {
+        Self {
+            version,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_usn(&mut self, usn: i64) -> &mut Self {
+        self.usn = usn;
+        self
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: i64) -> &mut Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn set_file_reference_number(&mut self, file: u128) -> &mut Self {
+        self.file_reference_number = file;
+        self
+    }
+
+    pub fn set_parent_file_reference_number(&mut self, parent: u128) -> &mut Self {
+        self.parent_file_reference_number = parent;
+        self
+    }
+
+    pub fn set_reason(&mut self, reason: u32) -> &mut Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn set_source_info(&mut self, source_info: u32) -> &mut Self {
+        self.source_info = source_info;
+        self
+    }
+
+    pub fn set_security_id(&mut self, security_id: u32) -> &mut Self {
+        self.security_id = security_id;
+        self
+    }
+
+    pub fn set_file_attributes(&mut self, file_attributes: u32) -> &mut Self {
+        self.file_attributes = file_attributes;
+        self
+    }
+
+    pub fn set_file_name(&mut self, file_name: &str) -> &mut Self {
+        self.file_name = file_name.to_string();
+        self
+    }
+
+    pub fn add_extent(&mut self, offset: i64, length: i64) -> &mut Self {
+        self.extents.push(Extent { offset, length });
+        self
+    }
+
+    // extents of the same change that follow in further records.
+    pub fn set_remaining_extents(&mut self, remaining: u32) -> &mut Self {
+        self.remaining_extents = remaining;
+        self
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let name: Vec<u8> = self
+            .file_name
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        if name.len() > u16::MAX as usize {
+            return Err(anyhow!("file name of {} bytes is too long.", name.len()));
+        }
+        if self.extents.len() > u16::MAX as usize {
+            return Err(anyhow!("{} extents are too many.", self.extents.len()));
+        }
+
+        let mut bytes = vec![];
+        let (major_version, name_offset) = match self.version {
+            RecordVersion::V2 => (2u16, 60u16),
+            RecordVersion::V3 => (3, 76),
+            RecordVersion::V4 => (4, 0),
+        };
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&major_version.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        match self.version {
+            RecordVersion::V2 => {
+                bytes.extend_from_slice(&(self.file_reference_number as u64).to_le_bytes());
+                bytes.extend_from_slice(&(self.parent_file_reference_number as u64).to_le_bytes());
+            }
+            RecordVersion::V3 | RecordVersion::V4 => {
+                bytes.extend_from_slice(&self.file_reference_number.to_le_bytes());
+                bytes.extend_from_slice(&self.parent_file_reference_number.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&self.usn.to_le_bytes());
+
+        if self.version == RecordVersion::V4 {
+            bytes.extend_from_slice(&self.reason.to_le_bytes());
+            bytes.extend_from_slice(&self.source_info.to_le_bytes());
+            bytes.extend_from_slice(&self.remaining_extents.to_le_bytes());
+            bytes.extend_from_slice(&(self.extents.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&(2 * size_of::<i64>() as u16).to_le_bytes());
+            for extent in &self.extents {
+                bytes.extend_from_slice(&extent.offset.to_le_bytes());
+                bytes.extend_from_slice(&extent.length.to_le_bytes());
+            }
+        } else {
+            bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+            bytes.extend_from_slice(&self.reason.to_le_bytes());
+            bytes.extend_from_slice(&self.source_info.to_le_bytes());
+            bytes.extend_from_slice(&self.security_id.to_le_bytes());
+            bytes.extend_from_slice(&self.file_attributes.to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&name_offset.to_le_bytes());
+            bytes.extend_from_slice(&name);
+        }
+
+        bytes.resize(padded(bytes.len()), 0);
+        let record_len = bytes.len() as u32;
+        bytes[..4].copy_from_slice(&record_len.to_le_bytes());
+        Ok(bytes)
+    }
+}
+
+impl From<&Record> for RecordBuilder {
+    fn from(record: &Record) -> Self {
+        Self {
+            version: match record.major_version {
+                3 => RecordVersion::V3,
+                4 => RecordVersion::V4,
+                _ => RecordVersion::V2,
+            },
+            usn: record.usn,
+            timestamp: record.timestamp,
+            file_reference_number: record.file_reference_number.0 as u128,
+            parent_file_reference_number: record.parent_file_reference_number.0 as u128,
+            reason: record.reason.0,
+            source_info: record.source_info,
+            security_id: record.security_id,
+            file_attributes: record.file_attributes.0,
+            file_name: record.file_name.clone(),
+            extents: record.extents.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// Lays records out the way NTFS writes `$J`: each at the next 8 byte aligned usn, never
+// crossing a 4 KiB page.
+#[derive(Clone, Debug, Default)]
+pub struct JournalWriter {
+    first_usn: i64,
+    next_usn: i64,
+    records: Vec<(i64, Vec<u8>)>,
+}
+
+impl JournalWriter {
+    pub fn new(first_usn: i64) -> Self {
+        let first_usn = padded(first_usn.max(0) as usize) as i64;
+        Self {
+            first_usn,
+            next_usn: first_usn,
+            records: vec![],
+        }
+    }
+
+    pub fn first_usn(&self) -> i64 {
+        self.first_usn
+    }
+
+    pub fn next_usn(&self) -> i64 {
+        self.next_usn
+    }
+
+    // the usn of the builder is replaced with the one the record is written at.
+    pub fn push(&mut self, record: &RecordBuilder) -> Result<i64> {
+        let page = PAGE_SIZE as i64;
+        let mut usn = self.next_usn;
+        let len = record.build()?.len() as i64;
+        if len > page {
+            return Err(anyhow!("record of {} bytes does not fit in a page.", len));
+        }
+        if usn % page + len > page {
+            usn = (usn / page + 1) * page;
+        }
+
+        let bytes = record.clone().set_usn(usn).build()?;
+        self.next_usn = usn + len;
+        self.records.push((usn, bytes));
+        Ok(usn)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = (i64, &[u8])> {
+        self.records
+            .iter()
+            .map(|(usn, bytes)| (*usn, bytes.as_slice()))
+    }
+
+    // FSCTL_READ_USN_JOURNAL output: the next usn, then the records back to back.
+    pub fn read_buffer(&self) -> Vec<u8> {
+        let mut buffer = self.next_usn.to_le_bytes().to_vec();
+        for (_, bytes) in &self.records {
+            buffer.extend_from_slice(bytes);
+        }
+        buffer
+    }
+
+    pub fn raw_records<const N: usize>(&self) -> Result<RawRecords<N>> {
+        let buffer = self.read_buffer();
+        if buffer.len() > N {
+            return Err(anyhow!(
+                "{} bytes of records do not fit in {} bytes.",
+                buffer.len(),
+                N
+            ));
+        }
+
+        let mut raw_ptr = Box::new([0u8; N]);
+        raw_ptr[..buffer.len()].copy_from_slice(&buffer);
+        Ok(RawRecords {
+            raw_ptr,
+            len: buffer.len() as u32,
+        })
+    }
+
+    // a `$J` stream, where the usn of a record is its offset. everything before the first
+    // usn is zero, like the sparse start of an extracted `$J`.
+    pub fn stream(&self) -> Vec<u8> {
+        let mut stream = vec![0u8; self.next_usn as usize];
+        for (usn, bytes) in &self.records {
+            let at = *usn as usize;
+            stream[at..at + bytes.len()].copy_from_slice(bytes);
+        }
+        stream
+    }
+
+    pub fn write_stream<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.stream())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flags::{FileAttributes, Reason};
+    use crate::raw::builder::{JournalWriter, RecordBuilder, RecordVersion};
+    use crate::raw::file::UsnJournalFile;
+    use crate::raw::parser::{Parser, PAGE_SIZE};
+    use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+    use crate::usn_record::Record;
+    use proptest::prelude::*;
+    use std::io::Cursor;
+
+    // captured from a live volume.
+    const CAPTURED: [u8; 144] = [
+        144, 0, 0, 0, 2, 0, 0, 0, 76, 119, 0, 0, 0, 0, 4, 0, 195, 162, 3, 0, 0, 0, 2, 0, 0, 0, 128,
+        144, 0, 0, 0, 0, 10, 27, 185, 192, 46, 86, 216, 1, 3, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 0,
+        32, 0, 0, 0, 80, 0, 60, 0, 51, 0, 51, 0, 48, 0, 66, 0, 67, 0, 50, 0, 51, 0, 53, 0, 68, 0,
+        66, 0, 55, 0, 65, 0, 55, 0, 56, 0, 56, 0, 50, 0, 52, 0, 52, 0, 67, 0, 57, 0, 68, 0, 67, 0,
+        66, 0, 65, 0, 52, 0, 68, 0, 50, 0, 56, 0, 68, 0, 65, 0, 51, 0, 57, 0, 70, 0, 53, 0, 56, 0,
+        66, 0, 52, 0, 48, 0, 56, 0, 53, 0, 0, 0, 0, 0,
+    ];
+
+    fn captured() -> RecordBuilder {
+        let mut builder = RecordBuilder::new(RecordVersion::V2);
+        builder
+            .set_usn(2424307712)
+            .set_timestamp(132950947809270538)
+            .set_file_reference_number(0x0004_0000_0000_774c)
+            .set_parent_file_reference_number(0x0002_0000_0003_a2c3)
+            .set_reason(0x80000003)
+            .set_file_attributes(0x20)
+            .set_file_name("330BC235DB7A788244C9DCBA4D28DA39F58B4085");
+        builder
+    }
+
+    #[test]
+    fn it_should_encode_a_captured_record() {
+        assert_eq!(captured().build().unwrap(), CAPTURED);
+    }
+
+    #[test]
+    fn it_should_round_trip_v2_and_v3() {
+        for version in [RecordVersion::V2, RecordVersion::V3] {
+            let mut builder = captured();
+            builder.version = version;
+            builder.set_file_name("a.txt");
+            let bytes = builder.build().unwrap();
+            let record = Record::from(bytes.as_slice());
+
+            assert_eq!(bytes.len() % 8, 0);
+            assert_eq!(RecordBuilder::from(&record), builder);
+        }
+        assert_eq!(
+            RecordBuilder::new(RecordVersion::V3).build().unwrap().len(),
+            80
+        );
+    }
+
+    fn any_record() -> impl Strategy<Value = Record> {
+        (
+            (
+                any::<i64>(),
+                any::<i64>(),
+                prop_oneof![Just(2u16), Just(3u16)],
+            ),
+            (any::<u64>(), any::<u64>(), any::<u32>(), any::<u32>()),
+            (any::<u32>(), any::<u32>(), ".{0,255}"),
+        )
+            .prop_map(
+                |(
+                    (usn, timestamp, major_version),
+                    (file, parent, reason, source_info),
+                    (security_id, attributes, file_name),
+                )| Record {
+                    usn,
+                    timestamp,
+                    major_version,
+                    file_reference_number: file.into(),
+                    parent_file_reference_number: parent.into(),
+                    reason: Reason(reason),
+                    source_info,
+                    security_id,
+                    file_attributes: FileAttributes(attributes),
+                    file_name,
+                    ..Default::default()
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn it_should_parse_what_it_builds(record in any_record()) {
+            let bytes = RecordBuilder::from(&record).build().unwrap();
+
+            prop_assert_eq!(bytes.len() % 8, 0);
+            prop_assert_eq!(Record::from(bytes.as_slice()), record);
+        }
+    }
+
+    #[test]
+    fn it_should_encode_v4_extents() {
+        let bytes = RecordBuilder::new(RecordVersion::V4)
+            .set_usn(4096)
+            .set_reason(0x1)
+            .add_extent(0x10000, 0x1000)
+            .add_extent(0x40000, 0x2000)
+            .build()
+            .unwrap();
+
+        assert_eq!(bytes.len(), 96);
+        assert_eq!(bytes[..8], [96, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(bytes[60..64], [2, 0, 16, 0]);
+        assert_eq!(bytes[80..88], 0x40000i64.to_le_bytes());
+        assert_eq!(Record::from(bytes.as_slice()).record_length, Some(96));
+    }
+
+    #[test]
+    fn it_should_write_read_buffers_and_streams() {
+        let mut writer = JournalWriter::new(2 * PAGE_SIZE as i64);
+        let mut record = captured();
+        record.set_file_name("a.txt");
+        let usns: Vec<i64> = (0..60).map(|_| writer.push(&record).unwrap()).collect();
+
+        assert_eq!(usns[1] - usns[0], 72);
+        assert_eq!(usns[56], 3 * PAGE_SIZE as i64);
+        assert_eq!(writer.next_usn(), usns[59] + 72);
+
+        let raw = writer.raw_records::<8192>().unwrap();
+        assert_eq!(raw.raw_ptr[..8], writer.next_usn().to_le_bytes());
+        assert_eq!(raw.split().len(), 60);
+        assert!(writer.raw_records::<1024>().is_err());
+
+        let file = UsnJournalFile::new(Cursor::new(writer.stream())).unwrap();
+        let raw = unsafe { file.raw_read::<65536>(0, 0, 2).unwrap() };
+        let records: Vec<Record> = raw.split().iter().map(|r| (*r).into()).collect();
+        assert_eq!(file.first_usn, 2 * PAGE_SIZE as i64);
+        assert_eq!(records.iter().map(|r| r.usn).collect::<Vec<_>>(), usns);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::raw::parser::{record_header, stale_records, PAGE_SIZE};
+use crate::raw::usn_journal_wrapper::RawUsnJournalData;
+use crate::raw::usn_journal_wrapper::{RawRecords, UsnJournalWrapper};
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::Path;
+
+// An offline `$J` stream, where the usn of a record is its offset in the stream.
+pub struct UsnJournalFile<R: Read + Seek> {
+    reader: RefCell<R>,
+    pub first_usn: i64,
+    pub next_usn: i64,
+}
+
+impl UsnJournalFile<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(&path)
+            .map_err(|e| anyhow!("open {} error: {}.", path.as_ref().display(), e))?;
+        Self::new(file)
+    }
+}
+
+impl<R: Read + Seek> UsnJournalFile<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let next_usn = reader.seek(SeekFrom::End(0))? as i64;
+        let first_usn = Self::first_used_page(&mut reader, next_usn)?;
+        Ok(Self {
+            reader: RefCell::new(reader),
+            first_usn,
+            next_usn,
+        })
+    }
+
+    // the start of $J is sparse, and extracted copies hold it as zeros.
+    fn first_used_page(reader: &mut R, len: i64) -> Result<i64> {
+        let mut chunk = vec![0u8; 256 * PAGE_SIZE];
+        let mut offset = 0;
+        reader.seek(SeekFrom::Start(0))?;
+        while offset < len {
+            let read = read_full(reader, &mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            if let Some(first) = chunk[..read].iter().position(|b| *b != 0) {
+                return Ok(offset + (first - first % PAGE_SIZE) as i64);
+            }
+            offset += read as i64;
+        }
+        Ok(len)
+    }
+
+    fn fill(&self, start: i64, output: &mut [u8]) -> Result<(usize, i64)> {
+        let page = PAGE_SIZE as i64;
+        let start = start + (-start).rem_euclid(size_of::<u64>() as i64);
+        let end = ((start + output.len() as i64) / page * page).min(self.next_usn);
+        let mut chunk = vec![0u8; (end - start).max(0) as usize];
+        {
+            let mut reader = self.reader.borrow_mut();
+            reader.seek(SeekFrom::Start(start as u64))?;
+            let read = read_full(&mut *reader, &mut chunk)?;
+            chunk.truncate(read);
+        }
+
+        let mut written = 0;
+        let mut pos = 0;
+        while pos + size_of::<u64>() <= chunk.len() {
+            let usn = start + pos as i64;
+            let page_end = ((usn / page + 1) * page - start) as usize;
+            let candidate = &chunk[pos..page_end.min(chunk.len())];
+            let Some((record_len, record_usn)) = record_header(candidate) else {
+                pos += size_of::<u64>();
+                continue;
+            };
+            // a valid record at the wrong offset is stale.
+            if record_usn == usn {
+                output[written..written + record_len].copy_from_slice(&candidate[..record_len]);
+                written += record_len;
+            }
+            pos += record_len;
+        }
+
+        Ok((written, start + chunk.len() as i64))
+    }
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+impl<R: Read + Seek> UsnJournalWrapper for UsnJournalFile<R> {
+    // a file is a read only source.
+    unsafe fn raw_create(&self) {}
+
+    unsafe fn raw_query<D: RawUsnJournalData + Default>(&self) -> Result<D> {
+        Ok(D::from_range(0, self.first_usn, self.next_usn))
+    }
+
+    // copies the live records of whole pages, like FSCTL_READ_USN_JOURNAL does.
+    // the file holds whatever the volume wrote, V4 records are dropped when parsing.
+    unsafe fn raw_read<const N: usize>(
+        &self,
+        start_usn: i64,
+        _: u64,
+        _: u16,
+    ) -> Result<RawRecords<N>> {
+        let header = size_of::<i64>();
+        if N < header + PAGE_SIZE {
+            return Err(anyhow!("buffer of {} bytes is smaller than a page.", N));
+        }
+
+        let mut output = Box::new([0u8; N]);
+        let mut start = start_usn.max(self.first_usn);
+        // zero pages in the middle of the stream must not look like the end of it.
+        let (written, next_usn) = loop {
+            let (written, next_usn) = self.fill(start, &mut output[header..])?;
+            if written > 0 || next_usn >= self.next_usn || next_usn == start {
+                break (written, next_usn);
+            }
+            start = next_usn;
+        };
+
+        output[..header].copy_from_slice(&next_usn.to_le_bytes());
+        Ok(RawRecords {
+            raw_ptr: output,
+            len: (header + written) as u32,
+        })
+    }
+
+    // whole pages, so the slack after the last live record of the first one is scanned too.
+    unsafe fn raw_read_slack(&self, start_usn: i64, next_usn: i64) -> Result<Vec<Vec<u8>>> {
+        let page = PAGE_SIZE as i64;
+        let start = start_usn.max(self.first_usn) / page * page;
+        let end = ((next_usn + page - 1) / page * page).min(self.next_usn);
+        let mut pages = vec![0u8; (end - start).max(0) as usize];
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(start as u64))?;
+        let read = read_full(&mut *reader, &mut pages)?;
+        pages.truncate(read);
+        Ok(stale_records(&pages, start)
+            .into_iter()
+            .map(|r| r.to_vec())
+            .collect())
+    }
+
+    unsafe fn raw_enum<const N: usize>(&self, _: u64) -> Result<RawRecords<N>> {
+        Err(anyhow!("a usn journal file has no mft to enumerate."))
+    }
+
+    unsafe fn raw_delete(&self) {}
+
+    unsafe fn raw_track_ranges(&self, _: u64, _: i64) -> Result<()> {
+        Err(anyhow!("a usn journal file is read only."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raw::builder::{JournalWriter, RecordBuilder, RecordVersion};
+    use crate::raw::file::UsnJournalFile;
+    use crate::raw::parser::{Parser, PAGE_SIZE};
+    use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+    use crate::reader::{Reader, RecordFetcher};
+    use crate::usn_journal_data::UsnJournalDataFactory;
+    use crate::usn_record::Record;
+    use std::io::Cursor;
+
+    fn put_record(buf: &mut [u8], usn: i64) {
+        let at = usn as usize;
+        buf[at..at + 4].copy_from_slice(&64u32.to_le_bytes());
+        buf[at + 4..at + 6].copy_from_slice(&2u16.to_le_bytes());
+        buf[at + 24..at + 32].copy_from_slice(&usn.to_le_bytes());
+        buf[at + 32..at + 40].copy_from_slice(&132989000930000000i64.to_le_bytes());
+        buf[at + 56..at + 58].copy_from_slice(&4u16.to_le_bytes());
+        buf[at + 58..at + 60].copy_from_slice(&60u16.to_le_bytes());
+        buf[at + 60..at + 64].copy_from_slice(&[97, 0, 98, 0]);
+    }
+
+    fn journal() -> Vec<u8> {
+        let mut buf = vec![0u8; 4 * PAGE_SIZE];
+        put_record(&mut buf, 2 * PAGE_SIZE as i64);
+        put_record(&mut buf, 2 * PAGE_SIZE as i64 + 64);
+        put_record(&mut buf, 3 * PAGE_SIZE as i64);
+        buf
+    }
+
+    #[test]
+    fn it_should_skip_leading_zero_pages() {
+        let file = UsnJournalFile::new(Cursor::new(journal())).unwrap();
+        let data = UsnJournalDataFactory::new(&file).query().unwrap();
+
+        assert_eq!(file.first_usn, 2 * PAGE_SIZE as i64);
+        assert_eq!(data.data.first_usn, 2 * PAGE_SIZE as i64);
+        assert_eq!(data.data.next_usn, 4 * PAGE_SIZE as i64);
+    }
+
+    #[test]
+    fn it_should_read_live_records() {
+        let file = UsnJournalFile::new(Cursor::new(journal())).unwrap();
+        let raw = unsafe { file.raw_read::<65536>(0, 0, 2).unwrap() };
+        let records: Vec<Record> = raw.split().iter().map(|r| (*r).into()).collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].usn, 3 * PAGE_SIZE as i64);
+        assert_eq!(raw.raw_ptr[..8], (4 * PAGE_SIZE as i64).to_le_bytes());
+    }
+
+    #[test]
+    fn it_should_drop_stale_records() {
+        let mut buf = journal();
+        put_record(&mut buf, 3 * PAGE_SIZE as i64 + 64);
+        buf[3 * PAGE_SIZE + 64 + 24] = 1;
+        let file = UsnJournalFile::new(Cursor::new(buf)).unwrap();
+        let raw = unsafe { file.raw_read::<65536>(3 * PAGE_SIZE as i64, 0, 2).unwrap() };
+
+        assert_eq!(raw.split().len(), 1);
+    }
+
+    #[test]
+    fn it_should_read_past_zero_pages() {
+        let mut buf = journal();
+        buf.extend_from_slice(&[0u8; 2 * PAGE_SIZE]);
+        put_record(&mut buf, 5 * PAGE_SIZE as i64);
+        let file = UsnJournalFile::new(Cursor::new(buf)).unwrap();
+        let raw = unsafe {
+            file.raw_read::<{ PAGE_SIZE + 8 }>(4 * PAGE_SIZE as i64, 0, 2)
+                .unwrap()
+        };
+
+        assert_eq!(raw.split().len(), 1);
+    }
+
+    #[test]
+    fn it_should_read_in_page_sized_steps() {
+        let file = UsnJournalFile::new(Cursor::new(journal())).unwrap();
+        let raw = unsafe { file.raw_read::<{ PAGE_SIZE + 8 }>(0, 0, 2).unwrap() };
+        let next = i64::from_le_bytes(raw.raw_ptr[..8].try_into().unwrap());
+
+        assert_eq!(raw.split().len(), 2);
+        assert_eq!(next, 3 * PAGE_SIZE as i64);
+    }
+
+    // a V2, a V3 and a V4 record, as a volume with range tracking on writes them.
+    fn versions() -> UsnJournalFile<Cursor<Vec<u8>>> {
+        let mut writer = JournalWriter::new(PAGE_SIZE as i64);
+        let mut builder = RecordBuilder::new(RecordVersion::V2);
+        builder
+            .set_timestamp(132989000930000000)
+            .set_file_reference_number(0x20)
+            .set_file_name("a.txt");
+        writer.push(&builder).unwrap();
+        let mut builder = RecordBuilder::new(RecordVersion::V3);
+        builder
+            .set_timestamp(132989000930000000)
+            .set_file_reference_number(0x21)
+            .set_file_name("b.txt");
+        writer.push(&builder).unwrap();
+        let mut builder = RecordBuilder::new(RecordVersion::V4);
+        builder
+            .set_file_reference_number(0x21)
+            .set_reason(0x1)
+            .add_extent(0x10000, 0x1000)
+            .add_extent(0x40000, 0x2000);
+        writer.push(&builder).unwrap();
+        UsnJournalFile::new(Cursor::new(writer.stream())).unwrap()
+    }
+
+    #[test]
+    fn it_should_read_v3_and_v4_records() {
+        let file = versions();
+        let mut reader = Reader::new(&file);
+        reader.set_max_major_version(4);
+        let records = reader.do_fetch().unwrap();
+
+        assert_eq!(
+            records.iter().map(|r| r.major_version).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(records[1].file_name, "b.txt");
+        assert_eq!(records[2].file_reference_number, 0x21.into());
+        assert_eq!(records[2].extents.len(), 2);
+        assert_eq!(records[2].extents[1].offset, 0x40000);
+    }
+
+    #[test]
+    fn it_should_drop_v4_records_unless_asked() {
+        let file = versions();
+        let records = Reader::new(&file).do_fetch().unwrap();
+
+        assert_eq!(
+            records.iter().map(|r| r.major_version).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+#![allow(non_camel_case_types, non_snake_case)]
+// Layouts and constants of winioctl.h, so the parsers build on every platform.
+// the live journal in `raw::windows` passes these straight to DeviceIoControl.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_RECORD_V2 {
+    pub RecordLength: u32,
+    pub MajorVersion: u16,
+    pub MinorVersion: u16,
+    pub FileReferenceNumber: u64,
+    pub ParentFileReferenceNumber: u64,
+    pub Usn: i64,
+    pub TimeStamp: i64,
+    pub Reason: u32,
+    pub SourceInfo: u32,
+    pub SecurityId: u32,
+    pub FileAttributes: u32,
+    pub FileNameLength: u16,
+    pub FileNameOffset: u16,
+    pub FileName: [u16; 1],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct FILE_ID_128 {
+    pub Identifier: [u8; 16],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_RECORD_V3 {
+    pub RecordLength: u32,
+    pub MajorVersion: u16,
+    pub MinorVersion: u16,
+    pub FileReferenceNumber: FILE_ID_128,
+    pub ParentFileReferenceNumber: FILE_ID_128,
+    pub Usn: i64,
+    pub TimeStamp: i64,
+    pub Reason: u32,
+    pub SourceInfo: u32,
+    pub SecurityId: u32,
+    pub FileAttributes: u32,
+    pub FileNameLength: u16,
+    pub FileNameOffset: u16,
+    pub FileName: [u16; 1],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_RECORD_COMMON_HEADER {
+    pub RecordLength: u32,
+    pub MajorVersion: u16,
+    pub MinorVersion: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_RECORD_EXTENT {
+    pub Offset: i64,
+    pub Length: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_RECORD_V4 {
+    pub Header: USN_RECORD_COMMON_HEADER,
+    pub FileReferenceNumber: FILE_ID_128,
+    pub ParentFileReferenceNumber: FILE_ID_128,
+    pub Usn: i64,
+    pub Reason: u32,
+    pub SourceInfo: u32,
+    pub RemainingExtents: u32,
+    pub NumberOfExtents: u16,
+    pub ExtentSize: u16,
+    pub Extents: [USN_RECORD_EXTENT; 1],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_JOURNAL_DATA_V0 {
+    pub UsnJournalID: u64,
+    pub FirstUsn: i64,
+    pub NextUsn: i64,
+    pub LowestValidUsn: i64,
+    pub MaxUsn: i64,
+    pub MaximumSize: u64,
+    pub AllocationDelta: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_JOURNAL_DATA_V1 {
+    pub UsnJournalID: u64,
+    pub FirstUsn: i64,
+    pub NextUsn: i64,
+    pub LowestValidUsn: i64,
+    pub MaxUsn: i64,
+    pub MaximumSize: u64,
+    pub AllocationDelta: u64,
+    pub MinSupportedMajorVersion: u16,
+    pub MaxSupportedMajorVersion: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_JOURNAL_DATA_V2 {
+    pub UsnJournalID: u64,
+    pub FirstUsn: i64,
+    pub NextUsn: i64,
+    pub LowestValidUsn: i64,
+    pub MaxUsn: i64,
+    pub MaximumSize: u64,
+    pub AllocationDelta: u64,
+    pub MinSupportedMajorVersion: u16,
+    pub MaxSupportedMajorVersion: u16,
+    pub Flags: u32,
+    pub RangeTrackChunkSize: u64,
+    pub RangeTrackFileSizeThreshold: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct READ_USN_JOURNAL_DATA_V0 {
+    pub StartUsn: i64,
+    pub ReasonMask: u32,
+    pub ReturnOnlyOnClose: u32,
+    pub Timeout: u64,
+    pub BytesToWaitFor: u64,
+    pub UsnJournalID: u64,
+}
+
+// a V0 read only returns V2 records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct READ_USN_JOURNAL_DATA_V1 {
+    pub StartUsn: i64,
+    pub ReasonMask: u32,
+    pub ReturnOnlyOnClose: u32,
+    pub Timeout: u64,
+    pub BytesToWaitFor: u64,
+    pub UsnJournalID: u64,
+    pub MinMajorVersion: u16,
+    pub MaxMajorVersion: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct USN_TRACK_MODIFIED_RANGES {
+    pub Flags: u32,
+    pub Unused: u32,
+    pub ChunkSize: u64,
+    pub FileSizeThreshold: i64,
+}
+
+pub const FLAG_USN_TRACK_MODIFIED_RANGES_ENABLE: u32 = 0x00000001;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct MFT_ENUM_DATA_V0 {
+    pub StartFileReferenceNumber: u64,
+    pub LowUsn: i64,
+    pub HighUsn: i64,
+}
+
+pub const USN_REASON_DATA_OVERWRITE: u32 = 0x00000001;
+pub const USN_REASON_DATA_EXTEND: u32 = 0x00000002;
+pub const USN_REASON_DATA_TRUNCATION: u32 = 0x00000004;
+pub const USN_REASON_NAMED_DATA_OVERWRITE: u32 = 0x00000010;
+pub const USN_REASON_NAMED_DATA_EXTEND: u32 = 0x00000020;
+pub const USN_REASON_NAMED_DATA_TRUNCATION: u32 = 0x00000040;
+pub const USN_REASON_FILE_CREATE: u32 = 0x00000100;
+pub const USN_REASON_FILE_DELETE: u32 = 0x00000200;
+pub const USN_REASON_EA_CHANGE: u32 = 0x00000400;
+pub const USN_REASON_SECURITY_CHANGE: u32 = 0x00000800;
+pub const USN_REASON_RENAME_OLD_NAME: u32 = 0x00001000;
+pub const USN_REASON_RENAME_NEW_NAME: u32 = 0x00002000;
+pub const USN_REASON_INDEXABLE_CHANGE: u32 = 0x00004000;
+pub const USN_REASON_BASIC_INFO_CHANGE: u32 = 0x00008000;
+pub const USN_REASON_HARD_LINK_CHANGE: u32 = 0x00010000;
+pub const USN_REASON_COMPRESSION_CHANGE: u32 = 0x00020000;
+pub const USN_REASON_ENCRYPTION_CHANGE: u32 = 0x00040000;
+pub const USN_REASON_OBJECT_ID_CHANGE: u32 = 0x00080000;
+pub const USN_REASON_REPARSE_POINT_CHANGE: u32 = 0x00100000;
+pub const USN_REASON_STREAM_CHANGE: u32 = 0x00200000;
+pub const USN_REASON_TRANSACTED_CHANGE: u32 = 0x00400000;
+pub const USN_REASON_INTEGRITY_CHANGE: u32 = 0x00800000;
+pub const USN_REASON_DESIRED_STORAGE_CLASS_CHANGE: u32 = 0x01000000;
+pub const USN_REASON_CLOSE: u32 = 0x80000000;
+
+pub const FILE_ATTRIBUTE_READONLY: u32 = 0x00000001;
+pub const FILE_ATTRIBUTE_HIDDEN: u32 = 0x00000002;
+pub const FILE_ATTRIBUTE_SYSTEM: u32 = 0x00000004;
+pub const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x00000010;
+pub const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x00000020;
+pub const FILE_ATTRIBUTE_DEVICE: u32 = 0x00000040;
+pub const FILE_ATTRIBUTE_NORMAL: u32 = 0x00000080;
+pub const FILE_ATTRIBUTE_TEMPORARY: u32 = 0x00000100;
+pub const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x00000200;
+pub const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x00000400;
+pub const FILE_ATTRIBUTE_COMPRESSED: u32 = 0x00000800;
+pub const FILE_ATTRIBUTE_OFFLINE: u32 = 0x00001000;
+pub const FILE_ATTRIBUTE_NOT_CONTENT_INDEXED: u32 = 0x00002000;
+pub const FILE_ATTRIBUTE_ENCRYPTED: u32 = 0x00004000;
+pub const FILE_ATTRIBUTE_INTEGRITY_STREAM: u32 = 0x00008000;
+pub const FILE_ATTRIBUTE_VIRTUAL: u32 = 0x00010000;
+pub const FILE_ATTRIBUTE_NO_SCRUB_DATA: u32 = 0x00020000;
+pub const FILE_ATTRIBUTE_EA: u32 = 0x00040000;
+pub const FILE_ATTRIBUTE_PINNED: u32 = 0x00080000;
+pub const FILE_ATTRIBUTE_UNPINNED: u32 = 0x00100000;
+pub const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x00400000;
+
+// GetLastError codes the journal calls fail with.
+pub const ERROR_ACCESS_DENIED: u32 = 5;
+pub const ERROR_HANDLE_EOF: u32 = 38;
+pub const ERROR_INVALID_PARAMETER: u32 = 87;
+pub const ERROR_JOURNAL_DELETE_IN_PROGRESS: u32 = 1178;
+pub const ERROR_JOURNAL_NOT_ACTIVE: u32 = 1179;
+pub const ERROR_JOURNAL_ENTRY_DELETED: u32 = 1181;
+
+#[cfg(test)]
+mod tests {
+    use crate::raw::ioctl::{
+        READ_USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V2,
+        USN_RECORD_V2, USN_RECORD_V3, USN_RECORD_V4, USN_TRACK_MODIFIED_RANGES,
+    };
+    use std::mem::size_of;
+
+    #[test]
+    fn it_should_match_the_windows_layouts() {
+        assert_eq!(size_of::<USN_RECORD_V2>(), 64);
+        assert_eq!(size_of::<USN_RECORD_V3>(), 80);
+        assert_eq!(size_of::<USN_RECORD_V4>(), 80);
+        assert_eq!(size_of::<USN_JOURNAL_DATA_V0>(), 56);
+        assert_eq!(size_of::<USN_JOURNAL_DATA_V1>(), 64);
+        assert_eq!(size_of::<USN_JOURNAL_DATA_V2>(), 80);
+        assert_eq!(size_of::<READ_USN_JOURNAL_DATA_V1>(), 48);
+        assert_eq!(size_of::<USN_TRACK_MODIFIED_RANGES>(), 24);
+    }
+}
@@ -1,4 +1,10 @@
+pub mod builder;
+pub mod file;
+pub mod ioctl;
+pub mod ntfs;
 pub mod parser;
 pub mod usn_journal_wrapper;
+#[cfg(windows)]
 pub mod volume_handle;
+#[cfg(windows)]
 pub mod windows;
@@ -0,0 +1,815 @@
+use crate::raw::ioctl::FILE_ATTRIBUTE_DIRECTORY;
+use anyhow::{anyhow, Result};
+use std::io::{Read, Seek, SeekFrom};
+
+const SECTOR_SIZE: u64 = 512;
+const ATTRIBUTE_END: u32 = 0xFFFF_FFFF;
+pub const ATTRIBUTE_STANDARD_INFORMATION: u32 = 0x10;
+pub const ATTRIBUTE_FILE_NAME: u32 = 0x30;
+pub const ATTRIBUTE_DATA: u32 = 0x80;
+// MFT entry of `$Extend`, the parent of `$UsnJrnl`.
+const EXTEND_ENTRY: u64 = 11;
+// the namespace of a short name kept beside a long one.
+const NAMESPACE_DOS: u8 = 2;
+const MAX_CLUSTER_SIZE: u64 = 2 * 1024 * 1024;
+const MAX_MFT_RECORD_SIZE: u64 = 64 * 1024;
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn utf16_at(bytes: &[u8], at: usize, chars: usize) -> String {
+    let units: Vec<u16> = bytes[at..at + chars * 2]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataRun {
+    pub vcn: u64,
+    // `None` for a sparse run.
+    pub lcn: Option<u64>,
+    pub length: u64,
+}
+
+pub fn decode_runs(bytes: &[u8], starting_vcn: u64) -> Result<Vec<DataRun>> {
+    let invalid = || anyhow!("invalid data run.");
+    let mut runs = Vec::new();
+    let mut pos = 0;
+    let mut vcn = starting_vcn;
+    let mut lcn: i64 = 0;
+    while pos < bytes.len() && bytes[pos] != 0 {
+        let length_size = (bytes[pos] & 0x0F) as usize;
+        let offset_size = (bytes[pos] >> 4) as usize;
+        pos += 1;
+        if length_size == 0 || length_size > 8 || offset_size > 8 {
+            return Err(invalid());
+        }
+        let field = bytes
+            .get(pos..pos + length_size + offset_size)
+            .ok_or_else(invalid)?;
+
+        let mut length = [0u8; 8];
+        length[..length_size].copy_from_slice(&field[..length_size]);
+        let length = u64::from_le_bytes(length);
+
+        let run_lcn = match offset_size {
+            0 => None,
+            _ => {
+                let offset = &field[length_size..];
+                let fill = if offset[offset_size - 1] & 0x80 != 0 {
+                    0xFF
+                } else {
+                    0
+                };
+                let mut delta = [fill; 8];
+                delta[..offset_size].copy_from_slice(offset);
+                lcn = lcn
+                    .checked_add(i64::from_le_bytes(delta))
+                    .ok_or_else(invalid)?;
+                Some(u64::try_from(lcn).map_err(|_| invalid())?)
+            }
+        };
+
+        runs.push(DataRun {
+            vcn,
+            lcn: run_lcn,
+            length,
+        });
+        vcn = vcn.checked_add(length).ok_or_else(invalid)?;
+        pos += length_size + offset_size;
+    }
+
+    Ok(runs)
+}
+
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    pub type_code: u32,
+    pub name: String,
+    // the value of a resident attribute, empty otherwise.
+    pub value: Vec<u8>,
+    pub runs: Vec<DataRun>,
+    pub starting_vcn: u64,
+    pub size: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    pub created: i64,
+    pub modified: i64,
+    pub mft_modified: i64,
+    pub accessed: i64,
+}
+
+impl FileTimes {
+    fn at(bytes: &[u8], at: usize) -> Self {
+        Self {
+            created: u64_at(bytes, at) as i64,
+            modified: u64_at(bytes, at + 8) as i64,
+            mft_modified: u64_at(bytes, at + 16) as i64,
+            accessed: u64_at(bytes, at + 24) as i64,
+        }
+    }
+}
+
+pub struct MftRecord {
+    pub entry: u64,
+    bytes: Vec<u8>,
+}
+
+impl MftRecord {
+    pub fn parse(entry: u64, mut bytes: Vec<u8>) -> Result<Self> {
+        if bytes.len() < 0x30 || &bytes[0..4] != b"FILE" {
+            return Err(anyhow!("mft entry {} has no FILE signature.", entry));
+        }
+
+        // the last two bytes of every sector were swapped out for the sequence number.
+        let usa_offset = u16_at(&bytes, 4) as usize;
+        let usa_count = u16_at(&bytes, 6) as usize;
+        if usa_offset + usa_count.max(1) * 2 > bytes.len() {
+            return Err(anyhow!("mft entry {} has an invalid fixup array.", entry));
+        }
+        let check = u16_at(&bytes, usa_offset);
+        for i in 1..usa_count {
+            let at = i * SECTOR_SIZE as usize - 2;
+            if at + 2 > bytes.len() || u16_at(&bytes, at) != check {
+                return Err(anyhow!("mft entry {} is torn.", entry));
+            }
+            let fixup = usa_offset + i * 2;
+            bytes.copy_within(fixup..fixup + 2, at);
+        }
+
+        Ok(Self { entry, bytes })
+    }
+
+    pub fn in_use(&self) -> bool {
+        u16_at(&self.bytes, 0x16) & 0x1 != 0
+    }
+
+    pub fn is_directory(&self) -> bool {
+        u16_at(&self.bytes, 0x16) & 0x2 != 0
+    }
+
+    pub fn sequence(&self) -> u16 {
+        u16_at(&self.bytes, 0x10)
+    }
+
+    // zero for a base record.
+    pub fn base_reference(&self) -> u64 {
+        u64_at(&self.bytes, 0x20)
+    }
+
+    pub fn attributes(&self) -> Result<Vec<Attribute>> {
+        let invalid = || anyhow!("mft entry {} has an invalid attribute.", self.entry);
+        let used = (u32_at(&self.bytes, 0x18) as usize).min(self.bytes.len());
+        let mut pos = u16_at(&self.bytes, 0x14) as usize;
+        let mut attributes = Vec::new();
+        while pos + 8 <= used {
+            let type_code = u32_at(&self.bytes, pos);
+            if type_code == ATTRIBUTE_END {
+                break;
+            }
+            let length = u32_at(&self.bytes, pos + 4) as usize;
+            if length < 0x18 || pos + length > used {
+                return Err(invalid());
+            }
+            let bytes = &self.bytes[pos..pos + length];
+            let name_length = bytes[9] as usize;
+            let name_offset = u16_at(bytes, 0x0A) as usize;
+            if name_offset + name_length * 2 > length {
+                return Err(invalid());
+            }
+            let name = utf16_at(bytes, name_offset, name_length);
+
+            let attribute = match bytes[8] {
+                0 => {
+                    let value_length = u32_at(bytes, 0x10) as usize;
+                    let value_offset = u16_at(bytes, 0x14) as usize;
+                    let value = bytes
+                        .get(value_offset..value_offset + value_length)
+                        .ok_or_else(invalid)?;
+                    Attribute {
+                        type_code,
+                        name,
+                        value: value.to_vec(),
+                        runs: vec![],
+                        starting_vcn: 0,
+                        size: value_length as u64,
+                    }
+                }
+                _ => {
+                    if length < 0x40 {
+                        return Err(invalid());
+                    }
+                    let starting_vcn = u64_at(bytes, 0x10);
+                    let runs_offset = u16_at(bytes, 0x20) as usize;
+                    let runs = bytes.get(runs_offset..).ok_or_else(invalid)?;
+                    Attribute {
+                        type_code,
+                        name,
+                        value: vec![],
+                        runs: decode_runs(runs, starting_vcn)?,
+                        starting_vcn,
+                        size: u64_at(bytes, 0x30),
+                    }
+                }
+            };
+            attributes.push(attribute);
+            pos += length;
+        }
+
+        Ok(attributes)
+    }
+
+    // the times shown by the shell, and changed by SetFileTime.
+    pub fn standard_information(&self) -> Result<Option<FileTimes>> {
+        Ok(self
+            .attributes()?
+            .iter()
+            .find(|a| a.type_code == ATTRIBUTE_STANDARD_INFORMATION && a.value.len() >= 0x20)
+            .map(|a| FileTimes::at(&a.value, 0)))
+    }
+
+    // the times of every $FILE_NAME attribute, only ever set by the file system.
+    pub fn file_name_times(&self) -> Result<Vec<FileTimes>> {
+        Ok(self
+            .attributes()?
+            .iter()
+            .filter(|a| a.type_code == ATTRIBUTE_FILE_NAME && a.value.len() >= 0x42)
+            .map(|a| FileTimes::at(&a.value, 8))
+            .collect())
+    }
+
+    // the windows file attributes, with FILE_ATTRIBUTE_DIRECTORY added for directories.
+    pub fn file_attributes(&self) -> Result<Option<u32>> {
+        let directory = match self.is_directory() {
+            true => FILE_ATTRIBUTE_DIRECTORY,
+            false => 0,
+        };
+        Ok(self
+            .attributes()?
+            .iter()
+            .find(|a| a.type_code == ATTRIBUTE_STANDARD_INFORMATION && a.value.len() >= 0x24)
+            .map(|a| u32_at(&a.value, 0x20) | directory))
+    }
+
+    // the file reference the journal uses for this entry.
+    pub fn file_reference(&self) -> u64 {
+        self.entry | (self.sequence() as u64) << 48
+    }
+
+    // (parent reference, name) of every $FILE_NAME attribute but the 8.3 alias of a long name.
+    pub fn file_names(&self) -> Result<Vec<(u64, String)>> {
+        Ok(self
+            .attributes()?
+            .iter()
+            .filter(|a| a.type_code == ATTRIBUTE_FILE_NAME && a.value.len() >= 0x42)
+            .filter(|a| a.value[0x41] != NAMESPACE_DOS)
+            .filter_map(|a| {
+                let chars = a.value[0x40] as usize;
+                (a.value.len() >= 0x42 + chars * 2)
+                    .then(|| (u64_at(&a.value, 0), utf16_at(&a.value, 0x42, chars)))
+            })
+            .collect())
+    }
+}
+
+pub struct NtfsVolume<R: Read + Seek> {
+    reader: R,
+    offset: u64,
+    // of the whole image, the $MFT can't be any larger.
+    length: u64,
+    cluster_size: u64,
+    mft_record_size: u64,
+    mft: Attribute,
+}
+
+impl<R: Read + Seek> NtfsVolume<R> {
+    // accepts a bare volume, or a disk with an MBR or GPT partition table.
+    pub fn open(mut reader: R) -> Result<Self> {
+        let offset = Self::find_volume(&mut reader)?;
+        let mut boot = [0u8; SECTOR_SIZE as usize];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut boot)?;
+
+        let invalid = || anyhow!("invalid ntfs boot sector.");
+        let bytes_per_sector = u16_at(&boot, 0x0B) as u64;
+        let cluster_size = bytes_per_sector * boot[0x0D] as u64;
+        let mft_lcn = u64_at(&boot, 0x30);
+        let mft_record_size = match boot[0x40] as i8 {
+            n if n < 0 => 1u64.checked_shl(n.unsigned_abs() as u32).unwrap_or(0),
+            n => n as u64 * cluster_size,
+        };
+        // ntfs clusters go up to 2 MiB, file records never past 64 KiB.
+        if cluster_size == 0
+            || cluster_size > MAX_CLUSTER_SIZE
+            || !(SECTOR_SIZE..=MAX_MFT_RECORD_SIZE).contains(&mft_record_size)
+        {
+            return Err(invalid());
+        }
+
+        let mft_offset = mft_lcn
+            .checked_mul(cluster_size)
+            .and_then(|at| at.checked_add(offset))
+            .ok_or_else(invalid)?;
+        let mut bytes = vec![0u8; mft_record_size as usize];
+        reader.seek(SeekFrom::Start(mft_offset))?;
+        reader.read_exact(&mut bytes)?;
+        let mft = MftRecord::parse(0, bytes)?
+            .attributes()?
+            .into_iter()
+            .find(|a| a.type_code == ATTRIBUTE_DATA && a.name.is_empty())
+            .ok_or_else(|| anyhow!("$MFT has no data attribute."))?;
+
+        let length = reader.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            reader,
+            offset,
+            length,
+            cluster_size,
+            mft_record_size,
+            mft,
+        })
+    }
+
+    fn is_ntfs(reader: &mut R, offset: u64) -> Result<bool> {
+        let mut oem = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset.saturating_add(3)))?;
+        Ok(reader.read_exact(&mut oem).is_ok() && &oem == b"NTFS    ")
+    }
+
+    fn find_volume(reader: &mut R) -> Result<u64> {
+        if Self::is_ntfs(reader, 0)? {
+            return Ok(0);
+        }
+
+        let mut mbr = [0u8; SECTOR_SIZE as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut mbr)?;
+        if mbr[510..512] != [0x55, 0xAA] {
+            return Err(anyhow!("no ntfs volume found."));
+        }
+
+        let mut candidates = vec![];
+        for i in 0..4 {
+            let entry = &mbr[446 + i * 16..446 + (i + 1) * 16];
+            match entry[4] {
+                0 => {}
+                0xEE => candidates.extend(Self::gpt_partitions(reader)?),
+                _ => candidates.push(u32_at(entry, 8) as u64 * SECTOR_SIZE),
+            }
+        }
+
+        for offset in candidates {
+            if Self::is_ntfs(reader, offset)? {
+                return Ok(offset);
+            }
+        }
+        Err(anyhow!("no ntfs volume found."))
+    }
+
+    fn gpt_partitions(reader: &mut R) -> Result<Vec<u64>> {
+        let mut header = [0u8; SECTOR_SIZE as usize];
+        reader.seek(SeekFrom::Start(SECTOR_SIZE))?;
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != b"EFI PART" {
+            return Ok(vec![]);
+        }
+
+        let entries_lba = u64_at(&header, 72);
+        let count = u32_at(&header, 80).min(128) as usize;
+        let size = u32_at(&header, 84) as usize;
+        if !(48..=SECTOR_SIZE as usize).contains(&size) {
+            return Ok(vec![]);
+        }
+        let Some(entries_offset) = entries_lba.checked_mul(SECTOR_SIZE) else {
+            return Ok(vec![]);
+        };
+        let mut entries = vec![0u8; count * size];
+        reader.seek(SeekFrom::Start(entries_offset))?;
+        reader.read_exact(&mut entries)?;
+
+        Ok(entries
+            .chunks_exact(size)
+            .filter(|e| e[0..16].iter().any(|b| *b != 0))
+            .filter_map(|e| u64_at(e, 32).checked_mul(SECTOR_SIZE))
+            .collect())
+    }
+
+    pub fn entry_count(&self) -> u64 {
+        self.mft.size.min(self.length.saturating_sub(self.offset)) / self.mft_record_size
+    }
+
+    pub fn read_mft_record(&mut self, entry: u64) -> Result<MftRecord> {
+        let mut bytes = vec![0u8; self.mft_record_size as usize];
+        let position = entry
+            .checked_mul(self.mft_record_size)
+            .ok_or_else(|| anyhow!("mft entry {} is out of range.", entry))?;
+        read_runs(
+            &mut self.reader,
+            self.offset,
+            self.cluster_size,
+            &self.mft.runs,
+            position,
+            &mut bytes,
+        )?;
+        MftRecord::parse(entry, bytes)
+    }
+
+    // finds `$Extend\$UsnJrnl` and opens its `$J` stream.
+    pub fn open_usn_journal(mut self) -> Result<RunStream<R>> {
+        let mut base = None;
+        let mut fragments: Vec<(u64, Attribute)> = vec![];
+        for entry in 0..self.entry_count() {
+            let record = match self.read_mft_record(entry) {
+                Ok(record) if record.in_use() => record,
+                _ => continue,
+            };
+            // a corrupt record can't be read for the journal either, skip it like a torn one.
+            let (Ok(names), Ok(attributes)) = (record.file_names(), record.attributes()) else {
+                continue;
+            };
+            let owner = match record.base_reference() & 0x0000_FFFF_FFFF_FFFF {
+                0 => entry,
+                base => base,
+            };
+            if base.is_none()
+                && names.iter().any(|(parent, name)| {
+                    parent & 0x0000_FFFF_FFFF_FFFF == EXTEND_ENTRY && name == "$UsnJrnl"
+                })
+            {
+                base = Some(entry);
+            }
+            for attribute in attributes {
+                if attribute.type_code == ATTRIBUTE_DATA && attribute.name == "$J" {
+                    fragments.push((owner, attribute));
+                }
+            }
+
+            // all runs found once they add up to the allocated clusters.
+            if let Some(base) = base {
+                let mut own: Vec<&Attribute> = fragments
+                    .iter()
+                    .filter(|(owner, _)| *owner == base)
+                    .map(|(_, a)| a)
+                    .collect();
+                own.sort_by_key(|a| a.starting_vcn);
+                let clusters = own
+                    .iter()
+                    .flat_map(|a| &a.runs)
+                    .fold(0u64, |sum, r| sum.saturating_add(r.length));
+                let size = own.first().map(|a| a.size).unwrap_or(u64::MAX);
+                if clusters.saturating_mul(self.cluster_size) >= size {
+                    break;
+                }
+            }
+        }
+
+        let base = base.ok_or_else(|| anyhow!("$UsnJrnl not found."))?;
+        let mut own: Vec<Attribute> = fragments
+            .into_iter()
+            .filter(|(owner, _)| *owner == base)
+            .map(|(_, a)| a)
+            .collect();
+        own.sort_by_key(|a| a.starting_vcn);
+        let size = own
+            .first()
+            .filter(|a| a.starting_vcn == 0)
+            .map(|a| a.size)
+            .ok_or_else(|| anyhow!("$UsnJrnl has no $J stream."))?;
+
+        Ok(RunStream {
+            reader: self.reader,
+            offset: self.offset,
+            cluster_size: self.cluster_size,
+            runs: own.into_iter().flat_map(|a| a.runs).collect(),
+            size,
+            position: 0,
+        })
+    }
+}
+
+fn read_runs<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    cluster_size: u64,
+    runs: &[DataRun],
+    mut position: u64,
+    buf: &mut [u8],
+) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let unmapped = || anyhow!("offset {} is not mapped.", position);
+        let vcn = position / cluster_size;
+        let run = runs
+            .iter()
+            .find(|r| r.vcn <= vcn && vcn - r.vcn < r.length)
+            .ok_or_else(unmapped)?;
+        // runs come from disk, so any of these can overflow.
+        let run_start = run.vcn.checked_mul(cluster_size).ok_or_else(unmapped)?;
+        let run_end = run
+            .length
+            .checked_mul(cluster_size)
+            .and_then(|len| len.checked_add(run_start))
+            .unwrap_or(u64::MAX);
+        let len = (run_end - position).min((buf.len() - done) as u64) as usize;
+        match run.lcn {
+            None => buf[done..done + len].fill(0),
+            Some(lcn) => {
+                let at = lcn
+                    .checked_mul(cluster_size)
+                    .and_then(|at| at.checked_add(offset))
+                    .and_then(|at| at.checked_add(position - run_start))
+                    .ok_or_else(unmapped)?;
+                reader.seek(SeekFrom::Start(at))?;
+                reader.read_exact(&mut buf[done..done + len])?;
+            }
+        }
+        done += len;
+        position += len as u64;
+    }
+    Ok(())
+}
+
+// A non-resident stream read through its data runs, sparse runs read as zeros.
+pub struct RunStream<R: Read + Seek> {
+    reader: R,
+    offset: u64,
+    cluster_size: u64,
+    runs: Vec<DataRun>,
+    size: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> Read for RunStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = (self.size.saturating_sub(self.position) as usize).min(buf.len());
+        read_runs(
+            &mut self.reader,
+            self.offset,
+            self.cluster_size,
+            &self.runs,
+            self.position,
+            &mut buf[..len],
+        )
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for RunStream<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.size.checked_add_signed(d),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start")
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::raw::ntfs::{
+        decode_runs, DataRun, MftRecord, NtfsVolume, ATTRIBUTE_DATA, ATTRIBUTE_FILE_NAME,
+        ATTRIBUTE_STANDARD_INFORMATION,
+    };
+    use std::io::{Cursor, Read};
+
+    const CLUSTER: usize = 512;
+    const MFT_LCN: usize = 16;
+    const RECORD: usize = 1024;
+
+    fn utf16(name: &str) -> Vec<u8> {
+        name.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    fn resident(type_code: u32, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; (0x18 + value.len()).next_multiple_of(8)];
+        let length = bytes.len() as u32;
+        bytes[0..4].copy_from_slice(&type_code.to_le_bytes());
+        bytes[4..8].copy_from_slice(&length.to_le_bytes());
+        bytes[0x0A..0x0C].copy_from_slice(&0x18u16.to_le_bytes());
+        bytes[0x10..0x14].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes[0x14..0x16].copy_from_slice(&0x18u16.to_le_bytes());
+        bytes[0x18..0x18 + value.len()].copy_from_slice(value);
+        bytes
+    }
+
+    fn non_resident(type_code: u32, name: &str, runs: &[u8], size: u64) -> Vec<u8> {
+        let name = utf16(name);
+        let runs_offset = (0x40 + name.len()).next_multiple_of(8);
+        let mut bytes = vec![0u8; (runs_offset + runs.len()).next_multiple_of(8)];
+        let length = bytes.len() as u32;
+        bytes[0..4].copy_from_slice(&type_code.to_le_bytes());
+        bytes[4..8].copy_from_slice(&length.to_le_bytes());
+        bytes[8] = 1;
+        bytes[9] = (name.len() / 2) as u8;
+        bytes[0x0A..0x0C].copy_from_slice(&0x40u16.to_le_bytes());
+        bytes[0x20..0x22].copy_from_slice(&(runs_offset as u16).to_le_bytes());
+        bytes[0x30..0x38].copy_from_slice(&size.to_le_bytes());
+        bytes[0x40..0x40 + name.len()].copy_from_slice(&name);
+        bytes[runs_offset..runs_offset + runs.len()].copy_from_slice(runs);
+        bytes
+    }
+
+    // all four times of the attribute set to `created`.
+    pub(crate) fn standard_information(created: i64, file_attributes: u32) -> Vec<u8> {
+        let mut value = vec![0u8; 0x48];
+        for i in 0..4 {
+            value[i * 8..i * 8 + 8].copy_from_slice(&created.to_le_bytes());
+        }
+        value[0x20..0x24].copy_from_slice(&file_attributes.to_le_bytes());
+        resident(ATTRIBUTE_STANDARD_INFORMATION, &value)
+    }
+
+    pub(crate) fn file_name(parent: u64, name: &str, created: i64) -> Vec<u8> {
+        let mut value = vec![0u8; 0x42];
+        value[0..8].copy_from_slice(&parent.to_le_bytes());
+        for i in 1..5 {
+            value[i * 8..i * 8 + 8].copy_from_slice(&created.to_le_bytes());
+        }
+        value[0x40] = name.encode_utf16().count() as u8;
+        value[0x41] = 1;
+        value.extend(utf16(name));
+        resident(ATTRIBUTE_FILE_NAME, &value)
+    }
+
+    // an entry without fixups, `flags` 0x1 for in use and 0x2 for a directory.
+    pub(crate) fn entry(sequence: u16, flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0u8; RECORD];
+        bytes[0..4].copy_from_slice(b"FILE");
+        bytes[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+        bytes[0x10..0x12].copy_from_slice(&sequence.to_le_bytes());
+        bytes[0x14..0x16].copy_from_slice(&0x38u16.to_le_bytes());
+        bytes[0x16..0x18].copy_from_slice(&flags.to_le_bytes());
+        bytes[0x1C..0x20].copy_from_slice(&(RECORD as u32).to_le_bytes());
+        let mut pos = 0x38;
+        for attribute in attributes {
+            bytes[pos..pos + attribute.len()].copy_from_slice(attribute);
+            pos += attribute.len();
+        }
+        bytes[pos..pos + 4].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+        bytes[0x18..0x1C].copy_from_slice(&(pos as u32 + 8).to_le_bytes());
+        bytes
+    }
+
+    // a bare volume of 512 byte clusters, with 16 mft entries at cluster 16 and a $J of two sparse
+    // clusters followed by two at cluster 64.
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 80 * CLUSTER];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        image[0x0D] = 1;
+        image[0x30..0x38].copy_from_slice(&(MFT_LCN as u64).to_le_bytes());
+        image[0x40] = 0xF6;
+
+        let mut put = |index: usize, bytes: &[u8]| {
+            let at = MFT_LCN * CLUSTER + index * RECORD;
+            image[at..at + bytes.len()].copy_from_slice(bytes);
+        };
+        put(
+            0,
+            &entry(
+                1,
+                0x1,
+                &[non_resident(
+                    ATTRIBUTE_DATA,
+                    "",
+                    &[0x11, 0x20, 0x10],
+                    16 * 1024,
+                )],
+            ),
+        );
+        put(11, &entry(1, 0x1, &[file_name(5, "$Extend", 0)]));
+        // an attribute shorter than its header.
+        let mut corrupt = entry(1, 0x1, &[file_name(11, "$Corrupt", 0)]);
+        corrupt[0x3C..0x40].copy_from_slice(&0x10u32.to_le_bytes());
+        put(12, &corrupt);
+        put(
+            13,
+            &entry(
+                1,
+                0x1,
+                &[
+                    file_name(11, "$UsnJrnl", 0),
+                    non_resident(ATTRIBUTE_DATA, "$J", &[0x01, 0x02, 0x11, 0x02, 0x40], 2048),
+                ],
+            ),
+        );
+        image[64 * CLUSTER..66 * CLUSTER].fill(0xAB);
+        image
+    }
+
+    #[test]
+    fn it_should_decode_runs() {
+        // 0x18 clusters at 0x5634, sparse 0x10 clusters, 0x08 clusters 0x10 back.
+        let runs = decode_runs(
+            &[0x21, 0x18, 0x34, 0x56, 0x01, 0x10, 0x11, 0x08, 0xF0, 0x00],
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            runs,
+            vec![
+                DataRun {
+                    vcn: 0,
+                    lcn: Some(0x5634),
+                    length: 0x18
+                },
+                DataRun {
+                    vcn: 0x18,
+                    lcn: None,
+                    length: 0x10
+                },
+                DataRun {
+                    vcn: 0x28,
+                    lcn: Some(0x5624),
+                    length: 0x08
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_reject_runs_past_the_last_cluster() {
+        // two sparse runs of u64::MAX clusters, found by fuzzing.
+        let mut bytes = vec![0x08];
+        bytes.extend([0xFF; 8]);
+        bytes.push(0x08);
+        bytes.extend([0xFF; 8]);
+
+        assert!(decode_runs(&bytes, 0).is_err());
+        assert!(decode_runs(&[0x11, 0x01, 0x01], u64::MAX).is_err());
+    }
+
+    #[test]
+    fn it_should_reject_an_invalid_boot_sector() {
+        let mut boot = vec![0u8; 1024];
+        boot[3..11].copy_from_slice(b"NTFS    ");
+        boot[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x0D] = 8;
+        // a file record of 2^109 bytes, found by fuzzing.
+        boot[0x40] = 0x93;
+        assert!(NtfsVolume::open(Cursor::new(boot.clone())).is_err());
+
+        boot[0x40] = 0xF6;
+        boot[0x30..0x38].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(NtfsVolume::open(Cursor::new(boot)).is_err());
+    }
+
+    #[test]
+    fn it_should_apply_fixups() {
+        let mut bytes = vec![0u8; 1024];
+        bytes[0..4].copy_from_slice(b"FILE");
+        bytes[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+        bytes[6..8].copy_from_slice(&3u16.to_le_bytes());
+        bytes[0x30..0x32].copy_from_slice(&[0x07, 0x00]);
+        bytes[0x32..0x34].copy_from_slice(&[0xAA, 0xBB]);
+        bytes[0x34..0x36].copy_from_slice(&[0xCC, 0xDD]);
+        bytes[510..512].copy_from_slice(&[0x07, 0x00]);
+        bytes[1022..1024].copy_from_slice(&[0x07, 0x00]);
+        bytes[0x14..0x16].copy_from_slice(&0x38u16.to_le_bytes());
+        bytes[0x18..0x1C].copy_from_slice(&1024u32.to_le_bytes());
+        bytes[0x38..0x3C].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+
+        let record = MftRecord::parse(0, bytes.clone()).unwrap();
+        bytes[1022] = 0x08;
+
+        assert!(record.attributes().unwrap().is_empty());
+        assert!(MftRecord::parse(0, bytes).is_err());
+    }
+
+    #[test]
+    fn it_should_open_usn_journal_past_corrupt_records() {
+        let mut volume = NtfsVolume::open(Cursor::new(image())).unwrap();
+        assert!(volume.read_mft_record(12).unwrap().attributes().is_err());
+
+        let mut journal = vec![];
+        volume
+            .open_usn_journal()
+            .unwrap()
+            .read_to_end(&mut journal)
+            .unwrap();
+
+        assert_eq!(journal.len(), 2048);
+        assert!(journal[..1024].iter().all(|b| *b == 0));
+        assert!(journal[1024..].iter().all(|b| *b == 0xAB));
+    }
+}
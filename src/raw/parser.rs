@@ -1,31 +1,121 @@
+use crate::raw::ioctl::USN_RECORD_V2;
 use crate::raw::usn_journal_wrapper::RawRecords;
 use std::mem::size_of;
-use windows::Win32::System::Ioctl::USN_RECORD_V2;
+
+// $J is written in pages, a record never crosses a page boundary.
+pub const PAGE_SIZE: usize = 4096;
 
 pub trait Parser {
-    fn parse<R: RawUsnRecord>(self) -> Box<Vec<R>>;
+    fn split(&self) -> Box<Vec<&[u8]>>;
+    fn parse<R: RawUsnRecord>(&self) -> Box<Vec<R>>;
 }
 
 impl<const N: usize> Parser for RawRecords<N> {
-    fn parse<R: RawUsnRecord>(self) -> Box<Vec<R>> {
-        let mut remainder_len = self.len;
-        // TODO: why size of i64?
-        let mut next = unsafe { self.raw_ptr.as_ptr().offset(size_of::<i64>() as isize) };
-        let mut usn_records = Box::new(Vec::new());
-        // TODO: comment why lager then 8
-        while remainder_len > 8 {
-            let usn_record = unsafe { next.cast::<R>().read() };
-            let record_len = usn_record.len();
-            next = unsafe { next.offset(record_len as _) };
-            usn_records.push(usn_record);
-
-            remainder_len -= record_len;
+    fn split(&self) -> Box<Vec<&[u8]>> {
+        // the output buffer starts with the next usn to read.
+        let header = size_of::<i64>();
+        let end = (self.len as usize).min(N);
+        let mut offset = header;
+        let mut slices = Box::new(Vec::new());
+        while offset + size_of::<u32>() <= end {
+            let record_len =
+                u32::from_le_bytes(self.raw_ptr[offset..offset + 4].try_into().unwrap()) as usize;
+            if record_len == 0 {
+                // zero padding up to the next page.
+                offset = header + ((offset - header) / PAGE_SIZE + 1) * PAGE_SIZE;
+                continue;
+            }
+
+            slices.push(&self.raw_ptr[offset..(offset + record_len).min(N)]);
+            offset += record_len;
         }
 
-        usn_records
+        slices
+    }
+
+    fn parse<R: RawUsnRecord>(&self) -> Box<Vec<R>> {
+        Box::new(
+            self.split()
+                .iter()
+                .take_while(|bytes| bytes.len() >= size_of::<R>())
+                .map(|bytes| unsafe { bytes.as_ptr().cast::<R>().read_unaligned() })
+                .collect(),
+        )
     }
 }
 
+// The fixed part of USN_RECORD_V2/V3/V4, and where in it the usn is.
+fn layout(major_version: u16) -> Option<(usize, usize)> {
+    match major_version {
+        2 => Some((60, 24)),
+        3 => Some((76, 40)),
+        4 => Some((64, 40)),
+        _ => None,
+    }
+}
+
+// The length and usn of the record `bytes` starts with, when it holds up as one: a known
+// version, 8 byte aligned, and its name or extents within a length that stays within `bytes`,
+// which end at the page.
+pub fn record_header(bytes: &[u8]) -> Option<(usize, i64)> {
+    if bytes.len() < size_of::<u64>() {
+        return None;
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as usize;
+    let i64_at = |i: usize| i64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    let record_len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    let (major, minor) = (u16_at(4) as u16, u16_at(6));
+    let (fixed, usn_at) = layout(major)?;
+    if minor != 0
+        || record_len < fixed
+        || !record_len.is_multiple_of(size_of::<u64>())
+        || record_len > bytes.len()
+    {
+        return None;
+    }
+
+    let usn = i64_at(usn_at);
+    let fits = match major {
+        4 => {
+            let (count, size) = (u16_at(60), u16_at(62));
+            size >= 2 * size_of::<i64>() && fixed + count * size <= record_len
+        }
+        _ => {
+            // the timestamp comes right after the usn.
+            let (name_len, name_offset) = (u16_at(fixed - 4), u16_at(fixed - 2));
+            i64_at(usn_at + 8) > 0
+                && name_offset == fixed
+                && name_len.is_multiple_of(size_of::<u16>())
+                && name_offset + name_len <= record_len
+        }
+    };
+    (usn >= 0 && fits).then_some((record_len, usn))
+}
+
+// Valid records that are not at the offset their usn says, left behind in the slack of a page
+// when it was reused. `pages` starts at the page boundary `first_usn`, and no record is read
+// across a page.
+pub fn stale_records(pages: &[u8], first_usn: i64) -> Vec<&[u8]> {
+    let mut stale = vec![];
+    for (i, page) in pages.chunks(PAGE_SIZE).enumerate() {
+        let page_usn = first_usn + (i * PAGE_SIZE) as i64;
+        let mut offset = 0;
+        while offset + size_of::<u64>() <= page.len() {
+            let candidate = &page[offset..];
+            let Some((record_len, usn)) = record_header(candidate) else {
+                offset += size_of::<u64>();
+                continue;
+            };
+            if usn != page_usn + offset as i64 {
+                stale.push(&candidate[..record_len]);
+            }
+            offset += record_len;
+        }
+    }
+    stale
+}
+
+#[allow(clippy::len_without_is_empty)]
 pub trait RawUsnRecord {
     fn len(&self) -> u32;
 }
@@ -35,3 +125,100 @@ impl RawUsnRecord for USN_RECORD_V2 {
         self.RecordLength
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::raw::ioctl::USN_RECORD_V2;
+    use crate::raw::parser::{stale_records, Parser, PAGE_SIZE};
+    use crate::raw::usn_journal_wrapper::RawRecords;
+    use crate::usn_record::Record;
+    use proptest::prelude::*;
+
+    fn put_record(buf: &mut [u8], at: usize, usn: i64) {
+        let name = [97u8, 0, 98, 0];
+        let record_len = 64u32;
+        buf[at..at + 64].fill(0);
+        buf[at..at + 4].copy_from_slice(&record_len.to_le_bytes());
+        buf[at + 4..at + 6].copy_from_slice(&2u16.to_le_bytes());
+        buf[at + 24..at + 32].copy_from_slice(&usn.to_le_bytes());
+        buf[at + 32..at + 40].copy_from_slice(&132989000930000000i64.to_le_bytes());
+        buf[at + 56..at + 58].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        buf[at + 58..at + 60].copy_from_slice(&60u16.to_le_bytes());
+        buf[at + 60..at + 64].copy_from_slice(&name);
+    }
+
+    #[test]
+    fn it_should_recover_records_after_live_end() {
+        let mut buf = [0u8; PAGE_SIZE];
+        put_record(&mut buf, 0, 4096);
+        put_record(&mut buf, 64, 128);
+        put_record(&mut buf, 128, 192);
+
+        let recovered = stale_records(&buf, 4096);
+
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(Record::from(recovered[0]).usn, 128);
+        assert_eq!(Record::from(recovered[1]).usn, 192);
+    }
+
+    #[test]
+    fn it_should_skip_live_records() {
+        let mut buf = [0u8; PAGE_SIZE];
+        put_record(&mut buf, 0, 4096);
+        put_record(&mut buf, 64, 4160);
+        put_record(&mut buf, 200, 256);
+
+        let recovered = stale_records(&buf, 4096);
+
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(Record::from(recovered[0]).usn, 256);
+    }
+
+    #[test]
+    fn it_should_not_cross_page_boundary() {
+        let mut buf = [0u8; PAGE_SIZE + 64];
+        put_record(&mut buf, PAGE_SIZE - 32, 512);
+        put_record(&mut buf, PAGE_SIZE, 576);
+
+        let recovered = stale_records(&buf, 0);
+
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(Record::from(recovered[0]).usn, 576);
+    }
+
+    #[test]
+    fn it_should_skip_zero_padding_to_next_page() {
+        let mut buf = [0u8; 8 + PAGE_SIZE + 64];
+        put_record(&mut buf, 8, 0);
+        put_record(&mut buf, 8 + PAGE_SIZE, 4096);
+        let raw = RawRecords {
+            raw_ptr: Box::new(buf),
+            len: (8 + PAGE_SIZE + 64) as u32,
+        };
+
+        let records = raw.parse::<USN_RECORD_V2>();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].Usn, 4096);
+    }
+
+    proptest! {
+        #[test]
+        fn it_should_not_panic_on_any_buffer(
+            body in proptest::collection::vec(any::<u8>(), 0..2 * PAGE_SIZE),
+            len in 0..2 * PAGE_SIZE as u32 + 64,
+        ) {
+            let mut raw_ptr = Box::new([0u8; 2 * PAGE_SIZE]);
+            raw_ptr[..body.len()].copy_from_slice(&body);
+            let raw = RawRecords { raw_ptr, len };
+
+            for bytes in raw.split().iter() {
+                let _ = Record::from(*bytes);
+            }
+            let _ = raw.parse::<USN_RECORD_V2>();
+            for bytes in stale_records(&body, 0) {
+                let _ = Record::from(bytes);
+            }
+        }
+    }
+}
@@ -1,19 +1,133 @@
-use crate::raw::windows::RawUsnJournalData;
-use anyhow::Result;
+use crate::raw::ioctl::{USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V2};
+use crate::usn_journal_data::{Data, DataVer};
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
 
 pub struct RawRecords<const N: usize> {
     pub raw_ptr: Box<[u8; N]>,
     pub len: u32,
 }
 
+// the raw calls hand buffers to the volume driver, callers go through the factories instead.
+#[allow(clippy::missing_safety_doc)]
 pub trait UsnJournalWrapper {
     unsafe fn raw_create(&self);
     unsafe fn raw_query<D: RawUsnJournalData + Default>(&self) -> Result<D>;
+    // V4 records of modified ranges only come with a `max_major_version` of 4.
     unsafe fn raw_read<const N: usize>(
         &self,
         start_usn: i64,
         usn_journal_id: u64,
+        max_major_version: u16,
     ) -> Result<RawRecords<N>>;
-    unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>>;
+    // stale records in the slack of the pages from `start_usn` to `next_usn`. a live volume only
+    // hands out live records, only sources with the pages themselves have any.
+    unsafe fn raw_read_slack(&self, _start_usn: i64, _next_usn: i64) -> Result<Vec<Vec<u8>>> {
+        Err(anyhow!(
+            "slack recovery is not supported on live volumes, read a $J file or an image."
+        ))
+    }
+    // the output starts with the file reference to continue from, like a read.
+    unsafe fn raw_enum<const N: usize>(&self, start_file_reference: u64) -> Result<RawRecords<N>>;
     unsafe fn raw_delete(&self);
+    // once on, range tracking stays on until the journal is deleted.
+    unsafe fn raw_track_ranges(&self, chunk_size: u64, file_size_threshold: i64) -> Result<()>;
+}
+
+// The GetLastError code a raw call failed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Win32Error(pub u32);
+
+impl Display for Win32Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "windows error {}.", self.0)
+    }
+}
+
+impl std::error::Error for Win32Error {}
+
+// also found under any context added on the way up.
+pub fn error_code(error: &anyhow::Error) -> Option<u32> {
+    error.downcast_ref::<Win32Error>().map(|e| e.0)
+}
+
+pub trait RawUsnJournalData {
+    const VER: DataVer;
+
+    fn from_data(data: &Data) -> Self;
+
+    // journal data for a source without a live journal, e.g. a $J file.
+    fn from_range(usn_journal_id: u64, first_usn: i64, next_usn: i64) -> Self
+    where
+        Self: Sized,
+    {
+        Self::from_data(&Data {
+            usn_journal_id,
+            first_usn,
+            next_usn,
+            lowest_valid_usn: first_usn,
+            max_usn: i64::MAX,
+            maximum_size: (next_usn - first_usn) as u64,
+            min_supported_major_version: Some(2),
+            max_supported_major_version: Some(2),
+            ..Default::default()
+        })
+    }
+}
+
+impl RawUsnJournalData for USN_JOURNAL_DATA_V0 {
+    const VER: DataVer = DataVer::V0;
+
+    fn from_data(data: &Data) -> Self {
+        Self {
+            UsnJournalID: data.usn_journal_id,
+            FirstUsn: data.first_usn,
+            NextUsn: data.next_usn,
+            LowestValidUsn: data.lowest_valid_usn,
+            MaxUsn: data.max_usn,
+            MaximumSize: data.maximum_size,
+            AllocationDelta: data.allocation_delta,
+        }
+    }
+}
+
+impl RawUsnJournalData for USN_JOURNAL_DATA_V1 {
+    const VER: DataVer = DataVer::V1;
+
+    fn from_data(data: &Data) -> Self {
+        let v0 = USN_JOURNAL_DATA_V0::from_data(data);
+        Self {
+            UsnJournalID: v0.UsnJournalID,
+            FirstUsn: v0.FirstUsn,
+            NextUsn: v0.NextUsn,
+            LowestValidUsn: v0.LowestValidUsn,
+            MaxUsn: v0.MaxUsn,
+            MaximumSize: v0.MaximumSize,
+            AllocationDelta: v0.AllocationDelta,
+            MinSupportedMajorVersion: data.min_supported_major_version.unwrap_or(2),
+            MaxSupportedMajorVersion: data.max_supported_major_version.unwrap_or(2),
+        }
+    }
+}
+
+impl RawUsnJournalData for USN_JOURNAL_DATA_V2 {
+    const VER: DataVer = DataVer::V2;
+
+    fn from_data(data: &Data) -> Self {
+        let v1 = USN_JOURNAL_DATA_V1::from_data(data);
+        Self {
+            UsnJournalID: v1.UsnJournalID,
+            FirstUsn: v1.FirstUsn,
+            NextUsn: v1.NextUsn,
+            LowestValidUsn: v1.LowestValidUsn,
+            MaxUsn: v1.MaxUsn,
+            MaximumSize: v1.MaximumSize,
+            AllocationDelta: v1.AllocationDelta,
+            MinSupportedMajorVersion: v1.MinSupportedMajorVersion,
+            MaxSupportedMajorVersion: v1.MaxSupportedMajorVersion,
+            Flags: data.flags.unwrap_or(0),
+            RangeTrackChunkSize: data.range_track_chunk_size.unwrap_or(0),
+            RangeTrackFileSizeThreshold: data.range_track_file_size_threshold.unwrap_or(0),
+        }
+    }
 }
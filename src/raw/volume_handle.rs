@@ -1,21 +1,89 @@
+use crate::volume::{VolumeInformation, VolumePath};
 use anyhow::{anyhow, Result};
-use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Foundation::{GetLastError, HANDLE};
 use windows::Win32::Storage::FileSystem::{
-    CreateFileW, FILE_ATTRIBUTE_READONLY, FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_READ,
-    FILE_SHARE_WRITE, OPEN_EXISTING,
+    CreateFileW, GetVolumeInformationW, GetVolumeNameForVolumeMountPointW, FILE_ATTRIBUTE_READONLY,
+    FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
 };
 
+const MAX_PATH: usize = 260;
+
+fn from_wide(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+// `\\?\Volume{GUID}\` of the volume at `root`, or mounted on it.
+fn volume_name(root: &str) -> Result<String> {
+    let mut name = [0u16; 50];
+    match unsafe { GetVolumeNameForVolumeMountPointW(root.to_string(), &mut name).as_bool() } {
+        true => Ok(from_wide(&name)),
+        false => Err(anyhow!(
+            "get volume name {} error: {}.",
+            root,
+            unsafe { GetLastError() }.0
+        )),
+    }
+}
+
 pub struct VolumeHandle {
     pub volume: String,
+    // with a trailing backslash, for the volume management functions.
+    pub root: String,
 }
 
 impl VolumeHandle {
     pub fn new(volume: char) -> Self {
         Self {
             volume: format!(r#"\\.\{}:"#, volume),
+            root: format!(r#"{}:\"#, volume),
         }
     }
 
+    // a mount point is opened through the volume GUID path of the volume mounted on it.
+    pub fn open(path: &VolumePath) -> Result<Self> {
+        let path = match path {
+            VolumePath::MountPoint(root) => VolumePath::parse(&volume_name(root)?)?,
+            _ => path.clone(),
+        };
+        Ok(Self {
+            volume: path
+                .device_path()
+                .ok_or_else(|| anyhow!("volume {} has no device path.", path))?,
+            root: path.root_path(),
+        })
+    }
+
+    pub fn information(&self) -> Result<VolumeInformation> {
+        let mut label = [0u16; MAX_PATH + 1];
+        let mut file_system = [0u16; MAX_PATH + 1];
+        let mut serial_number = 0;
+        let ok = unsafe {
+            GetVolumeInformationW(
+                self.root.to_string(),
+                &mut label,
+                &mut serial_number,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut file_system,
+            )
+        };
+        if !ok.as_bool() {
+            return Err(anyhow!(
+                "get volume information {} error: {}.",
+                self.root,
+                unsafe { GetLastError() }.0
+            ));
+        }
+
+        Ok(VolumeInformation {
+            guid_path: volume_name(&self.root)?,
+            serial_number,
+            file_system: from_wide(&file_system),
+            label: from_wide(&label),
+        })
+    }
+
     pub unsafe fn create(&self) -> Result<HANDLE> {
         CreateFileW(
             self.volume.to_string(),
@@ -33,6 +101,7 @@ impl VolumeHandle {
 #[cfg(test)]
 mod tests {
     use crate::raw::volume_handle::VolumeHandle;
+    use crate::volume::VolumePath;
 
     #[test]
     fn it_should_get_a_error() {
@@ -40,6 +109,12 @@ mod tests {
         assert!(unsafe { h.create().is_err() });
     }
 
+    #[test]
+    fn it_should_not_open_a_folder_without_a_volume() {
+        let path = VolumePath::parse(r"C:\no\such\mount\").unwrap();
+        assert!(VolumeHandle::open(&path).is_err());
+    }
+
     #[test]
     #[ignore]
     fn it_should_return_a_handle() {
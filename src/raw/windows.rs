@@ -1,11 +1,18 @@
-use crate::raw::usn_journal_wrapper::{RawRecords, UsnJournalWrapper};
+use crate::raw::ioctl::{
+    ERROR_HANDLE_EOF, ERROR_INVALID_PARAMETER, FLAG_USN_TRACK_MODIFIED_RANGES_ENABLE,
+    MFT_ENUM_DATA_V0, READ_USN_JOURNAL_DATA_V0, READ_USN_JOURNAL_DATA_V1,
+    USN_TRACK_MODIFIED_RANGES,
+};
+use crate::raw::usn_journal_wrapper::{
+    RawRecords, RawUsnJournalData, UsnJournalWrapper, Win32Error,
+};
 use crate::raw::volume_handle::VolumeHandle;
-use anyhow::{anyhow, Result};
-use std::mem::{size_of_val, transmute};
+use anyhow::Result;
+use std::mem::{size_of, size_of_val, transmute};
 use windows::Win32::Foundation::GetLastError;
 use windows::Win32::System::Ioctl::{
-    FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V0,
-    USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V2,
+    FSCTL_ENUM_USN_DATA, FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL,
+    FSCTL_USN_TRACK_MODIFIED_RANGES,
 };
 use windows::Win32::System::IO::DeviceIoControl;
 
@@ -40,7 +47,11 @@ impl<'a> UsnJournalWrapper for WindowsUsnJournal<'a> {
         )
         .as_bool()
         {
-            return Err(anyhow!(GetLastError().0));
+            return Err(Win32Error(GetLastError().0).into());
+        }
+        // an older volume fills in only the structure it knows.
+        if (ret_bytes as usize) < size_of_val(&result) {
+            return Err(Win32Error(ERROR_INVALID_PARAMETER).into());
         }
 
         Ok(result)
@@ -50,23 +61,31 @@ impl<'a> UsnJournalWrapper for WindowsUsnJournal<'a> {
         &self,
         start_usn: i64,
         usn_journal_id: u64,
+        max_major_version: u16,
     ) -> Result<RawRecords<N>, anyhow::Error> {
         let mut output = Box::new([0u8; N]);
         let mut ret_bytes = 0;
-        let input = READ_USN_JOURNAL_DATA_V0 {
+        let input = READ_USN_JOURNAL_DATA_V1 {
             StartUsn: start_usn,
             ReasonMask: u32::MAX,
             ReturnOnlyOnClose: 0,
             Timeout: 0,
             BytesToWaitFor: 0,
             UsnJournalID: usn_journal_id,
+            MinMajorVersion: 2,
+            MaxMajorVersion: max_major_version.max(2),
+        };
+        // V1 starts with V0, which is all windows 7 knows.
+        let input_len = match max_major_version {
+            0..=2 => size_of::<READ_USN_JOURNAL_DATA_V0>(),
+            _ => size_of_val(&input),
         };
 
         match DeviceIoControl(
             self.handle.create()?,
             FSCTL_READ_USN_JOURNAL,
             transmute(&input),
-            size_of_val(&input) as _,
+            input_len as _,
             transmute(output.as_mut_ptr()),
             output.len() as _,
             &mut ret_bytes,
@@ -78,24 +97,78 @@ impl<'a> UsnJournalWrapper for WindowsUsnJournal<'a> {
                 raw_ptr: output,
                 len: ret_bytes,
             }),
-            false => Ok(RawRecords {
-                raw_ptr: output,
-                len: 0,
-            }),
+            // e.g. ERROR_JOURNAL_ENTRY_DELETED once the journal wrapped past `start_usn`.
+            false => Err(Win32Error(GetLastError().0).into()),
         }
     }
 
-    unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>> {
-        todo!()
+    unsafe fn raw_enum<const N: usize>(&self, start_file_reference: u64) -> Result<RawRecords<N>> {
+        let mut output = Box::new([0u8; N]);
+        let mut ret_bytes = 0;
+        let input = MFT_ENUM_DATA_V0 {
+            StartFileReferenceNumber: start_file_reference,
+            LowUsn: 0,
+            HighUsn: i64::MAX,
+        };
+
+        if !DeviceIoControl(
+            self.handle.create()?,
+            FSCTL_ENUM_USN_DATA,
+            &input as *const _ as _,
+            size_of_val(&input) as _,
+            output.as_mut_ptr() as _,
+            output.len() as _,
+            &mut ret_bytes,
+            std::ptr::null_mut(),
+        )
+        .as_bool()
+        {
+            // past the last file.
+            return match GetLastError().0 {
+                ERROR_HANDLE_EOF => Ok(RawRecords {
+                    raw_ptr: output,
+                    len: 0,
+                }),
+                code => Err(Win32Error(code).into()),
+            };
+        }
+
+        Ok(RawRecords {
+            raw_ptr: output,
+            len: ret_bytes,
+        })
     }
 
     unsafe fn raw_delete(&self) {
         todo!()
     }
-}
 
-pub trait RawUsnJournalData {}
+    unsafe fn raw_track_ranges(&self, chunk_size: u64, file_size_threshold: i64) -> Result<()> {
+        let input = USN_TRACK_MODIFIED_RANGES {
+            Flags: FLAG_USN_TRACK_MODIFIED_RANGES_ENABLE,
+            Unused: 0,
+            ChunkSize: chunk_size,
+            FileSizeThreshold: file_size_threshold,
+        };
+        // USN_RANGE_TRACK_OUTPUT, the usn tracking starts at.
+        let mut output = 0i64;
+        let mut ret_bytes = 0;
 
-impl RawUsnJournalData for USN_JOURNAL_DATA_V0 {}
-impl RawUsnJournalData for USN_JOURNAL_DATA_V1 {}
-impl RawUsnJournalData for USN_JOURNAL_DATA_V2 {}
+        if !DeviceIoControl(
+            self.handle.create()?,
+            FSCTL_USN_TRACK_MODIFIED_RANGES,
+            &input as *const _ as _,
+            size_of_val(&input) as _,
+            &mut output as *mut _ as _,
+            size_of_val(&output) as _,
+            &mut ret_bytes,
+            std::ptr::null_mut(),
+        )
+        .as_bool()
+        {
+            return Err(Win32Error(GetLastError().0).into());
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,29 @@
+use crate::checkpoint::Checkpoint;
+use crate::filter::Filter;
+use crate::raw::parser::PAGE_SIZE;
 use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
 use crate::usn_journal_data::UsnJournalDataFactory;
 use crate::usn_journal_record::UsnRecordFactory;
 use crate::usn_record::{Record, Records};
 use anyhow::Result;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 
 pub trait RecordFetcher {
     fn do_fetch(&self) -> Result<Box<Vec<Record>>>;
 }
 
-struct Reader<'a, U: UsnJournalWrapper> {
+pub struct Reader<'a, U: UsnJournalWrapper> {
     pub usn_journal: &'a U,
+    pub recover_slack: bool,
+    pub filter: Option<Filter>,
+    pub max_major_version: u16,
+    next_usn: Cell<i64>,
+    usn_journal_id: Cell<u64>,
+    // kept for the journal data structure it found, queried again for every block.
+    data_factory: UsnJournalDataFactory<'a, U>,
+    // usns of the records recovered so far, as pages are scanned again by later blocks.
+    recovered: RefCell<HashSet<i64>>,
 }
 
 impl<'a, U> Reader<'a, U>
@@ -17,80 +31,203 @@ where
     U: UsnJournalWrapper,
 {
     pub fn new(usn_journal: &'a U) -> Self {
-        Self { usn_journal }
+        Self {
+            usn_journal,
+            recover_slack: false,
+            filter: None,
+            max_major_version: 2,
+            next_usn: Cell::new(0),
+            usn_journal_id: Cell::new(0),
+            data_factory: UsnJournalDataFactory::new(usn_journal),
+            recovered: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub fn set_start_usn(&mut self, usn: i64) -> &Self {
+        self.next_usn.set(usn);
+        self
+    }
+
+    // reads fail on live volumes, which don't hand out the pages.
+    pub fn set_recover_slack(&mut self, recover: bool) -> &Self {
+        self.recover_slack = recover;
+        self
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) -> &Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    // 4 reads the V4 records of modified ranges too, see `ranges`.
+    pub fn set_max_major_version(&mut self, version: u16) -> &Self {
+        self.max_major_version = version;
+        self
+    }
+
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) -> &Self {
+        self.next_usn.set(checkpoint.next_usn);
+        self.usn_journal_id.set(checkpoint.usn_journal_id);
+        self
     }
 
-    pub fn read(&self) -> Result<Records<Self>> {
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            usn_journal_id: self.usn_journal_id.get(),
+            next_usn: self.next_usn.get(),
+        }
+    }
+
+    pub fn read(&self) -> Result<Records<'_, Self>> {
         let records = Records {
             content: self.do_fetch()?,
             fetcher: self,
         };
         Ok(records)
     }
+
+    // moves to the first record at or after `filetime` and returns its usn, assuming timestamps
+    // grow with the usn. in a `$J` file the usn is the offset, so this only reads a few pages.
+    pub fn seek_to_time(&mut self, filetime: i64) -> Result<i64> {
+        let data = self.data_factory.query()?.data;
+        self.usn_journal_id.set(data.usn_journal_id);
+
+        // records never cross a page, so a page start is always a record boundary.
+        let page = PAGE_SIZE as i64;
+        let (mut low, mut high) = (data.first_usn / page, (data.next_usn + page - 1) / page);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.read_at((mid * page).max(data.first_usn))?.0.first() {
+                Some(r) if r.timestamp < filetime => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+
+        // matching records may start part way into the page before.
+        let mut usn = ((low - 1) * page).max(data.first_usn);
+        let found = loop {
+            let (records, next_usn) = self.read_at(usn)?;
+            if let Some(r) = records.iter().find(|r| r.timestamp >= filetime) {
+                break r.usn;
+            }
+            if records.is_empty() || next_usn <= usn {
+                break data.next_usn;
+            }
+            usn = next_usn;
+        };
+
+        self.next_usn.set(found);
+        Ok(found)
+    }
+
+    fn read_at(&self, usn: i64) -> Result<(Vec<Record>, i64)> {
+        let mut record_factory = UsnRecordFactory::new(self.usn_journal);
+        record_factory.set_usn_journal_id(self.usn_journal_id.get());
+        record_factory.set_start_usn(usn);
+        let raw_records = record_factory.read::<65535>()?;
+        let next_usn = raw_records.next_usn.unwrap_or(usn);
+        Ok((*raw_records.parse(), next_usn))
+    }
+
+    fn fetch_block(&self) -> Result<Vec<Record>> {
+        let data = self.data_factory.query()?;
+        let mut record_factory = UsnRecordFactory::new(self.usn_journal);
+        record_factory.set_usn_journal_id(data.data.usn_journal_id);
+        self.usn_journal_id.set(data.data.usn_journal_id);
+        record_factory.set_max_major_version(self.max_major_version);
+        let start_usn = self.next_usn.get();
+        record_factory.set_start_usn(start_usn);
+        let raw_records = record_factory.read::<65535>()?;
+        if let Some(next_usn) = raw_records.next_usn {
+            self.next_usn.set(next_usn);
+        }
+        let mut records = *raw_records.parse();
+        if self.recover_slack {
+            records.extend(self.recover(start_usn)?);
+        }
+        Ok(records)
+    }
+
+    // stale records of the pages just read, in usn order, each once over all blocks.
+    // copies of the live records just read have their usns in the block.
+    fn recover(&self, start_usn: i64) -> Result<Vec<Record>> {
+        let mut seen = self.recovered.borrow_mut();
+        let block = start_usn..self.next_usn.get();
+        let stale = unsafe {
+            self.usn_journal
+                .raw_read_slack(start_usn, self.next_usn.get())?
+        };
+        let mut recovered: Vec<Record> = stale
+            .iter()
+            .map(|bytes| Record {
+                recovered: true,
+                ..Record::from(bytes.as_slice())
+            })
+            .filter(|r| !block.contains(&r.usn) && seen.insert(r.usn))
+            .collect();
+        recovered.sort_by_key(|r| r.usn);
+        Ok(recovered)
+    }
 }
 
 impl<'a, U> RecordFetcher for Reader<'a, U>
 where
     U: UsnJournalWrapper,
 {
+    // an empty block ends iteration, so blocks the filter empties are skipped.
     fn do_fetch(&self) -> Result<Box<Vec<Record>>> {
-        let data_factory = UsnJournalDataFactory::new(self.usn_journal);
-        let data = data_factory.query()?;
-        let mut record_factory = UsnRecordFactory::new(self.usn_journal);
-        record_factory.set_usn_journal_id(data.data.usn_journal_id);
-        let raw_records = record_factory.read::<65535>()?;
-        let records = raw_records.parse();
-        Ok(records)
+        loop {
+            let mut records = self.fetch_block()?;
+            let Some(filter) = &self.filter else {
+                return Ok(Box::new(records));
+            };
+            if records.is_empty() {
+                return Ok(Box::new(records));
+            }
+            records.retain(|r| filter.matches(r));
+            if !records.is_empty() {
+                return Ok(Box::new(records));
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::raw::usn_journal_wrapper::{RawRecords, UsnJournalWrapper};
+    use crate::filter::Filter;
+    use crate::flags::{FileAttributes, Reason};
+    use crate::raw::builder::RecordBuilder;
+    use crate::raw::file::UsnJournalFile;
+    use crate::raw::parser::PAGE_SIZE;
     use crate::reader::{Reader, RecordFetcher};
-    use anyhow::Result;
+    use crate::testing::{Call, FakeJournal};
+    use crate::usn_journal_data::DataVer;
+    use crate::usn_record::Record;
+    use std::io::Cursor;
 
-    struct TestUsnJournal {}
-
-    impl UsnJournalWrapper for TestUsnJournal {
-        unsafe fn raw_create(&self) {
-            unreachable!()
-        }
-
-        unsafe fn raw_query<D: Default>(&self) -> Result<D> {
-            Ok(Default::default())
-        }
-
-        unsafe fn raw_read<const N: usize>(&self, _: i64, _: u64) -> Result<RawRecords<N>> {
-            let p = [
-                144u8, 0, 128, 144, 0, 0, 0, 0, 144, 0, 0, 0, 2, 0, 0, 0, 76, 119, 0, 0, 0, 0, 4,
-                0, 195, 162, 3, 0, 0, 0, 2, 0, 0, 0, 128, 144, 0, 0, 0, 0, 10, 27, 185, 192, 46,
-                86, 216, 1, 3, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 80, 0, 60, 0, 51, 0,
-                51, 0, 48, 0, 66, 0, 67, 0, 50, 0, 51, 0, 53, 0, 68, 0, 66, 0, 55, 0, 65, 0, 55, 0,
-                56, 0, 56, 0, 50, 0, 52, 0, 52, 0, 67, 0, 57, 0, 68, 0, 67, 0, 66, 0, 65, 0, 52, 0,
-                68, 0, 50, 0, 56, 0, 68, 0, 65, 0, 51, 0, 57, 0, 70, 0, 53, 0, 56, 0, 66, 0, 52, 0,
-                48, 0, 56, 0, 53, 0,
-            ];
-            let mut pp = [0u8; N];
-            pp[0..148].clone_from_slice(&p);
-            Ok(RawRecords {
-                raw_ptr: Box::new(pp),
-                len: 152,
+    // the record captured from a live volume in `raw::builder`.
+    fn captured() -> FakeJournal {
+        let journal = FakeJournal::new();
+        journal.set_usn_bounds(2424307712, 2424307712);
+        journal
+            .append(&Record {
+                timestamp: 132950947809270538,
+                major_version: 2,
+                file_reference_number: 0x0004_0000_0000_774c.into(),
+                parent_file_reference_number: 0x0002_0000_0003_a2c3.into(),
+                reason: Reason(0x80000003),
+                file_attributes: FileAttributes(0x20),
+                file_name: "330BC235DB7A788244C9DCBA4D28DA39F58B4085".to_string(),
+                ..Default::default()
             })
-        }
-
-        unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>> {
-            unreachable!()
-        }
-
-        unsafe fn raw_delete(&self) {
-            unreachable!()
-        }
+            .unwrap();
+        journal
     }
 
     #[test]
     fn it_should_be_read() {
-        let reader = Reader::new(&TestUsnJournal {});
+        let journal = captured();
+        let reader = Reader::new(&journal);
         let records = reader.do_fetch().unwrap();
         let only_one = records.first().unwrap();
 
@@ -100,11 +237,132 @@ mod tests {
 
     #[test]
     fn it_should_be_read_and_into() {
-        let reader = Reader::new(&TestUsnJournal {});
+        let journal = captured();
+        let reader = Reader::new(&journal);
         let records = reader.read().unwrap();
         let mut iter = records.into_iter();
         let only_one = iter.next().unwrap();
 
         assert_eq!(only_one.usn, 2424307712);
     }
+
+    #[test]
+    fn it_should_probe_journal_data_once() {
+        let journal = captured();
+        journal.set_data_ver(DataVer::V0);
+        let reader = Reader::new(&journal);
+
+        assert_eq!(reader.do_fetch().unwrap().len(), 1);
+        assert!(reader.do_fetch().unwrap().is_empty());
+        // V2 and V1 are rejected once, the blocks after ask for V0 right away.
+        assert_eq!(journal.calls(Call::Query), 4);
+    }
+
+    #[test]
+    fn it_should_apply_filter() {
+        let journal = captured();
+        let mut reader = Reader::new(&journal);
+        reader.set_filter(Filter::parse("usn=2424307712 AND name:330bc*").unwrap());
+        let records = reader.do_fetch().unwrap();
+
+        assert_eq!(records.len(), 1);
+    }
+
+    // four records a page from the third page on, one tick apart.
+    fn journal(pages: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; pages * PAGE_SIZE];
+        for (i, at) in (2 * PAGE_SIZE..buf.len())
+            .step_by(PAGE_SIZE)
+            .flat_map(|p| (p..p + 256).step_by(64))
+            .enumerate()
+        {
+            buf[at..at + 4].copy_from_slice(&64u32.to_le_bytes());
+            buf[at + 4..at + 6].copy_from_slice(&2u16.to_le_bytes());
+            buf[at + 24..at + 32].copy_from_slice(&(at as i64).to_le_bytes());
+            buf[at + 32..at + 40].copy_from_slice(&(1000 + i as i64).to_le_bytes());
+            buf[at + 56..at + 58].copy_from_slice(&4u16.to_le_bytes());
+            buf[at + 58..at + 60].copy_from_slice(&60u16.to_le_bytes());
+            buf[at + 60..at + 64].copy_from_slice(&[97, 0, 98, 0]);
+        }
+        buf
+    }
+
+    #[test]
+    fn it_should_seek_to_time() {
+        let file = UsnJournalFile::new(Cursor::new(journal(64))).unwrap();
+        let mut reader = Reader::new(&file);
+
+        let usn = reader.seek_to_time(1000 + 4 * 30 + 2).unwrap();
+        let first = reader.read().unwrap().into_iter().next().unwrap();
+
+        assert_eq!(usn, (32 * PAGE_SIZE + 128) as i64);
+        assert_eq!(first.usn, usn);
+        assert_eq!(first.timestamp, 1000 + 4 * 30 + 2);
+    }
+
+    #[test]
+    fn it_should_seek_to_bounds() {
+        let file = UsnJournalFile::new(Cursor::new(journal(8))).unwrap();
+        let mut reader = Reader::new(&file);
+
+        assert_eq!(reader.seek_to_time(0).unwrap(), 2 * PAGE_SIZE as i64);
+        assert_eq!(reader.seek_to_time(1004).unwrap(), 3 * PAGE_SIZE as i64);
+        assert_eq!(reader.seek_to_time(i64::MAX).unwrap(), 8 * PAGE_SIZE as i64);
+    }
+
+    #[test]
+    fn it_should_recover_slack_from_journal_pages() {
+        let mut stream = vec![0u8; 21 * PAGE_SIZE];
+        let mut put = |at: usize, usn: i64, name: &str| {
+            let bytes = RecordBuilder::default()
+                .set_usn(usn)
+                .set_timestamp(132989000930000000)
+                .set_file_name(name)
+                .build()
+                .unwrap();
+            stream[at..at + bytes.len()].copy_from_slice(&bytes);
+        };
+        // stale usns are from before the file was truncated.
+        put(0, 0, "a.txt");
+        put(1024, 500000, "old.txt");
+        put(2048, 300000, "old.txt");
+        // read in the next block, with copies of a record recovered before and of a live one.
+        let page = 20 * PAGE_SIZE;
+        put(page, page as i64, "b.txt");
+        put(page + 1024, 300000, "old.txt");
+        put(page + 2048, 100000, "old.txt");
+        put(page + 3072, page as i64, "b.txt");
+        let file = UsnJournalFile::new(Cursor::new(stream)).unwrap();
+        let mut reader = Reader::new(&file);
+        reader.set_recover_slack(true);
+        let mut records = vec![];
+        loop {
+            let block = reader.do_fetch().unwrap();
+            if block.is_empty() {
+                break;
+            }
+            records.extend(block.iter().map(|r| (r.usn, r.recovered)));
+        }
+
+        assert_eq!(
+            records,
+            vec![
+                (0, false),
+                (300000, true),
+                (500000, true),
+                (page as i64, false),
+                (100000, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_recover_slack_from_a_live_volume() {
+        let journal = captured();
+        let mut reader = Reader::new(&journal);
+        reader.set_recover_slack(true);
+        let err = reader.do_fetch().unwrap_err();
+
+        assert!(err.to_string().contains("not supported on live volumes"));
+    }
 }
@@ -0,0 +1,272 @@
+use crate::raw::ioctl::{USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE};
+use crate::usn_journal_data::Data;
+use crate::usn_record::Record;
+use crate::util::filetime::TICKS_PER_SECOND;
+use std::collections::{BTreeMap, HashMap};
+
+const TICKS_PER_HOUR: i64 = 3600 * TICKS_PER_SECOND;
+
+// Summary counts over a record stream. shards read separately can be merged into one.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    pub records: u64,
+    pub by_reason: BTreeMap<String, u64>,
+    // keyed by the FILETIME of the start of the hour.
+    pub by_hour: BTreeMap<i64, u64>,
+    // keyed by path when resolved, by file reference otherwise.
+    pub by_file: HashMap<String, u64>,
+    // keyed by parent path when resolved, by parent reference otherwise.
+    pub by_directory: HashMap<String, u64>,
+    // lower case, without the dot. files without one are not counted.
+    pub by_extension: HashMap<String, u64>,
+    pub creates: u64,
+    pub deletes: u64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+}
+
+// How far back the records reach, compared with what the journal can hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coverage {
+    // in FILETIME ticks, between the oldest and the newest record.
+    pub time_span: i64,
+    pub used_bytes: u64,
+    pub maximum_size: u64,
+    // the span a full journal would cover at the same rate.
+    pub projected_span: Option<i64>,
+}
+
+fn top<K: Clone + Ord>(counts: &HashMap<K, u64>, n: usize) -> Vec<(K, u64)> {
+    let mut top: Vec<(K, u64)> = counts.iter().map(|(k, c)| (k.clone(), *c)).collect();
+    top.sort_by(|(ka, a), (kb, b)| b.cmp(a).then_with(|| ka.cmp(kb)));
+    top.truncate(n);
+    top
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, record: &Record) {
+        self.records += 1;
+        for name in record.reason.names() {
+            *self.by_reason.entry(name.to_string()).or_default() += 1;
+        }
+        // the unix epoch falls on an hour, so these are whole hours of both.
+        let hour = record
+            .timestamp
+            .div_euclid(TICKS_PER_HOUR)
+            .saturating_mul(TICKS_PER_HOUR);
+        *self.by_hour.entry(hour).or_default() += 1;
+
+        let (file, directory) = match record.path.as_deref() {
+            Some(path) => (
+                path.to_string(),
+                path.rsplit_once('\\')
+                    .map(|(parent, _)| parent.to_string())
+                    .unwrap_or_default(),
+            ),
+            None => (
+                record.file_reference_number.to_string(),
+                record.parent_file_reference_number.to_string(),
+            ),
+        };
+        *self.by_file.entry(file).or_default() += 1;
+        *self.by_directory.entry(directory).or_default() += 1;
+        if let Some((stem, extension)) = record.file_name.rsplit_once('.') {
+            if !stem.is_empty() && !extension.is_empty() {
+                *self
+                    .by_extension
+                    .entry(extension.to_lowercase())
+                    .or_default() += 1;
+            }
+        }
+
+        if record.reason.contains(USN_REASON_FILE_CREATE) {
+            self.creates += 1;
+        }
+        if record.reason.contains(USN_REASON_FILE_DELETE) {
+            self.deletes += 1;
+        }
+        self.first_timestamp = Some(
+            self.first_timestamp
+                .map_or(record.timestamp, |t| t.min(record.timestamp)),
+        );
+        self.last_timestamp = Some(
+            self.last_timestamp
+                .map_or(record.timestamp, |t| t.max(record.timestamp)),
+        );
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        self.records += other.records;
+        for (reason, c) in other.by_reason {
+            *self.by_reason.entry(reason).or_default() += c;
+        }
+        for (hour, c) in other.by_hour {
+            *self.by_hour.entry(hour).or_default() += c;
+        }
+        for (into, from) in [
+            (&mut self.by_file, other.by_file),
+            (&mut self.by_directory, other.by_directory),
+            (&mut self.by_extension, other.by_extension),
+        ] {
+            for (k, c) in from {
+                *into.entry(k).or_default() += c;
+            }
+        }
+        self.creates += other.creates;
+        self.deletes += other.deletes;
+        self.first_timestamp = self
+            .first_timestamp
+            .into_iter()
+            .chain(other.first_timestamp)
+            .min();
+        self.last_timestamp = self
+            .last_timestamp
+            .into_iter()
+            .chain(other.last_timestamp)
+            .max();
+    }
+
+    pub fn top_files(&self, n: usize) -> Vec<(String, u64)> {
+        top(&self.by_file, n)
+    }
+
+    pub fn top_directories(&self, n: usize) -> Vec<(String, u64)> {
+        top(&self.by_directory, n)
+    }
+
+    pub fn top_extensions(&self, n: usize) -> Vec<(String, u64)> {
+        top(&self.by_extension, n)
+    }
+
+    // None when nothing was deleted.
+    pub fn create_delete_ratio(&self) -> Option<f64> {
+        match self.deletes {
+            0 => None,
+            deletes => Some(self.creates as f64 / deletes as f64),
+        }
+    }
+
+    pub fn time_span(&self) -> Option<i64> {
+        Some(self.last_timestamp?.saturating_sub(self.first_timestamp?))
+    }
+
+    pub fn coverage(&self, data: &Data) -> Coverage {
+        let time_span = self.time_span().unwrap_or(0);
+        let used_bytes = (data.next_usn - data.first_usn).max(0) as u64;
+        let projected_span = match used_bytes {
+            0 => None,
+            used => Some((time_span as f64 * data.maximum_size as f64 / used as f64) as i64),
+        };
+        Coverage {
+            time_span,
+            used_bytes,
+            maximum_size: data.maximum_size,
+            projected_span,
+        }
+    }
+}
+
+impl Extend<Record> for Stats {
+    fn extend<T: IntoIterator<Item = Record>>(&mut self, records: T) {
+        for record in records {
+            self.add(&record);
+        }
+    }
+}
+
+impl FromIterator<Record> for Stats {
+    fn from_iter<T: IntoIterator<Item = Record>>(records: T) -> Self {
+        let mut stats = Stats::new();
+        stats.extend(records);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::Stats;
+    use crate::testing::{record, TIMESTAMP};
+    use crate::usn_journal_data::Data;
+    use crate::usn_record::Record;
+
+    const HOUR: i64 = 36_000_000_000;
+
+    fn records() -> Vec<Record> {
+        vec![
+            record(0x10, 5, 0x80000100, "a.docx"),
+            Record {
+                timestamp: TIMESTAMP + 1,
+                ..record(0x10, 5, 0x80000002, "a.docx")
+            },
+            Record {
+                timestamp: TIMESTAMP + HOUR,
+                ..record(0x11, 5, 0x80000100, "b.TXT")
+            },
+            Record {
+                timestamp: TIMESTAMP + HOUR,
+                ..record(0x12, 5, 0x80000200, ".gitignore")
+            },
+        ]
+    }
+
+    #[test]
+    fn it_should_count_records() {
+        let stats: Stats = records().into_iter().collect();
+
+        assert_eq!(stats.records, 4);
+        assert_eq!(stats.by_reason["CLOSE"], 4);
+        assert_eq!(stats.by_reason["FILE_CREATE"], 2);
+        assert_eq!(stats.by_hour.values().copied().collect::<Vec<_>>(), [2, 2]);
+        assert_eq!(stats.top_files(1), vec![("0x10".to_string(), 2)]);
+        assert_eq!(stats.top_directories(5), vec![("0x5".to_string(), 4)]);
+        assert_eq!(
+            stats.top_extensions(5),
+            vec![("docx".to_string(), 2), ("txt".to_string(), 1)]
+        );
+        assert_eq!(stats.create_delete_ratio(), Some(2.0));
+        assert_eq!(stats.time_span(), Some(HOUR));
+    }
+
+    #[test]
+    fn it_should_count_extreme_timestamps() {
+        let mut first = record(0x10, 5, 0x2, "a.txt");
+        first.timestamp = i64::MIN;
+        let mut last = first.clone();
+        last.timestamp = i64::MAX;
+        let stats: Stats = [first, last].into_iter().collect();
+
+        assert_eq!(stats.by_hour.len(), 2);
+        assert_eq!(stats.by_hour.keys().next(), Some(&i64::MIN));
+        assert_eq!(stats.time_span(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn it_should_merge_shards() {
+        let all: Stats = records().into_iter().collect();
+        let mut first: Stats = records()[..2].iter().cloned().collect();
+        let second: Stats = records()[2..].iter().cloned().collect();
+        first.merge(second);
+
+        assert_eq!(first, all);
+    }
+
+    #[test]
+    fn it_should_compare_with_capacity() {
+        let stats: Stats = records().into_iter().collect();
+        let data = Data {
+            first_usn: 0,
+            next_usn: 1 << 20,
+            maximum_size: 4 << 20,
+            ..Default::default()
+        };
+        let coverage = stats.coverage(&data);
+
+        assert_eq!(coverage.used_bytes, 1 << 20);
+        assert_eq!(coverage.projected_span, Some(4 * HOUR));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::checkpoint::Checkpoint;
+use crate::index::Index;
+use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
+use crate::usn_record::Record;
+use anyhow::Result;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// `D:\Projects\` and `\projects` are the same scope. the drive is dropped, as the index only
+// covers one volume.
+fn normalize(scope: &str) -> String {
+    let scope = match scope.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => &scope[2..],
+        _ => scope,
+    };
+    format!("\\{}", scope.trim_matches('\\')).to_lowercase()
+}
+
+fn in_scope(scope: &str, path: &str) -> bool {
+    match scope {
+        "\\" => true,
+        _ => path
+            .strip_prefix(scope)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('\\')),
+    }
+}
+
+struct Subscriber {
+    scopes: Vec<String>,
+    sender: Sender<Record>,
+}
+
+// Hands records to subscribers by subtree, from a single read of the journal. a record is in
+// a subtree when its path is, as of the record: the old name record of a move out of the tree
+// still is, later records of the moved files no longer are.
+pub struct Subscriptions<'a, U: UsnJournalWrapper> {
+    usn_journal: &'a U,
+    index: Index,
+    subscribers: Vec<Subscriber>,
+}
+
+impl<'a, U: UsnJournalWrapper> Subscriptions<'a, U> {
+    // `index` resolves the paths and is followed from its checkpoint on.
+    pub fn new(usn_journal: &'a U, index: Index) -> Self {
+        Self {
+            usn_journal,
+            index,
+            subscribers: vec![],
+        }
+    }
+
+    // records within any of `scopes`, with their paths filled in. dropping the receiver ends
+    // the subscription.
+    pub fn subscribe<S: AsRef<str>>(&mut self, scopes: &[S]) -> Receiver<Record> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(Subscriber {
+            scopes: scopes.iter().map(|s| normalize(s.as_ref())).collect(),
+            sender,
+        });
+        receiver
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.index.checkpoint()
+    }
+
+    // reads everything since the last poll once, for all subscribers, and returns how many
+    // records were read.
+    pub fn poll(&mut self) -> Result<usize> {
+        let subscribers = &mut self.subscribers;
+        self.index.follow_with(self.usn_journal, |index, record| {
+            let path = index.join(record.parent_file_reference_number, &record.file_name);
+            let lower = path.to_lowercase();
+            subscribers.retain(|subscriber| {
+                if !subscriber.scopes.iter().any(|s| in_scope(s, &lower)) {
+                    return true;
+                }
+                let record = Record {
+                    path: Some(path.clone()),
+                    ..record.clone()
+                };
+                subscriber.sender.send(record).is_ok()
+            });
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::Index;
+    use crate::subscription::{normalize, Subscriptions};
+    use crate::testing::{record, Call, FakeJournal};
+    use crate::usn_record::Record;
+
+    const ROOT: u64 = 0x0005_0000_0000_0005;
+    const PROJECTS: u64 = 0x10;
+    const OTHER: u64 = 0x11;
+    const APP: u64 = 0x12;
+
+    // `\Projects`, `\Other` and `\Other\app`.
+    fn journal() -> FakeJournal {
+        let journal = FakeJournal::new();
+        journal
+            .extend(&[
+                record(PROJECTS, ROOT, 0x80000100, "Projects"),
+                record(OTHER, ROOT, 0x80000100, "Other"),
+                record(APP, OTHER, 0x80000100, "app"),
+            ])
+            .unwrap();
+        journal
+    }
+
+    fn names(records: impl Iterator<Item = Record>) -> Vec<String> {
+        records.map(|r| r.path.unwrap()).collect()
+    }
+
+    #[test]
+    fn it_should_normalize_scopes() {
+        assert_eq!(normalize("D:\\Projects\\"), "\\projects");
+        assert_eq!(normalize("\\projects"), "\\projects");
+        assert_eq!(normalize("d:\\"), "\\");
+    }
+
+    #[test]
+    fn it_should_share_one_read_loop() {
+        let journal = journal();
+        let mut subscriptions = Subscriptions::new(&journal, Index::from_enum(&journal).unwrap());
+        let projects = subscriptions.subscribe(&["D:\\Projects\\"]);
+        let other = subscriptions.subscribe(&["\\other\\app", "\\Projects"]);
+        journal
+            .extend(&[
+                record(0x20, PROJECTS, 0x100, "a.txt"),
+                record(0x21, APP, 0x100, "b.txt"),
+                record(0x22, ROOT, 0x100, "c.txt"),
+                // `\ProjectsOld` is not `\Projects`.
+                record(0x23, ROOT, 0x100, "ProjectsOld"),
+            ])
+            .unwrap();
+        let reads = journal.calls(Call::Read);
+
+        assert_eq!(subscriptions.poll().unwrap(), 4);
+        assert_eq!(journal.calls(Call::Read) - reads, 2);
+        assert_eq!(names(projects.try_iter()), vec!["\\Projects\\a.txt"]);
+        assert_eq!(
+            names(other.try_iter()),
+            vec!["\\Projects\\a.txt", "\\Other\\app\\b.txt"]
+        );
+    }
+
+    #[test]
+    fn it_should_follow_directories_moved_in_and_out() {
+        let journal = journal();
+        let mut subscriptions = Subscriptions::new(&journal, Index::from_enum(&journal).unwrap());
+        let projects = subscriptions.subscribe(&["\\Projects"]);
+        journal
+            .extend(&[
+                // `\Other\app` moved in, then written to.
+                record(APP, OTHER, 0x1000, "app"),
+                record(APP, PROJECTS, 0x2000, "app"),
+                record(0x20, APP, 0x100, "a.txt"),
+                // and moved out again.
+                record(APP, PROJECTS, 0x1000, "app"),
+                record(APP, ROOT, 0x2000, "app"),
+                record(0x20, APP, 0x2, "a.txt"),
+            ])
+            .unwrap();
+        subscriptions.poll().unwrap();
+
+        assert_eq!(
+            names(projects.try_iter()),
+            vec![
+                "\\Projects\\app",
+                "\\Projects\\app\\a.txt",
+                "\\Projects\\app"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_drop_closed_subscriptions() {
+        let journal = journal();
+        let mut subscriptions = Subscriptions::new(&journal, Index::from_enum(&journal).unwrap());
+        drop(subscriptions.subscribe(&["\\"]));
+        let all = subscriptions.subscribe(&["\\"]);
+        journal.append(&record(0x20, ROOT, 0x100, "a.txt")).unwrap();
+        subscriptions.poll().unwrap();
+
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(names(all.try_iter()), vec!["\\a.txt"]);
+        assert_eq!(subscriptions.checkpoint().next_usn, journal.data().next_usn);
+    }
+}
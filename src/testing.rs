@@ -0,0 +1,520 @@
+use crate::flags::{FileAttributes, Reason};
+use crate::raw::builder::RecordBuilder;
+use crate::raw::ioctl::{
+    ERROR_INVALID_PARAMETER, ERROR_JOURNAL_ENTRY_DELETED, ERROR_JOURNAL_NOT_ACTIVE,
+    FLAG_USN_TRACK_MODIFIED_RANGES_ENABLE, USN_REASON_FILE_DELETE,
+};
+use crate::raw::parser::PAGE_SIZE;
+use crate::raw::usn_journal_wrapper::{
+    RawRecords, RawUsnJournalData, UsnJournalWrapper, Win32Error,
+};
+use crate::usn_journal_data::{Data, DataVer};
+use crate::usn_record::Record;
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Call {
+    Create,
+    Query,
+    Read,
+    Enum,
+    Delete,
+    TrackRanges,
+}
+
+struct State {
+    data: Data,
+    // the newest journal data structure the volume knows.
+    data_ver: DataVer,
+    active: bool,
+    // encoded records, keyed by usn.
+    records: BTreeMap<i64, Vec<u8>>,
+    calls: HashMap<Call, usize>,
+    // (call, nth call of that kind, GetLastError code).
+    failures: Vec<(Call, usize, u32)>,
+}
+
+// An in-memory journal that behaves like a live volume, for testing consumers without windows.
+// failures, reads before the lowest valid usn included, are returned as a `Win32Error`, the
+// same as `WindowsUsnJournal`.
+pub struct FakeJournal {
+    state: RefCell<State>,
+}
+
+impl Default for FakeJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn fail(&mut self, call: Call) -> Result<()> {
+        let nth = self.calls.entry(call).or_default();
+        *nth += 1;
+        let nth = *nth;
+        match self.failures.iter().position(|f| f.0 == call && f.1 == nth) {
+            Some(i) => Err(Win32Error(self.failures.remove(i).2).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn active(&self) -> Result<()> {
+        match self.active {
+            true => Ok(()),
+            false => Err(Win32Error(ERROR_JOURNAL_NOT_ACTIVE).into()),
+        }
+    }
+
+    fn purge(&mut self, to_usn: i64) {
+        let to_usn = to_usn.clamp(self.data.lowest_valid_usn, self.data.next_usn);
+        self.records = self.records.split_off(&to_usn);
+        self.data.lowest_valid_usn = to_usn;
+        self.data.first_usn = self
+            .records
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.data.next_usn);
+    }
+
+    // a full journal drops its oldest allocation delta.
+    fn wrap(&mut self) {
+        let data = self.data;
+        if data.maximum_size == 0 {
+            return;
+        }
+        let used = (data.next_usn - data.lowest_valid_usn) as u64;
+        if used > data.maximum_size + data.allocation_delta {
+            let to_usn = data.next_usn - data.maximum_size as i64;
+            let page = PAGE_SIZE as i64;
+            self.purge((to_usn + page - 1) / page * page);
+        }
+    }
+}
+
+impl FakeJournal {
+    // an empty, active journal of 32 MiB with an 8 MiB allocation delta.
+    pub fn new() -> Self {
+        Self {
+            state: RefCell::new(State {
+                data: Data {
+                    usn_journal_id: 1,
+                    max_usn: i64::MAX,
+                    maximum_size: 32 << 20,
+                    allocation_delta: 8 << 20,
+                    min_supported_major_version: Some(2),
+                    max_supported_major_version: Some(2),
+                    ..Default::default()
+                },
+                data_ver: DataVer::V2,
+                active: true,
+                records: BTreeMap::new(),
+                calls: HashMap::new(),
+                failures: vec![],
+            }),
+        }
+    }
+
+    pub fn data(&self) -> Data {
+        self.state.borrow().data
+    }
+
+    // writes the record at the next usn, which is returned. like NTFS, a record that does
+    // not fit in the rest of a page starts the next one.
+    pub fn append(&self, record: &Record) -> Result<i64> {
+        let mut state = self.state.borrow_mut();
+        let page = PAGE_SIZE as i64;
+        let mut builder = RecordBuilder::from(record);
+        let mut usn = state.data.next_usn;
+        if usn % page + builder.build()?.len() as i64 > page {
+            usn = (usn / page + 1) * page;
+        }
+        let bytes = builder.set_usn(usn).build()?;
+
+        state.data.next_usn = usn + bytes.len() as i64;
+        if state.records.is_empty() {
+            state.data.first_usn = usn;
+        }
+        state.records.insert(usn, bytes);
+        state.wrap();
+        Ok(usn)
+    }
+
+    pub fn extend<'r, I: IntoIterator<Item = &'r Record>>(&self, records: I) -> Result<Vec<i64>> {
+        records.into_iter().map(|r| self.append(r)).collect()
+    }
+
+    pub fn set_usn_journal_id(&self, usn_journal_id: u64) {
+        self.state.borrow_mut().data.usn_journal_id = usn_journal_id;
+    }
+
+    // newer structures are rejected with `ERROR_INVALID_PARAMETER`, like on older windows.
+    // V0 only volumes have no record version bounds.
+    pub fn set_data_ver(&self, data_ver: DataVer) {
+        let mut state = self.state.borrow_mut();
+        state.data_ver = data_ver;
+        if data_ver == DataVer::V0 {
+            state.data.min_supported_major_version = None;
+            state.data.max_supported_major_version = None;
+        }
+    }
+
+    // records outside of the bounds are dropped.
+    pub fn set_usn_bounds(&self, first_usn: i64, next_usn: i64) {
+        let mut state = self.state.borrow_mut();
+        state
+            .records
+            .retain(|usn, _| (first_usn..next_usn).contains(usn));
+        state.data.first_usn = first_usn;
+        state.data.lowest_valid_usn = first_usn;
+        state.data.next_usn = next_usn;
+    }
+
+    // zero disables wrap around.
+    pub fn set_maximum_size(&self, maximum_size: u64, allocation_delta: u64) {
+        let mut state = self.state.borrow_mut();
+        state.data.maximum_size = maximum_size;
+        state.data.allocation_delta = allocation_delta;
+        state.wrap();
+    }
+
+    // drops every record before `to_usn`, reading from there fails with entry deleted.
+    pub fn purge(&self, to_usn: i64) {
+        self.state.borrow_mut().purge(to_usn);
+    }
+
+    pub fn delete_journal(&self) {
+        let mut state = self.state.borrow_mut();
+        state.active = false;
+        state.records.clear();
+    }
+
+    // a new, empty journal. usns carry on from the deleted one.
+    pub fn recreate(&self, usn_journal_id: u64) {
+        let mut state = self.state.borrow_mut();
+        let next_usn = state.data.next_usn;
+        state.active = true;
+        state.records.clear();
+        state.data.usn_journal_id = usn_journal_id;
+        state.data.first_usn = next_usn;
+        state.data.lowest_valid_usn = next_usn;
+    }
+
+    // the `nth` call of this kind, counting from 1 and including calls already made,
+    // fails with a GetLastError code such as `ERROR_ACCESS_DENIED`.
+    pub fn fail_at(&self, call: Call, nth: usize, error: u32) {
+        self.state.borrow_mut().failures.push((call, nth, error));
+    }
+
+    pub fn fail_next(&self, call: Call, error: u32) {
+        let nth = self.calls(call) + 1;
+        self.fail_at(call, nth, error);
+    }
+
+    pub fn calls(&self, call: Call) -> usize {
+        self.state.borrow().calls.get(&call).copied().unwrap_or(0)
+    }
+
+    fn output<const N: usize>(header: i64, records: &[&Vec<u8>]) -> Result<RawRecords<N>> {
+        let mut output = Box::new([0u8; N]);
+        let mut len = size_of::<i64>();
+        if N < len {
+            return Err(anyhow!("buffer of {} bytes is too small.", N));
+        }
+        output[..len].copy_from_slice(&header.to_le_bytes());
+        for bytes in records {
+            output[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        }
+        Ok(RawRecords {
+            raw_ptr: output,
+            len: len as u32,
+        })
+    }
+}
+
+impl UsnJournalWrapper for FakeJournal {
+    // creates a journal when there is none, with the next id.
+    unsafe fn raw_create(&self) {
+        let (failed, active, usn_journal_id) = {
+            let mut state = self.state.borrow_mut();
+            let failed = state.fail(Call::Create).is_err();
+            (failed, state.active, state.data.usn_journal_id)
+        };
+        if !failed && !active {
+            self.recreate(usn_journal_id + 1);
+        }
+    }
+
+    unsafe fn raw_query<D: RawUsnJournalData + Default>(&self) -> Result<D> {
+        let mut state = self.state.borrow_mut();
+        state.fail(Call::Query)?;
+        if D::VER > state.data_ver {
+            return Err(Win32Error(ERROR_INVALID_PARAMETER).into());
+        }
+        state.active()?;
+        Ok(D::from_data(&state.data))
+    }
+
+    unsafe fn raw_read<const N: usize>(
+        &self,
+        start_usn: i64,
+        usn_journal_id: u64,
+        max_major_version: u16,
+    ) -> Result<RawRecords<N>> {
+        let mut state = self.state.borrow_mut();
+        state.fail(Call::Read)?;
+        state.active()?;
+        if usn_journal_id != state.data.usn_journal_id {
+            return Err(Win32Error(ERROR_INVALID_PARAMETER).into());
+        }
+        let major_version = |bytes: &[u8]| u16::from_le_bytes([bytes[4], bytes[5]]);
+        // zero reads from the start of the journal.
+        let start_usn = match start_usn {
+            0 => state.data.lowest_valid_usn,
+            usn if usn < state.data.lowest_valid_usn => {
+                return Err(Win32Error(ERROR_JOURNAL_ENTRY_DELETED).into())
+            }
+            usn => usn,
+        };
+
+        let mut room = N.saturating_sub(size_of::<i64>());
+        let mut next_usn = state.data.next_usn;
+        let mut records = vec![];
+        for (usn, bytes) in state.records.range(start_usn..) {
+            if major_version(bytes) > max_major_version.max(2) {
+                continue;
+            }
+            if bytes.len() > room {
+                next_usn = *usn;
+                break;
+            }
+            room -= bytes.len();
+            records.push(bytes);
+        }
+        Self::output(next_usn.max(start_usn), &records)
+    }
+
+    // the last record of every file that still exists, by file reference. the output
+    // starts with the file reference after the last one.
+    unsafe fn raw_enum<const N: usize>(&self, start_file_reference: u64) -> Result<RawRecords<N>> {
+        let mut state = self.state.borrow_mut();
+        state.fail(Call::Enum)?;
+        state.active()?;
+
+        let mut files: BTreeMap<u64, &Vec<u8>> = BTreeMap::new();
+        // range records have no name.
+        for bytes in state.records.values().filter(|b| b[4] != 4) {
+            let record = Record::from(bytes.as_slice());
+            match record.reason.contains(USN_REASON_FILE_DELETE) {
+                true => files.remove(&record.file_reference_number.0),
+                false => files.insert(record.file_reference_number.0, bytes),
+            };
+        }
+
+        let mut room = N.saturating_sub(size_of::<i64>());
+        let mut next = files.keys().last().map_or(0, |f| f + 1);
+        let mut records = vec![];
+        for (file, bytes) in files.range(start_file_reference..) {
+            if bytes.len() > room {
+                next = *file;
+                break;
+            }
+            room -= bytes.len();
+            records.push(*bytes);
+        }
+        Self::output(next as i64, &records)
+    }
+
+    unsafe fn raw_delete(&self) {
+        let failed = self.state.borrow_mut().fail(Call::Delete).is_err();
+        if !failed {
+            self.delete_journal();
+        }
+    }
+
+    // V4 records are appended by the caller, this only records the settings.
+    unsafe fn raw_track_ranges(&self, chunk_size: u64, file_size_threshold: i64) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.fail(Call::TrackRanges)?;
+        state.active()?;
+        if state.data_ver < DataVer::V2 || chunk_size == 0 {
+            return Err(Win32Error(ERROR_INVALID_PARAMETER).into());
+        }
+        state.data.flags = Some(FLAG_USN_TRACK_MODIFIED_RANGES_ENABLE);
+        state.data.range_track_chunk_size = Some(chunk_size);
+        state.data.range_track_file_size_threshold = Some(file_size_threshold);
+        Ok(())
+    }
+}
+
+pub const TIMESTAMP: i64 = 132989000930000000;
+
+// A version 2 record of a file in `parent`, made at TIMESTAMP. the usn and any other field are
+// set with struct update syntax.
+pub fn record(file: u64, parent: u64, reason: u32, name: &str) -> Record {
+    Record {
+        timestamp: TIMESTAMP,
+        major_version: 2,
+        file_reference_number: file.into(),
+        parent_file_reference_number: parent.into(),
+        reason: Reason(reason),
+        file_attributes: FileAttributes(0x20),
+        file_name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+// numbers `records` from usn 1, each a tick after TIMESTAMP and the one before.
+pub fn numbered<I: IntoIterator<Item = Record>>(records: I) -> Vec<Record> {
+    records
+        .into_iter()
+        .zip(1..)
+        .map(|(record, usn)| Record {
+            usn,
+            timestamp: TIMESTAMP + usn,
+            ..record
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flags::Reason;
+    use crate::raw::ioctl::{
+        ERROR_ACCESS_DENIED, ERROR_JOURNAL_ENTRY_DELETED, ERROR_JOURNAL_NOT_ACTIVE,
+        USN_JOURNAL_DATA_V2,
+    };
+    use crate::raw::parser::{Parser, PAGE_SIZE};
+    use crate::raw::usn_journal_wrapper::{error_code, UsnJournalWrapper};
+    use crate::reader::Reader;
+    use crate::testing::{record, Call, FakeJournal};
+    use crate::usn_record::Record;
+
+    fn read_all(journal: &FakeJournal) -> Vec<Record> {
+        let reader = Reader::new(journal);
+        reader.read().unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn it_should_read_appended_records() {
+        let journal = FakeJournal::new();
+        let usns = journal
+            .extend(&[
+                record(0x10, 5, 0x80000100, "a.txt"),
+                record(0x11, 5, 0x80000100, "b.txt"),
+            ])
+            .unwrap();
+        let records = read_all(&journal);
+
+        assert_eq!(usns, vec![0, 72]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[1],
+            Record {
+                usn: 72,
+                ..record(0x11, 5, 0x80000100, "b.txt")
+            }
+        );
+        assert_eq!(journal.data().next_usn, 144);
+    }
+
+    #[test]
+    fn it_should_not_cross_pages() {
+        let journal = FakeJournal::new();
+        for i in 0..100 {
+            journal.append(&record(i, 5, 0x80000100, "a.txt")).unwrap();
+        }
+        let raw = unsafe { journal.raw_read::<65536>(0, 1, 2).unwrap() };
+
+        assert_eq!(raw.split().len(), 100);
+        assert!(raw.split().iter().all(|r| r.len() == 72));
+        assert_eq!(Record::from(raw.split()[56]).usn, PAGE_SIZE as i64);
+    }
+
+    #[test]
+    fn it_should_purge_and_wrap_around() {
+        let journal = FakeJournal::new();
+        let usns = journal
+            .extend(&vec![record(0x10, 5, 0x80000100, "a.txt"); 200])
+            .unwrap();
+        journal.purge(usns[100]);
+        let err = unsafe { journal.raw_read::<65536>(usns[50], 1, 2).err().unwrap() };
+
+        assert_eq!(error_code(&err), Some(ERROR_JOURNAL_ENTRY_DELETED));
+        assert_eq!(read_all(&journal).len(), 100);
+
+        journal.set_maximum_size(PAGE_SIZE as u64, 0);
+        let data = journal.data();
+        assert_eq!(data.lowest_valid_usn, 3 * PAGE_SIZE as i64);
+        assert_eq!(data.first_usn, 3 * PAGE_SIZE as i64);
+    }
+
+    #[test]
+    fn it_should_delete_and_recreate() {
+        let journal = FakeJournal::new();
+        journal
+            .append(&record(0x10, 5, 0x80000100, "a.txt"))
+            .unwrap();
+        unsafe { journal.raw_delete() };
+
+        let err = unsafe { journal.raw_query::<USN_JOURNAL_DATA_V2>() }
+            .err()
+            .unwrap();
+        assert_eq!(error_code(&err), Some(ERROR_JOURNAL_NOT_ACTIVE));
+
+        unsafe { journal.raw_create() };
+        journal
+            .append(&record(0x11, 5, 0x80000100, "b.txt"))
+            .unwrap();
+        let data = unsafe { journal.raw_query::<USN_JOURNAL_DATA_V2>().unwrap() };
+        assert_eq!(data.UsnJournalID, 2);
+        assert_eq!(data.FirstUsn, 72);
+        assert!(unsafe { journal.raw_read::<65536>(72, 1, 2) }.is_err());
+        assert_eq!(read_all(&journal)[0].file_name, "b.txt");
+    }
+
+    #[test]
+    fn it_should_fail_at_chosen_calls() {
+        let journal = FakeJournal::new();
+        journal
+            .append(&record(0x10, 5, 0x80000100, "a.txt"))
+            .unwrap();
+        journal.fail_at(Call::Read, 2, ERROR_ACCESS_DENIED);
+
+        assert!(unsafe { journal.raw_read::<65536>(0, 1, 2) }.is_ok());
+        let err = unsafe { journal.raw_read::<65536>(0, 1, 2) }.err().unwrap();
+        assert_eq!(error_code(&err), Some(ERROR_ACCESS_DENIED));
+        assert_eq!(err.to_string(), "windows error 5.");
+        assert!(unsafe { journal.raw_read::<65536>(0, 1, 2) }.is_ok());
+        assert_eq!(journal.calls(Call::Read), 3);
+    }
+
+    #[test]
+    fn it_should_enumerate_existing_files() {
+        let journal = FakeJournal::new();
+        journal
+            .append(&record(0x11, 5, 0x80000100, "b.txt"))
+            .unwrap();
+        journal
+            .append(&record(0x10, 5, 0x80000100, "a.txt"))
+            .unwrap();
+        journal
+            .append(&Record {
+                reason: Reason(0x80000200),
+                ..record(0x12, 5, 0x80000100, "c.txt")
+            })
+            .unwrap();
+        let raw = unsafe { journal.raw_enum::<65536>(0).unwrap() };
+        let names: Vec<String> = raw
+            .split()
+            .iter()
+            .map(|r| Record::from(*r).file_name)
+            .collect();
+
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+    }
+}
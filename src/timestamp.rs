@@ -0,0 +1,240 @@
+use crate::util::filetime::{
+    offset_suffix, to_iso8601_with_offset, TICKS_PER_SECOND, UNIX_EPOCH_AS_FILETIME,
+};
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// FILETIME conversions, exact to 100ns. zero means the time was never set and negative values
+// are not valid FILETIMEs, so both are errors.
+
+fn unix_parts(filetime: i64) -> Result<(i64, u32)> {
+    if filetime <= 0 {
+        return Err(anyhow!("filetime {} is out of range.", filetime));
+    }
+    let ticks = filetime - UNIX_EPOCH_AS_FILETIME;
+    Ok((
+        ticks.div_euclid(TICKS_PER_SECOND),
+        (ticks.rem_euclid(TICKS_PER_SECOND) * 100) as u32,
+    ))
+}
+
+pub fn to_system_time(filetime: i64) -> Result<SystemTime> {
+    let (seconds, nanos) = unix_parts(filetime)?;
+    let time = match seconds >= 0 {
+        true => UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos)),
+        false => UNIX_EPOCH
+            .checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+            .and_then(|t| t.checked_add(Duration::from_nanos(nanos as u64))),
+    };
+    time.ok_or_else(|| anyhow!("filetime {} is out of range.", filetime))
+}
+
+pub fn from_system_time(time: SystemTime) -> Result<i64> {
+    let ticks = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_nanos() / 100).ok(),
+        Err(e) => i64::try_from(e.duration().as_nanos().div_ceil(100))
+            .ok()
+            .map(|t| -t),
+    };
+    ticks
+        .and_then(|t| t.checked_add(UNIX_EPOCH_AS_FILETIME))
+        .filter(|f| *f > 0)
+        .ok_or_else(|| anyhow!("{:?} is out of the filetime range.", time))
+}
+
+#[cfg(feature = "chrono")]
+pub fn to_chrono(filetime: i64) -> Result<chrono::DateTime<chrono::Utc>> {
+    let (seconds, nanos) = unix_parts(filetime)?;
+    chrono::DateTime::from_timestamp(seconds, nanos)
+        .ok_or_else(|| anyhow!("filetime {} is out of range.", filetime))
+}
+
+#[cfg(feature = "chrono")]
+pub fn from_chrono<Tz: chrono::TimeZone>(time: &chrono::DateTime<Tz>) -> Result<i64> {
+    from_system_time(time.with_timezone(&chrono::Utc).into())
+}
+
+#[cfg(feature = "time")]
+pub fn to_time(filetime: i64) -> Result<time::OffsetDateTime> {
+    let (seconds, nanos) = unix_parts(filetime)?;
+    time::OffsetDateTime::from_unix_timestamp_nanos(seconds as i128 * 1_000_000_000 + nanos as i128)
+        .map_err(|e| anyhow!("filetime {} is out of range: {}.", filetime, e))
+}
+
+#[cfg(feature = "time")]
+pub fn from_time(time: time::OffsetDateTime) -> Result<i64> {
+    i64::try_from(time.unix_timestamp_nanos().div_euclid(100))
+        .ok()
+        .and_then(|t| t.checked_add(UNIX_EPOCH_AS_FILETIME))
+        .filter(|f| *f > 0)
+        .ok_or_else(|| anyhow!("{} is out of the filetime range.", time))
+}
+
+// A fixed offset from UTC used when displaying timestamps, e.g. `+09:00`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UtcOffset(i32);
+
+impl UtcOffset {
+    pub const UTC: UtcOffset = UtcOffset(0);
+
+    pub fn from_seconds(seconds: i32) -> Result<Self> {
+        match seconds.unsigned_abs() < 86_400 {
+            true => Ok(Self(seconds)),
+            false => Err(anyhow!(
+                "utc offset of {} seconds is out of range.",
+                seconds
+            )),
+        }
+    }
+
+    pub fn seconds(&self) -> i32 {
+        self.0
+    }
+
+    // "2022-06-05T19:54:53.0000000+09:00", or with "Z" for UTC.
+    pub fn format(&self, filetime: i64) -> String {
+        to_iso8601_with_offset(filetime, self.0)
+    }
+}
+
+impl Display for UtcOffset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", offset_suffix(self.0))
+    }
+}
+
+impl FromStr for UtcOffset {
+    type Err = anyhow::Error;
+
+    // "Z", "UTC", "+09:00", "-0530" or "+9".
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid utc offset {}.", s);
+        if s == "Z" || s.eq_ignore_ascii_case("utc") {
+            return Ok(Self::UTC);
+        }
+        let (sign, rest) = match s.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = match rest.split_once(':') {
+            Some(parts) => parts,
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+        // digits only, as parse takes a sign of its own.
+        let number = |s: &str| match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            true => s.parse::<i32>().map_err(|_| invalid()),
+            false => Err(invalid()),
+        };
+        let (hours, minutes) = (number(hours)?, number(minutes)?);
+        if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+            return Err(invalid());
+        }
+        Self::from_seconds(sign * (hours * 3600 + minutes * 60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timestamp::{from_system_time, to_system_time, UtcOffset};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn it_should_convert_system_time() {
+        let time = to_system_time(132989000930000001).unwrap();
+
+        assert_eq!(
+            time.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::new(1654426493, 100)
+        );
+        assert_eq!(from_system_time(time).unwrap(), 132989000930000001);
+    }
+
+    #[test]
+    fn it_should_convert_before_unix_epoch() {
+        // 1969-12-31T23:59:59.9999999Z
+        let filetime = 116444735999999999;
+        let time = to_system_time(filetime).unwrap();
+
+        assert_eq!(
+            UNIX_EPOCH.duration_since(time).unwrap(),
+            Duration::from_nanos(100)
+        );
+        assert_eq!(from_system_time(time).unwrap(), filetime);
+    }
+
+    #[test]
+    fn it_should_reject_out_of_range() {
+        assert!(to_system_time(0).is_err());
+        assert!(to_system_time(-1).is_err());
+        assert!(from_system_time(UNIX_EPOCH - Duration::from_secs(400 * 366 * 86_400)).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn it_should_convert_chrono() {
+        let time = crate::timestamp::to_chrono(132989000930000001).unwrap();
+
+        assert_eq!(time.to_rfc3339(), "2022-06-05T10:54:53.000000100+00:00");
+        assert_eq!(
+            crate::timestamp::from_chrono(&time).unwrap(),
+            132989000930000001
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn it_should_convert_time() {
+        let time = crate::timestamp::to_time(132989000930000001).unwrap();
+
+        assert_eq!(time.unix_timestamp_nanos(), 1654426493000000100);
+        assert_eq!(
+            crate::timestamp::from_time(time).unwrap(),
+            132989000930000001
+        );
+        assert!(crate::timestamp::to_time(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn it_should_format_with_offset() {
+        let offset: UtcOffset = "+09:00".parse().unwrap();
+
+        assert_eq!(
+            offset.format(132989000930000001),
+            "2022-06-05T19:54:53.0000001+09:00"
+        );
+        assert_eq!(
+            "-0530"
+                .parse::<UtcOffset>()
+                .unwrap()
+                .format(132989000930000000),
+            "2022-06-05T05:24:53.0000000-05:30"
+        );
+        assert_eq!(
+            UtcOffset::UTC.format(132989000930000000),
+            "2022-06-05T10:54:53.0000000Z"
+        );
+        assert!("+24:00".parse::<UtcOffset>().is_err());
+        assert!("09:00".parse::<UtcOffset>().is_err());
+    }
+
+    #[test]
+    fn it_should_reject_out_of_range_offsets() {
+        for offset in [
+            "+999999",
+            "+2147483647",
+            "-99999:00",
+            "+-5",
+            "-+05:00",
+            "+05:-1",
+            "+",
+        ] {
+            assert!(offset.parse::<UtcOffset>().is_err(), "{}", offset);
+        }
+        assert_eq!("+23:59".parse::<UtcOffset>().unwrap().seconds(), 86_340);
+        assert!(UtcOffset::from_seconds(i32::MIN).is_err());
+    }
+}
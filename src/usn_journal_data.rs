@@ -1,11 +1,13 @@
-use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
-use crate::util::windows_version::{MatchVersion, WindowsVersion};
+use crate::raw::ioctl::ERROR_INVALID_PARAMETER;
+use crate::raw::ioctl::{USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V2};
+use crate::raw::usn_journal_wrapper::{error_code, UsnJournalWrapper};
+pub use crate::util::windows_version::{MatchVersion, WindowsVersion};
 use anyhow::Result;
-use windows::Win32::System::Ioctl::{
-    USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V2,
-};
+use std::cell::Cell;
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Data {
     pub usn_journal_id: u64,
     pub first_usn: i64,
@@ -21,6 +23,14 @@ pub struct Data {
     pub range_track_file_size_threshold: Option<i64>,
 }
 
+impl Data {
+    // the record major versions a read can ask for. volumes that only answer V0 write V2.
+    pub fn record_versions(&self) -> RangeInclusive<u16> {
+        self.min_supported_major_version.unwrap_or(2)
+            ..=self.max_supported_major_version.unwrap_or(2)
+    }
+}
+
 impl From<USN_JOURNAL_DATA_V0> for Data {
     fn from(u: USN_JOURNAL_DATA_V0) -> Self {
         Self {
@@ -78,8 +88,10 @@ impl From<USN_JOURNAL_DATA_V2> for Data {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataVer {
+    #[default]
     V0,
     V1,
     V2,
@@ -91,12 +103,20 @@ pub struct UsnJournalData<'a, U: UsnJournalWrapper> {
     pub data: Data,
 }
 
+impl<'a, U: UsnJournalWrapper> UsnJournalData<'a, U> {
+    pub fn usn_journal(&self) -> &'a U {
+        self.usn_journal
+    }
+}
+
 pub struct UsnJournalDataFactory<'a, U>
 where
     U: UsnJournalWrapper,
 {
     usn_journal: &'a U,
     version: &'a WindowsVersion,
+    // the structure the volume answered with, asked for first from then on.
+    ver: Cell<Option<DataVer>>,
 }
 
 impl<'a, U> MatchVersion<'a> for UsnJournalDataFactory<'a, U>
@@ -104,7 +124,7 @@ where
     U: UsnJournalWrapper,
 {
     fn by_win_version(&mut self, version: &'a WindowsVersion) {
-        self.version = &version;
+        self.version = version;
     }
 }
 
@@ -116,84 +136,141 @@ where
         Self {
             usn_journal,
             version: &WindowsVersion::Unknown,
+            ver: Cell::new(None),
         }
     }
 
+    // asks for the newest structure first and falls back to older ones, which is what
+    // older volumes and windows versions reject the newer ones with. a known windows version
+    // only skips the structures it doesn't have. later queries start at the one found.
     pub fn query(&self) -> Result<UsnJournalData<'a, U>> {
-        let result = match &self.version {
-            WindowsVersion::GreaterWin7 => UsnJournalData {
-                usn_journal: self.usn_journal,
-                ver: DataVer::V2,
-                data: unsafe { self.usn_journal.raw_query::<USN_JOURNAL_DATA_V2>()?.into() },
-            },
-            WindowsVersion::Win7 => UsnJournalData {
-                usn_journal: self.usn_journal,
-                ver: DataVer::V1,
-                data: unsafe { self.usn_journal.raw_query::<USN_JOURNAL_DATA_V1>()?.into() },
-            },
-            _ => UsnJournalData {
-                usn_journal: self.usn_journal,
-                ver: DataVer::V0,
-                data: unsafe { self.usn_journal.raw_query::<USN_JOURNAL_DATA_V0>()?.into() },
-            },
+        let mut ver = match (self.ver.get(), self.version) {
+            (Some(ver), _) => ver,
+            (None, WindowsVersion::Win7) => DataVer::V1,
+            (None, WindowsVersion::Other) => DataVer::V0,
+            _ => DataVer::V2,
         };
-
-        Ok(result)
+        loop {
+            let data = unsafe {
+                match ver {
+                    DataVer::V2 => self
+                        .usn_journal
+                        .raw_query::<USN_JOURNAL_DATA_V2>()
+                        .map(Data::from),
+                    DataVer::V1 => self
+                        .usn_journal
+                        .raw_query::<USN_JOURNAL_DATA_V1>()
+                        .map(Data::from),
+                    DataVer::V0 => self
+                        .usn_journal
+                        .raw_query::<USN_JOURNAL_DATA_V0>()
+                        .map(Data::from),
+                }
+            };
+            ver = match (data, ver) {
+                (Ok(data), ver) => {
+                    self.ver.set(Some(ver));
+                    return Ok(UsnJournalData {
+                        usn_journal: self.usn_journal,
+                        ver,
+                        data,
+                    });
+                }
+                (Err(e), DataVer::V0) => return Err(e),
+                (Err(e), _) if error_code(&e) != Some(ERROR_INVALID_PARAMETER) => return Err(e),
+                (Err(_), DataVer::V2) => DataVer::V1,
+                (Err(_), DataVer::V1) => DataVer::V0,
+            };
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::raw::usn_journal_wrapper::{RawRecords, UsnJournalWrapper};
-    use crate::raw::windows::RawUsnJournalData;
-    use crate::usn_journal_data::UsnJournalDataFactory;
+    use crate::raw::ioctl::ERROR_ACCESS_DENIED;
+    use crate::raw::usn_journal_wrapper::error_code;
+    use crate::testing::{Call, FakeJournal};
+    use crate::usn_journal_data::{DataVer, UsnJournalDataFactory};
     use crate::util::windows_version::{MatchVersion, WindowsVersion};
-    use anyhow::Result;
-    use windows::Win32::System::Ioctl::USN_JOURNAL_DATA_V2;
 
-    struct TestUsnJournal {}
-    trait Mock {
-        fn get_usn_journal_id(&self) -> u64;
+    #[test]
+    fn it_has_available_to_query() {
+        let journal = FakeJournal::new();
+        let factory = UsnJournalDataFactory::new(&journal);
+        let data = factory.query().unwrap();
+
+        assert_eq!(data.data.usn_journal_id, 1);
     }
-    impl Mock for USN_JOURNAL_DATA_V2 {
-        fn get_usn_journal_id(&self) -> u64 {
-            self.UsnJournalID
-        }
+
+    #[test]
+    fn it_has_available_to_specify_os_query() {
+        let journal = FakeJournal::new();
+        journal.set_usn_journal_id(7);
+        let mut factory = UsnJournalDataFactory::new(&journal);
+        let version = WindowsVersion::get();
+        factory.by_win_version(&version);
+        let data = factory.query().unwrap();
+
+        assert_eq!(data.data.usn_journal_id, 7);
     }
 
-    impl UsnJournalWrapper for TestUsnJournal {
-        unsafe fn raw_create(&self) {
-            unreachable!()
-        }
-        unsafe fn raw_query<D: RawUsnJournalData + Default>(&self) -> Result<D> {
-            Ok(Default::default())
-        }
-        unsafe fn raw_read<const N: usize>(&self, _: i64, _: u64) -> Result<RawRecords<N>> {
-            unreachable!()
-        }
-        unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>> {
-            unreachable!()
-        }
-        unsafe fn raw_delete(&self) {
-            unreachable!()
-        }
+    #[test]
+    fn it_should_fall_back_to_older_journal_data() {
+        let journal = FakeJournal::new();
+        let data = UsnJournalDataFactory::new(&journal).query().unwrap();
+
+        assert_eq!(data.ver, DataVer::V2);
+        assert_eq!(journal.calls(Call::Query), 1);
+
+        journal.set_data_ver(DataVer::V1);
+        let data = UsnJournalDataFactory::new(&journal).query().unwrap();
+        assert_eq!(data.ver, DataVer::V1);
+        assert_eq!(data.data.flags, None);
+        assert_eq!(data.data.record_versions(), 2..=2);
+        assert_eq!(journal.calls(Call::Query), 3);
+
+        journal.set_data_ver(DataVer::V0);
+        let data = UsnJournalDataFactory::new(&journal).query().unwrap();
+        assert_eq!(data.ver, DataVer::V0);
+        assert_eq!(data.data.max_supported_major_version, None);
+        assert_eq!(data.data.record_versions(), 2..=2);
+        assert_eq!(journal.calls(Call::Query), 6);
     }
 
     #[test]
-    fn it_has_available_to_query() {
-        let factory = UsnJournalDataFactory::new(&TestUsnJournal {});
+    fn it_should_remember_the_structure_found() {
+        let journal = FakeJournal::new();
+        journal.set_data_ver(DataVer::V0);
+        let factory = UsnJournalDataFactory::new(&journal);
+        factory.query().unwrap();
         let data = factory.query().unwrap();
 
-        assert_eq!(data.data.usn_journal_id, 0);
+        assert_eq!(data.ver, DataVer::V0);
+        assert_eq!(journal.calls(Call::Query), 4);
     }
 
     #[test]
-    fn it_has_available_to_specify_os_query() {
-        let mut factory = UsnJournalDataFactory::new(&TestUsnJournal {});
-        let version = WindowsVersion::get();
-        factory.by_win_version(&version);
+    fn it_should_not_fall_back_on_other_errors() {
+        let journal = FakeJournal::new();
+        journal.fail_next(Call::Query, ERROR_ACCESS_DENIED);
+        let err = UsnJournalDataFactory::new(&journal)
+            .query()
+            .err()
+            .unwrap()
+            .context("query journal.");
+
+        assert_eq!(error_code(&err), Some(ERROR_ACCESS_DENIED));
+        assert_eq!(journal.calls(Call::Query), 1);
+    }
+
+    #[test]
+    fn it_should_skip_structures_the_windows_version_lacks() {
+        let journal = FakeJournal::new();
+        let mut factory = UsnJournalDataFactory::new(&journal);
+        factory.by_win_version(&WindowsVersion::Win7);
         let data = factory.query().unwrap();
 
-        assert_eq!(data.data.usn_journal_id, 0);
+        assert_eq!(data.ver, DataVer::V1);
+        assert_eq!(journal.calls(Call::Query), 1);
     }
 }
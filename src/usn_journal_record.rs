@@ -2,8 +2,14 @@ use crate::raw::parser::Parser;
 use crate::raw::usn_journal_wrapper::{RawRecords, UsnJournalWrapper};
 use crate::usn_record::Record;
 use anyhow::{anyhow, Result};
+use std::mem::size_of;
 use std::os::raw::c_longlong;
-use windows::Win32::System::Ioctl::USN_RECORD_V2;
+
+// the usn or file reference the output starts with, none in an output too short for one.
+fn next_of<const N: usize>(raw_records: &RawRecords<N>) -> Option<i64> {
+    (raw_records.len as usize >= size_of::<c_longlong>())
+        .then(|| unsafe { (raw_records.raw_ptr.as_ptr() as *const c_longlong).read_unaligned() })
+}
 
 pub struct UsnRecordFactory<'a, U>
 where
@@ -11,7 +17,9 @@ where
 {
     usn_journal: &'a U,
     pub start_usn: i64,
+    pub start_file_reference: u64,
     pub usn_journal_id: Option<u64>,
+    pub max_major_version: u16,
 }
 
 impl<'a, U: UsnJournalWrapper> UsnRecordFactory<'a, U> {
@@ -19,7 +27,9 @@ impl<'a, U: UsnJournalWrapper> UsnRecordFactory<'a, U> {
         Self {
             usn_journal,
             start_usn: 0,
+            start_file_reference: 0,
             usn_journal_id: None,
+            max_major_version: 2,
         }
     }
 
@@ -33,34 +43,46 @@ impl<'a, U: UsnJournalWrapper> UsnRecordFactory<'a, U> {
         self
     }
 
-    pub fn read<const N: usize>(&self) -> Result<UsnJournalRecord<U, N>> {
+    pub fn set_start_file_reference(&mut self, reference: u64) -> &Self {
+        self.start_file_reference = reference;
+        self
+    }
+
+    // 4 adds the V4 records of modified ranges, once range tracking is on.
+    pub fn set_max_major_version(&mut self, version: u16) -> &Self {
+        self.max_major_version = version;
+        self
+    }
+
+    pub fn read<const N: usize>(&self) -> Result<UsnJournalRecord<'_, U, N>> {
         // TODO: should match windows version.
         let usn_journal_id = self
             .usn_journal_id
             .ok_or(anyhow!("usn journal id not found."))?;
 
-        let raw_records = unsafe { self.usn_journal.raw_read(self.start_usn, usn_journal_id)? };
-        // TODO: reading next block here or raw layer?
-        let next_usn = unsafe { (raw_records.raw_ptr.as_ptr() as *const c_longlong).read() };
+        let raw_records = unsafe {
+            self.usn_journal
+                .raw_read(self.start_usn, usn_journal_id, self.max_major_version)?
+        };
         Ok(UsnJournalRecord {
-            usn_journal: &self.usn_journal,
+            usn_journal: self.usn_journal,
+            next_usn: next_of(&raw_records),
             raw: raw_records,
-            next_usn: Some(next_usn),
+            max_major_version: self.max_major_version,
         })
     }
 
-    pub fn enums<const N: usize>(&self) -> Result<UsnJournalRecord<U, N>> {
-        let usn_journal_id = self
-            .usn_journal_id
+    pub fn enums<const N: usize>(&self) -> Result<UsnJournalRecord<'_, U, N>> {
+        self.usn_journal_id
             .ok_or(anyhow!("usn journal id not found."))?;
 
-        let raw_records = unsafe { self.usn_journal.raw_enum()? };
-        // TODO: reading next block here or raw layer?
-        let next_usn = unsafe { (raw_records.raw_ptr.as_ptr() as *const c_longlong).read() };
+        let raw_records = unsafe { self.usn_journal.raw_enum(self.start_file_reference)? };
+        // the file reference to continue from, rather than a usn.
         Ok(UsnJournalRecord {
-            usn_journal: &self.usn_journal,
+            usn_journal: self.usn_journal,
+            next_usn: next_of(&raw_records),
             raw: raw_records,
-            next_usn: Some(next_usn),
+            max_major_version: self.max_major_version,
         })
     }
 }
@@ -69,116 +91,55 @@ pub struct UsnJournalRecord<'a, U: UsnJournalWrapper, const N: usize> {
     pub usn_journal: &'a U,
     pub raw: RawRecords<N>,
     pub next_usn: Option<i64>,
+    pub max_major_version: u16,
 }
 
 impl<'a, U: UsnJournalWrapper, const N: usize> UsnJournalRecord<'a, U, N> {
     pub fn parse(self) -> Box<Vec<Record>> {
         // TODO: how represent in here each windows version.
-        let raw_records = self.raw.parse::<USN_RECORD_V2>();
-        Box::new(
-            raw_records
-                .iter()
-                .map(|r| Record {
-                    usn: r.Usn,
-                    timestamp: r.TimeStamp,
-                })
-                .collect(),
-        )
+        // a `$J` file has the range records whether asked for or not.
+        let records: Vec<Record> = self
+            .raw
+            .split()
+            .iter()
+            .map(|r| Record::from(*r))
+            .filter(|r| r.major_version < 4 || self.max_major_version >= 4)
+            .collect();
+
+        Box::new(records)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::raw::usn_journal_wrapper::UsnJournalWrapper;
-    use crate::usn_journal_record::{RawRecords, UsnRecordFactory};
-    use anyhow::Result;
-
-    struct TestUsnJournal {}
-
-    impl UsnJournalWrapper for TestUsnJournal {
-        unsafe fn raw_create(&self) {
-            unreachable!()
-        }
-
-        unsafe fn raw_query<D: Default>(&self) -> Result<D> {
-            unreachable!()
-        }
-
-        unsafe fn raw_read<const N: usize>(&self, _: i64, _: u64) -> Result<RawRecords<N>> {
-            let p = [
-                144u8, 0, 128, 144, 0, 0, 0, 0, 144, 0, 0, 0, 2, 0, 0, 0, 76, 119, 0, 0, 0, 0, 4,
-                0, 195, 162, 3, 0, 0, 0, 2, 0, 0, 0, 128, 144, 0, 0, 0, 0, 10, 27, 185, 192, 46,
-                86, 216, 1, 3, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 80, 0, 60, 0, 51, 0,
-                51, 0, 48, 0, 66, 0, 67, 0, 50, 0, 51, 0, 53, 0, 68, 0, 66, 0, 55, 0, 65, 0, 55, 0,
-                56, 0, 56, 0, 50, 0, 52, 0, 52, 0, 67, 0, 57, 0, 68, 0, 67, 0, 66, 0, 65, 0, 52, 0,
-                68, 0, 50, 0, 56, 0, 68, 0, 65, 0, 51, 0, 57, 0, 70, 0, 53, 0, 56, 0, 66, 0, 52, 0,
-                48, 0, 56, 0, 53, 0,
-            ];
-            let mut pp = [0u8; N];
-            pp.copy_from_slice(&p);
-            Ok(RawRecords {
-                raw_ptr: Box::new(pp),
-                len: 152,
-            })
-        }
-
-        unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>> {
-            unreachable!()
-        }
-
-        unsafe fn raw_delete(&self) {
-            unreachable!()
-        }
-    }
-
-    struct TestUsnJournal2 {}
-
-    impl UsnJournalWrapper for TestUsnJournal2 {
-        unsafe fn raw_create(&self) {
-            unreachable!()
-        }
-
-        unsafe fn raw_query<D: Default>(&self) -> Result<D> {
-            unreachable!()
-        }
-
-        unsafe fn raw_read<const N: usize>(&self, _: i64, _: u64) -> Result<RawRecords<N>> {
-            let p = [
-                0, 1, 128, 145, 0, 0, 0, 0, 128, 0, 0, 0, 2, 0, 0, 0, 91, 122, 0, 0, 0, 0, 10, 0,
-                2, 23, 0, 0, 0, 0, 17, 0, 0, 0, 128, 145, 0, 0, 0, 0, 9, 60, 69, 37, 174, 87, 216,
-                1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 66, 0, 60, 0, 109, 0, 111, 0,
-                122, 0, 112, 0, 108, 0, 117, 0, 103, 0, 105, 0, 110, 0, 45, 0, 98, 0, 108, 0, 111,
-                0, 99, 0, 107, 0, 45, 0, 100, 0, 105, 0, 103, 0, 101, 0, 115, 0, 116, 0, 50, 0, 53,
-                0, 54, 0, 46, 0, 115, 0, 98, 0, 115, 0, 116, 0, 111, 0, 114, 0, 101, 0, 0, 0, 128,
-                0, 0, 0, 2, 0, 0, 0, 91, 122, 0, 0, 0, 0, 10, 0, 2, 23, 0, 0, 0, 0, 17, 0, 128, 0,
-                128, 145, 0, 0, 0, 0, 9, 60, 69, 37, 174, 87, 216, 1, 3, 1, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 32, 0, 0, 0, 66, 0, 60, 0, 109, 0, 111, 0, 122, 0, 112, 0, 108, 0, 117, 0,
-                103, 0, 105, 0, 110, 0, 45, 0, 98, 0, 108, 0, 111, 0, 99, 0, 107, 0, 45, 0, 100, 0,
-                105, 0, 103, 0, 101, 0, 115, 0, 116, 0, 50, 0, 53, 0, 54, 0, 46, 0, 115, 0, 98, 0,
-                115, 0, 116, 0, 111, 0, 114, 0, 101, 0,
-            ];
-            let mut pp = [0u8; N];
-            pp.copy_from_slice(&p);
-            Ok(RawRecords {
-                raw_ptr: Box::new(pp),
-                len: 264,
-            })
-        }
-
-        unsafe fn raw_enum<const N: usize>(&self) -> Result<RawRecords<N>> {
-            unreachable!()
-        }
-
-        unsafe fn raw_delete(&self) {
-            unreachable!()
-        }
+    use crate::testing::{record, FakeJournal};
+    use crate::usn_journal_record::UsnRecordFactory;
+    use crate::usn_record::Record;
+
+    fn journal(first_usn: i64, records: &[Record]) -> FakeJournal {
+        let journal = FakeJournal::new();
+        journal.set_usn_bounds(first_usn, first_usn);
+        journal.extend(records).unwrap();
+        journal
     }
 
     #[test]
     fn it_should_has_one_record() {
-        let mut factory = UsnRecordFactory::new(&TestUsnJournal {});
-        factory.set_usn_journal_id(0);
-        let raw_usn_records = factory.read::<148>().unwrap();
+        let journal = journal(
+            2424307712,
+            &[Record {
+                timestamp: 132950947809270538,
+                ..record(
+                    0x0004_0000_0000_774c,
+                    0x0002_0000_0003_a2c3,
+                    0x80000003,
+                    "330BC235DB7A788244C9DCBA4D28DA39F58B4085",
+                )
+            }],
+        );
+        let mut factory = UsnRecordFactory::new(&journal);
+        factory.set_usn_journal_id(1);
+        let raw_usn_records = factory.read::<152>().unwrap();
         let usn_records = raw_usn_records.parse();
         let first = usn_records.first().unwrap();
 
@@ -188,9 +149,23 @@ mod tests {
 
     #[test]
     fn it_should_has_two_records() {
-        let mut factory = UsnRecordFactory::new(&TestUsnJournal2 {});
-        factory.set_usn_journal_id(0);
-        let raw_usn_records = factory.read::<262>().unwrap();
+        let name = "mozplugin-block-digest256.sbstore";
+        let journal = journal(
+            2441084928,
+            &[
+                Record {
+                    timestamp: 132952594468649993,
+                    ..record(0x000a_0000_0000_7a5b, 0x0011_0000_0000_1702, 0x102, name)
+                },
+                Record {
+                    timestamp: 132952594468649993,
+                    ..record(0x000a_0000_0000_7a5b, 0x0011_0000_0000_1702, 0x103, name)
+                },
+            ],
+        );
+        let mut factory = UsnRecordFactory::new(&journal);
+        factory.set_usn_journal_id(1);
+        let raw_usn_records = factory.read::<264>().unwrap();
         let usn_records = raw_usn_records.parse();
         let first = usn_records.first().unwrap();
         let second = usn_records.get(1).unwrap();
@@ -1,15 +1,213 @@
+use crate::file_reference::FileReference;
+use crate::flags::{FileAttributes, Reason};
+use crate::raw::ioctl::{
+    FILE_ID_128, USN_RECORD_EXTENT, USN_RECORD_V2, USN_RECORD_V3, USN_RECORD_V4,
+};
 use crate::reader::RecordFetcher;
+use crate::timestamp::{to_system_time, UtcOffset};
 use crate::usn_journal_record_iter::UsnJournalIter;
+use crate::util::filetime::to_unix_seconds;
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+use std::time::SystemTime;
 
-#[derive(Clone, Debug, Default)]
+// Bytes of a file changed, from a V4 record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extent {
+    pub offset: i64,
+    pub length: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Record {
     pub usn: i64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::filetime::rfc3339"))]
     pub timestamp: i64,
+    pub major_version: u16,
+    pub file_reference_number: FileReference,
+    pub parent_file_reference_number: FileReference,
+    pub reason: Reason,
+    pub source_info: u32,
+    pub security_id: u32,
+    pub file_attributes: FileAttributes,
+    pub file_name: String,
+    // filled in when a path resolver is available.
+    pub path: Option<String>,
+    // which volume or source the record came from, set when reading several at once.
+    pub volume: Option<String>,
+    // found in page slack rather than in the live journal.
+    pub recovered: bool,
+    // the ranges a V4 record reports, which has no name, timestamp or attributes.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extents: Vec<Extent>,
+    // the bytes a V4 record takes up in the journal, V2 and V3 records are sized by their name.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub record_length: Option<u32>,
+}
+
+// copies the fixed part of a record, zero filled when the bytes are short.
+fn header<T: Copy>(bytes: &[u8]) -> T {
+    let mut header = vec![0u8; size_of::<T>()];
+    let available = bytes.len().min(header.len());
+    header[..available].copy_from_slice(&bytes[..available]);
+    unsafe { header.as_ptr().cast::<T>().read_unaligned() }
+}
+
+fn file_name(bytes: &[u8], offset: u16, len: u16) -> String {
+    let name_start = (offset as usize).min(bytes.len());
+    let name_end = (name_start + len as usize).min(bytes.len());
+    let name: Vec<u16> = bytes[name_start..name_end]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&name)
+}
+
+// NTFS file ids fit in the low half of a FILE_ID_128.
+fn file_id(id: FILE_ID_128) -> FileReference {
+    u64::from_le_bytes(id.Identifier[..8].try_into().unwrap()).into()
+}
+
+// the extents follow the fixed part, `ExtentSize` apart.
+fn extents(bytes: &[u8], count: u16, size: u16) -> Vec<Extent> {
+    let start = size_of::<USN_RECORD_V4>() - size_of::<USN_RECORD_EXTENT>();
+    let size = (size as usize).max(size_of::<USN_RECORD_EXTENT>());
+    (0..count as usize)
+        .map(|i| start + i * size)
+        .take_while(|at| at + size_of::<USN_RECORD_EXTENT>() <= bytes.len())
+        .map(|at| {
+            let e = header::<USN_RECORD_EXTENT>(&bytes[at..]);
+            Extent {
+                offset: e.Offset,
+                length: e.Length,
+            }
+        })
+        .collect()
+}
+
+impl From<&[u8]> for Record {
+    fn from(bytes: &[u8]) -> Self {
+        let major_version = header::<[u16; 3]>(bytes)[2];
+        if major_version == 4 {
+            let r = header::<USN_RECORD_V4>(bytes);
+            return Self {
+                usn: r.Usn,
+                major_version: r.Header.MajorVersion,
+                file_reference_number: file_id(r.FileReferenceNumber),
+                parent_file_reference_number: file_id(r.ParentFileReferenceNumber),
+                reason: Reason(r.Reason),
+                source_info: r.SourceInfo,
+                extents: extents(bytes, r.NumberOfExtents, r.ExtentSize),
+                record_length: Some(r.Header.RecordLength),
+                ..Default::default()
+            };
+        }
+        if major_version == 3 {
+            let r = header::<USN_RECORD_V3>(bytes);
+            return Self {
+                usn: r.Usn,
+                timestamp: r.TimeStamp,
+                major_version: r.MajorVersion,
+                file_reference_number: file_id(r.FileReferenceNumber),
+                parent_file_reference_number: file_id(r.ParentFileReferenceNumber),
+                reason: Reason(r.Reason),
+                source_info: r.SourceInfo,
+                security_id: r.SecurityId,
+                file_attributes: FileAttributes(r.FileAttributes),
+                file_name: file_name(bytes, r.FileNameOffset, r.FileNameLength),
+                path: None,
+                volume: None,
+                recovered: false,
+                extents: vec![],
+                record_length: None,
+            };
+        }
+
+        let r = header::<USN_RECORD_V2>(bytes);
+        Self {
+            usn: r.Usn,
+            timestamp: r.TimeStamp,
+            major_version: r.MajorVersion,
+            file_reference_number: r.FileReferenceNumber.into(),
+            parent_file_reference_number: r.ParentFileReferenceNumber.into(),
+            reason: Reason(r.Reason),
+            source_info: r.SourceInfo,
+            security_id: r.SecurityId,
+            file_attributes: FileAttributes(r.FileAttributes),
+            file_name: file_name(bytes, r.FileNameOffset, r.FileNameLength),
+            path: None,
+            volume: None,
+            recovered: false,
+            extents: vec![],
+            record_length: None,
+        }
+    }
 }
 
 impl Record {
+    // whole seconds, rounded down so pre-1970 times stay ordered.
     pub fn get_unix_timestamp(&self) -> i64 {
-        (self.timestamp / 10_000_000) - 11644473600
+        to_unix_seconds(self.timestamp)
+    }
+
+    pub fn system_time(&self) -> Result<SystemTime> {
+        to_system_time(self.timestamp)
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> Result<chrono::DateTime<chrono::Utc>> {
+        crate::timestamp::to_chrono(self.timestamp)
+    }
+
+    #[cfg(feature = "time")]
+    pub fn offset_date_time(&self) -> Result<time::OffsetDateTime> {
+        crate::timestamp::to_time(self.timestamp)
+    }
+
+    // like `Display`, with the timestamp shown at `offset`.
+    pub fn display_with_offset(&self, offset: UtcOffset) -> RecordDisplay<'_> {
+        RecordDisplay {
+            record: self,
+            offset,
+        }
+    }
+}
+
+pub struct RecordDisplay<'a> {
+    record: &'a Record,
+    offset: UtcOffset,
+}
+
+impl Display for RecordDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let r = self.record;
+        write!(
+            f,
+            "{:>12} {} {:>18} {:>18} [{}] [{}] {}{}",
+            r.usn,
+            self.offset.format(r.timestamp),
+            r.file_reference_number.to_string(),
+            r.parent_file_reference_number.to_string(),
+            r.reason,
+            r.file_attributes,
+            r.path.as_deref().unwrap_or(&r.file_name),
+            if r.recovered { " (recovered)" } else { "" }
+        )
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display_with_offset(UtcOffset::UTC).fmt(f)
     }
 }
 
@@ -85,4 +283,69 @@ mod tests {
         let unix_timestapm = record.get_unix_timestamp();
         assert_eq!(unix_timestapm, 1654426493);
     }
+
+    #[test]
+    fn it_should_convert_timestamp_before_unix_epoch() {
+        let record = Record {
+            timestamp: 116444735995000000,
+            ..Default::default()
+        };
+
+        assert_eq!(record.get_unix_timestamp(), -1);
+        assert!(Record::default().system_time().is_err());
+    }
+
+    #[test]
+    fn it_should_display_record() {
+        let record = Record {
+            usn: 42,
+            timestamp: 132989000930000000,
+            file_reference_number: 0x10.into(),
+            parent_file_reference_number: 0x5.into(),
+            reason: crate::flags::Reason(0x80000100),
+            file_name: "a.txt".to_string(),
+            recovered: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "          42 2022-06-05T10:54:53.0000000Z               0x10                0x5 \
+             [FILE_CREATE|CLOSE] [] a.txt (recovered)"
+        );
+    }
+
+    #[test]
+    fn it_should_display_record_with_offset() {
+        let record = Record {
+            usn: 42,
+            timestamp: 132989000930000000,
+            file_name: "a.txt".to_string(),
+            ..Default::default()
+        };
+        let offset = "+09:00".parse().unwrap();
+
+        assert!(record
+            .display_with_offset(offset)
+            .to_string()
+            .contains(" 2022-06-05T19:54:53.0000000+09:00 "));
+    }
+
+    #[test]
+    fn it_should_display_extreme_timestamps() {
+        let record = Record {
+            timestamp: i64::MIN,
+            ..Default::default()
+        };
+        let offset = "-12:00".parse().unwrap();
+
+        assert!(record
+            .to_string()
+            .contains(" -27627-04-19T21:11:54.5224192Z "));
+        assert!(record
+            .display_with_offset(offset)
+            .to_string()
+            .contains(" -27627-04-19T09:11:54.5224192-12:00 "));
+        assert_eq!(record.get_unix_timestamp(), -933_981_677_286);
+    }
 }
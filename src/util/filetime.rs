@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+
+// 100ns intervals between 1601-01-01 and 1970-01-01.
+pub const UNIX_EPOCH_AS_FILETIME: i64 = 116_444_736_000_000_000;
+pub const TICKS_PER_SECOND: i64 = 10_000_000;
+// the epoch falls on a whole second, so whole seconds can be shifted without overflowing.
+const UNIX_EPOCH_AS_SECONDS: i64 = UNIX_EPOCH_AS_FILETIME / TICKS_PER_SECOND;
+
+pub fn to_unix_seconds(filetime: i64) -> i64 {
+    filetime.div_euclid(TICKS_PER_SECOND) - UNIX_EPOCH_AS_SECONDS
+}
+
+pub fn to_iso8601(filetime: i64) -> String {
+    to_iso8601_with_offset(filetime, 0)
+}
+
+// local time at `offset_seconds` east of UTC, suffixed with the offset or "Z".
+pub fn to_iso8601_with_offset(filetime: i64, offset_seconds: i32) -> String {
+    let seconds = to_unix_seconds(filetime) + offset_seconds as i64;
+    let fraction = filetime.rem_euclid(TICKS_PER_SECOND);
+    let days = seconds.div_euclid(86_400);
+    let time = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:07}{}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        fraction,
+        offset_suffix(offset_seconds)
+    )
+}
+
+// "Z" for UTC, otherwise "+hh:mm" or "-hh:mm".
+pub fn offset_suffix(offset_seconds: i32) -> String {
+    let minutes = offset_seconds.abs() / 60;
+    match offset_seconds {
+        0 => "Z".to_string(),
+        o if o < 0 => format!("-{:02}:{:02}", minutes / 60, minutes % 60),
+        _ => format!("+{:02}:{:02}", minutes / 60, minutes % 60),
+    }
+}
+
+pub fn from_iso8601(text: &str) -> Result<i64> {
+    let invalid = || anyhow!("invalid timestamp {}.", text);
+    let text = text.strip_suffix('Z').ok_or_else(invalid)?;
+    let (date, time) = text.split_once('T').ok_or_else(invalid)?;
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    // years before 0000 are written with a leading sign, the fields themselves have none.
+    let (sign, date) = match date.strip_prefix('-') {
+        Some(date) => (-1, date),
+        None => (1, date),
+    };
+
+    let number = |s: &str| match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse::<i64>().map_err(|_| invalid()),
+        false => Err(invalid()),
+    };
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || time.len() != 3 || fraction.len() > 7 {
+        return Err(invalid());
+    }
+
+    let (year, month, day) = (sign * number(date[0])?, number(date[1])?, number(date[2])?);
+    let (hour, minute, second) = (number(time[0])?, number(time[1])?, number(time[2])?);
+    // a million years is far past the filetime range, and keeps the day count from overflowing.
+    if !(-1_000_000..=1_000_000).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..60).contains(&second)
+    {
+        return Err(invalid());
+    }
+
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second;
+    let ticks = match fraction.is_empty() {
+        true => 0,
+        false => number(&format!("{:0<7}", fraction))?,
+    };
+
+    // the whole seconds of i64::MIN are past the range, only its fraction brings it back.
+    let filetime =
+        (seconds + UNIX_EPOCH_AS_SECONDS) as i128 * TICKS_PER_SECOND as i128 + ticks as i128;
+    i64::try_from(filetime).map_err(|_| anyhow!("timestamp {} is out of range.", text))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's days -> (y, m, d) for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(feature = "serde")]
+pub mod rfc3339 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(filetime: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_iso8601(*filetime))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::from_iso8601(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::filetime::{
+        from_iso8601, to_iso8601, to_iso8601_with_offset, to_unix_seconds,
+    };
+
+    #[test]
+    fn it_should_format_iso8601() {
+        assert_eq!(
+            to_iso8601(132989000930000000),
+            "2022-06-05T10:54:53.0000000Z"
+        );
+        assert_eq!(
+            to_iso8601(132989000930000001),
+            "2022-06-05T10:54:53.0000001Z"
+        );
+        assert_eq!(to_iso8601(0), "1601-01-01T00:00:00.0000000Z");
+    }
+
+    #[test]
+    fn it_should_format_extreme_timestamps() {
+        assert_eq!(to_iso8601(i64::MAX), "30828-09-14T02:48:05.4775807Z");
+        assert_eq!(to_iso8601(i64::MIN), "-27627-04-19T21:11:54.5224192Z");
+        assert_eq!(
+            to_iso8601_with_offset(i64::MAX, 14 * 3600),
+            "30828-09-14T16:48:05.4775807+14:00"
+        );
+        assert_eq!(
+            to_iso8601_with_offset(i64::MIN, -12 * 3600),
+            "-27627-04-19T09:11:54.5224192-12:00"
+        );
+        assert_eq!(to_unix_seconds(i64::MIN), -933_981_677_286);
+        assert_eq!(to_unix_seconds(-1), -11_644_473_601);
+    }
+
+    #[test]
+    fn it_should_parse_iso8601() {
+        assert_eq!(
+            from_iso8601("2022-06-05T10:54:53.0000001Z").unwrap(),
+            132989000930000001
+        );
+        assert_eq!(
+            from_iso8601("2022-06-05T10:54:53Z").unwrap(),
+            132989000930000000
+        );
+        assert_eq!(from_iso8601("1601-01-01T00:00:00.0000000Z").unwrap(), 0);
+        assert!(from_iso8601("2022-06-05 10:54:53").is_err());
+    }
+
+    #[test]
+    fn it_should_reject_out_of_range_timestamps() {
+        for text in [
+            "99999999999999-01-01T00:00:00Z",
+            "40000-01-01T00:00:00Z",
+            "2024-13-45T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "2024-04-31T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01T00:60:00Z",
+            "2024-01-01T00:00:60Z",
+            "2024-01-01T00:00:00.-1Z",
+            "2024-+1-01T00:00:00Z",
+            "--2024-01-01T00:00:00Z",
+            "-99999999999999-01-01T00:00:00Z",
+            "-27627-04-19T21:11:54.5224191Z",
+        ] {
+            assert!(from_iso8601(text).is_err(), "{}", text);
+        }
+        assert!(from_iso8601("2024-02-29T23:59:59.9999999Z").is_ok());
+        assert!(from_iso8601("30000-01-01T00:00:00Z").is_ok());
+    }
+
+    #[test]
+    fn it_should_parse_extreme_timestamps() {
+        for filetime in [i64::MIN, -1, i64::MAX] {
+            assert_eq!(from_iso8601(&to_iso8601(filetime)).unwrap(), filetime);
+        }
+        assert_eq!(
+            from_iso8601("-0001-12-31T00:00:00Z").unwrap(),
+            -50_522_832_000 * 10_000_000
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_should_fail_to_deserialize_out_of_range_timestamps() {
+        let mut value = serde_json::to_value(crate::usn_record::Record::default()).unwrap();
+        value["timestamp"] = "99999999999999-01-01T00:00:00Z".into();
+        let error = serde_json::from_value::<crate::usn_record::Record>(value).unwrap_err();
+
+        assert!(error.to_string().contains("99999999999999"));
+    }
+}
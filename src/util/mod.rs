@@ -1 +1,2 @@
+pub mod filetime;
 pub mod windows_version;
@@ -1,10 +1,20 @@
-use windows::Win32::System::SystemInformation::{GetVersionExW, OSVERSIONINFOW};
+#[cfg(windows)]
+use windows::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+// GetVersionExW reports 6.2 to processes without a manifest, ntdll tells the truth.
+#[cfg(windows)]
+#[link(name = "ntdll")]
+extern "system" {
+    fn RtlGetVersion(version: *mut OSVERSIONINFOW) -> i32;
+}
 
 pub trait MatchVersion<'a> {
-    fn by_win_version(&mut self, version: &'a WindowsVersion) {}
+    fn by_win_version(&mut self, _version: &'a WindowsVersion) {}
 }
 
+// Only a hint for `UsnJournalDataFactory`, which probes what the volume supports anyway.
 pub enum WindowsVersion {
+    // vista and older.
     Other,
     Win7,
     GreaterWin7,
@@ -12,21 +22,29 @@ pub enum WindowsVersion {
 }
 
 impl WindowsVersion {
+    #[cfg(not(windows))]
+    pub fn get() -> WindowsVersion {
+        WindowsVersion::Unknown
+    }
+
+    #[cfg(windows)]
     pub fn get() -> WindowsVersion {
         let version = unsafe {
             let mut osvi = OSVERSIONINFOW {
                 dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
                 ..Default::default()
             };
-            GetVersionExW(&mut osvi);
+            if RtlGetVersion(&mut osvi) != 0 {
+                return WindowsVersion::Unknown;
+            }
             (osvi.dwMajorVersion, osvi.dwMinorVersion)
         };
 
+        // windows 11 is still 10.0.
         match version {
             (6, 1) => WindowsVersion::Win7,
-            (6, _) => WindowsVersion::GreaterWin7,
-            (10, _) => WindowsVersion::GreaterWin7,
-            (11, _) => WindowsVersion::GreaterWin7,
+            (6, 0) => WindowsVersion::Other,
+            (major, _) if major >= 6 => WindowsVersion::GreaterWin7,
             _ => WindowsVersion::Other,
         }
     }
@@ -39,6 +57,6 @@ mod tests {
     #[ignore]
     #[test]
     fn it_should_get_os_version() {
-        let version = WindowsVersion::get();
+        let _version = WindowsVersion::get();
     }
 }
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// A volume as the user names it. shown with a trailing backslash, the way the volume
+// management functions take it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VolumePath {
+    // `C`, `C:`, `C:\` or `\\.\C:`.
+    Letter(char),
+    // a folder another volume is mounted on, e.g. `C:\mnt\vhd\`.
+    MountPoint(String),
+    // `\\?\Volume{GUID}\`, the only name unlettered volumes have. lower case, without braces.
+    Guid(String),
+}
+
+fn is_guid(guid: &str) -> bool {
+    let groups: Vec<&str> = guid.split('-').collect();
+    groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|g| g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl VolumePath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid volume path {}.", path);
+        let normalized = path.trim().replace('/', "\\");
+        let rest = normalized
+            .strip_prefix(r"\\?\")
+            .or_else(|| normalized.strip_prefix(r"\\.\"))
+            .unwrap_or(&normalized);
+
+        if rest
+            .get(..7)
+            .is_some_and(|p| p.eq_ignore_ascii_case("volume{"))
+        {
+            let (guid, tail) = rest[7..].split_once('}').ok_or_else(invalid)?;
+            if !is_guid(guid) || !matches!(tail, "" | "\\") {
+                return Err(invalid());
+            }
+            return Ok(VolumePath::Guid(guid.to_lowercase()));
+        }
+
+        let letter = match rest.as_bytes() {
+            [letter] | [letter, b':', ..] if letter.is_ascii_alphabetic() => {
+                letter.to_ascii_uppercase() as char
+            }
+            _ => return Err(invalid()),
+        };
+        let folders: Vec<&str> = rest
+            .get(2..)
+            .unwrap_or_default()
+            .split('\\')
+            .filter(|f| !f.is_empty())
+            .collect();
+        if rest.len() > 2 && !rest[2..].starts_with('\\') {
+            // `C:dir` is relative to the current directory of C.
+            return Err(invalid());
+        }
+        if folders.iter().any(|f| matches!(*f, "." | "..")) {
+            return Err(invalid());
+        }
+        Ok(match folders.is_empty() {
+            true => VolumePath::Letter(letter),
+            false => VolumePath::MountPoint(format!("{}:\\{}\\", letter, folders.join("\\"))),
+        })
+    }
+
+    // what CreateFileW opens the volume itself with. a mount point has to be resolved to its
+    // volume GUID path first.
+    pub fn device_path(&self) -> Option<String> {
+        match self {
+            VolumePath::Letter(letter) => Some(format!(r"\\.\{}:", letter)),
+            VolumePath::MountPoint(_) => None,
+            VolumePath::Guid(guid) => Some(format!(r"\\?\Volume{{{}}}", guid)),
+        }
+    }
+
+    pub fn root_path(&self) -> String {
+        match self {
+            VolumePath::Letter(letter) => format!("{}:\\", letter),
+            VolumePath::MountPoint(path) => path.clone(),
+            VolumePath::Guid(guid) => format!(r"\\?\Volume{{{}}}\", guid),
+        }
+    }
+}
+
+impl FromStr for VolumePath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl Display for VolumePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.root_path())
+    }
+}
+
+// What tells a volume apart whatever it is mounted as, to match saved journal checkpoints
+// with the right volume after a reboot.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VolumeInformation {
+    // `\\?\Volume{GUID}\`, kept while the partition exists.
+    pub guid_path: String,
+    // new on every format.
+    pub serial_number: u32,
+    // e.g. `NTFS` or `ReFS`.
+    pub file_system: String,
+    pub label: String,
+}
+
+impl VolumeInformation {
+    pub fn same_volume(&self, other: &VolumeInformation) -> bool {
+        self.guid_path.eq_ignore_ascii_case(&other.guid_path)
+            && self.serial_number == other.serial_number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::volume::{VolumeInformation, VolumePath};
+
+    const GUID: &str = "6f1d0a1c-3b2a-11ee-9e4b-806e6f6e6963";
+
+    #[test]
+    fn it_should_parse_drive_letters() {
+        for path in ["c", "C:", r"c:\", r"\\.\C:", r"\\?\C:\", "C:/"] {
+            assert_eq!(VolumePath::parse(path).unwrap(), VolumePath::Letter('C'));
+        }
+        assert_eq!(VolumePath::Letter('D').device_path().unwrap(), r"\\.\D:");
+        assert_eq!(VolumePath::Letter('D').to_string(), r"D:\");
+    }
+
+    #[test]
+    fn it_should_parse_mount_points() {
+        let path = VolumePath::MountPoint(r"C:\mnt\vhd\".to_string());
+        for input in [
+            r"c:\mnt\vhd",
+            r"C:\mnt\\vhd\",
+            "C:/mnt/vhd/",
+            r"\\?\C:\mnt\vhd",
+        ] {
+            assert_eq!(VolumePath::parse(input).unwrap(), path);
+        }
+        assert_eq!(path.device_path(), None);
+        assert_eq!(path.root_path(), r"C:\mnt\vhd\");
+    }
+
+    #[test]
+    fn it_should_parse_guid_paths() {
+        let upper = format!(r"\\?\Volume{{{}}}\", GUID.to_uppercase());
+        let path = VolumePath::parse(&upper).unwrap();
+
+        assert_eq!(path, VolumePath::Guid(GUID.to_string()));
+        assert_eq!(
+            VolumePath::parse(&format!(r"\\.\volume{{{}}}", GUID)).unwrap(),
+            path
+        );
+        assert_eq!(
+            path.device_path().unwrap(),
+            format!(r"\\?\Volume{{{}}}", GUID)
+        );
+        assert_eq!(path.to_string(), format!(r"\\?\Volume{{{}}}\", GUID));
+    }
+
+    #[test]
+    fn it_should_reject_other_paths() {
+        for path in [
+            "",
+            "CD",
+            "Čšř",
+            "C:dir",
+            r"C:\mnt\..\vhd",
+            r"\\server\share",
+            r"relative\path",
+            r"\\?\Volume{not-a-guid}\",
+            r"\\?\Volume{6f1d0a1c-3b2a-11ee-9e4b-806e6f6e6963}\dir",
+        ] {
+            assert!(VolumePath::parse(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn it_should_match_the_same_volume() {
+        let volume = VolumeInformation {
+            guid_path: format!(r"\\?\Volume{{{}}}\", GUID),
+            serial_number: 0x1234_abcd,
+            file_system: "NTFS".to_string(),
+            label: "data".to_string(),
+        };
+        let renamed = VolumeInformation {
+            label: "backup".to_string(),
+            ..volume.clone()
+        };
+        let formatted = VolumeInformation {
+            serial_number: 0x5678_0000,
+            ..volume.clone()
+        };
+
+        assert!(volume.same_volume(&renamed));
+        assert!(!volume.same_volume(&formatted));
+    }
+}